@@ -0,0 +1,191 @@
+//! A small arithmetic evaluator for answers typed into the query, such as
+//! `= (17*34)+5` or `2^10`.
+
+/// The result of `query` when it is an arithmetic expression, formatted for
+/// display. A query starting with `=` is always treated as one; otherwise it
+/// needs an operator, so plain names and numbers are left to the search.
+pub fn answer(query: &str) -> Option<String> {
+    let query = query.trim();
+    let expr = match query.strip_prefix('=') {
+        Some(expr) => expr,
+        None if query.contains(['+', '-', '*', '/', '%', '^']) => query,
+        None => return None,
+    };
+    evaluate(expr).map(format_number)
+}
+
+/// Evaluates `+ - * / % ^`, parentheses and decimals with the usual
+/// precedence; `^` binds tightest and to the right. Returns `None` for
+/// anything malformed or without a finite result.
+pub fn evaluate(expr: &str) -> Option<f64> {
+    let mut parser = Parser {
+        chars: expr.chars().filter(|c| !c.is_whitespace()).collect(),
+        pos: 0,
+    };
+    let value = parser.sum()?;
+    (parser.pos == parser.chars.len() && value.is_finite()).then_some(value)
+}
+
+/// Integers without a fractional part, everything else with up to ten
+/// decimals and no trailing zeros.
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        return format!("{}", value as i64);
+    }
+    let text = format!("{:.10}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn sum(&mut self) -> Option<f64> {
+        let mut value = self.product()?;
+        loop {
+            if self.eat('+') {
+                value += self.product()?;
+            } else if self.eat('-') {
+                value -= self.product()?;
+            } else {
+                return Some(value);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Option<f64> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value *= self.unary()?;
+            } else if self.eat('/') {
+                value /= self.unary()?;
+            } else if self.eat('%') {
+                value %= self.unary()?;
+            } else {
+                return Some(value);
+            }
+        }
+    }
+
+    /// A sign applies to the whole power, so `-2^2` is -4.
+    fn unary(&mut self) -> Option<f64> {
+        if self.eat('-') {
+            return Some(-self.unary()?);
+        }
+        if self.eat('+') {
+            return self.unary();
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Option<f64> {
+        let base = self.primary()?;
+        if self.eat('^') {
+            return Some(base.powf(self.unary()?));
+        }
+        Some(base)
+    }
+
+    fn primary(&mut self) -> Option<f64> {
+        if self.eat('(') {
+            let value = self.sum()?;
+            return self.eat(')').then_some(value);
+        }
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.pos += 1;
+        }
+        let number: String = self.chars[start..self.pos].iter().collect();
+        number.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        assert_eq!(evaluate("2+3*4"), Some(14.0));
+        assert_eq!(evaluate("(2+3)*4"), Some(20.0));
+        assert_eq!(evaluate("10-4-3"), Some(3.0));
+        assert_eq!(evaluate("16/4/2"), Some(2.0));
+    }
+
+    #[test]
+    fn power_is_right_associative_and_binds_tightest() {
+        assert_eq!(evaluate("2^3^2"), Some(512.0));
+        assert_eq!(evaluate("2*3^2"), Some(18.0));
+        assert_eq!(evaluate("2^-1"), Some(0.5));
+    }
+
+    #[test]
+    fn unary_minus_applies_to_the_whole_power() {
+        assert_eq!(evaluate("-2^2"), Some(-4.0));
+        assert_eq!(evaluate("(-2)^2"), Some(4.0));
+        assert_eq!(evaluate("3--2"), Some(5.0));
+        assert_eq!(evaluate("-(1+2)"), Some(-3.0));
+        assert_eq!(evaluate("+4"), Some(4.0));
+    }
+
+    #[test]
+    fn percent_is_the_remainder() {
+        assert_eq!(evaluate("17%5"), Some(2.0));
+        assert_eq!(evaluate("2+7%4"), Some(5.0));
+        assert_eq!(evaluate("5.5%2"), Some(1.5));
+    }
+
+    #[test]
+    fn division_by_zero_has_no_answer() {
+        assert_eq!(evaluate("1/0"), None);
+        assert_eq!(evaluate("0/0"), None);
+        assert_eq!(evaluate("5%0"), None);
+        assert_eq!(answer("= 1/0"), None);
+    }
+
+    #[test]
+    fn malformed_input_has_no_answer() {
+        for expr in ["1.2.3", "(", "(1+2", "1+2)", "", "1+", "*2", "2^", "."] {
+            assert_eq!(evaluate(expr), None, "{:?}", expr);
+        }
+    }
+
+    #[test]
+    fn whitespace_is_ignored() {
+        assert_eq!(evaluate(" ( 17 * 34 ) + 5 "), Some(583.0));
+    }
+
+    #[test]
+    fn answers_are_formatted_without_trailing_zeros() {
+        assert_eq!(answer("= (17*34)+5").as_deref(), Some("583"));
+        assert_eq!(answer("1/4").as_deref(), Some("0.25"));
+        assert_eq!(answer("1/3").as_deref(), Some("0.3333333333"));
+        assert_eq!(answer("2^10").as_deref(), Some("1024"));
+    }
+
+    #[test]
+    fn plain_words_and_names_are_left_to_the_search() {
+        assert_eq!(answer("firefox"), None);
+        assert_eq!(answer("firefox-esr"), None);
+        assert_eq!(answer("x-terminal-emulator"), None);
+        // Numbers alone need a `=` to count as a sum
+        assert_eq!(answer("42"), None);
+        assert_eq!(answer("=42").as_deref(), Some("42"));
+    }
+}
@@ -1,10 +1,13 @@
+use crate::config::Config;
 use crate::error::LauncherError;
 use std::{
+    collections::HashMap,
     env,
     ffi::OsStr,
     fs,
-    path::Path,
-    process::Command,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
     time::{Duration, Instant},
 };
 
@@ -14,7 +17,16 @@ pub struct LaunchItem {
     pub display_name: String,
     pub command: String,
     pub description: Option<String>,
+    pub generic_name: Option<String>,
+    pub keywords: Vec<String>,
     pub icon: Option<String>,
+    pub working_dir: Option<String>,
+    /// The executable or .desktop file the item was read from.
+    pub source: Option<PathBuf>,
+    /// WM_CLASS of the windows the item opens, from `StartupWMClass`.
+    pub wm_class: Option<String>,
+    /// Runs in `terminal_command`, from `Terminal=true`.
+    pub needs_terminal: bool,
     pub item_type: ItemType,
 }
 
@@ -22,20 +34,68 @@ pub struct LaunchItem {
 pub enum ItemType {
     Command,
     Application,
+    /// A session action such as reboot, from the power menu.
+    Action,
+    /// Text copied instead of run, like an emoji.
+    Character,
+    /// A line read from stdin in dmenu mode, printed when picked.
+    Text,
+    /// An open window, focused when picked.
+    Window(u32),
+    /// An SSH host, connected to in a terminal when picked.
+    Host,
+    /// The result of arithmetic typed into the query, copied when picked.
+    Answer,
+    /// A file opened with `xdg-open`, or a directory to browse into.
+    File,
+    /// An entry printed by the script mode of that name.
+    Script(String),
+    /// A running process, sent a signal when picked.
+    Process(u32),
+    /// A web search opened in the browser.
+    Web,
+}
+
+impl ItemType {
+    /// Lowercase name of the kind of item, as `--json` prints it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ItemType::Command => "command",
+            ItemType::Application => "application",
+            ItemType::Action => "action",
+            ItemType::Character => "character",
+            ItemType::Text => "text",
+            ItemType::Window(_) => "window",
+            ItemType::Host => "host",
+            ItemType::Answer => "answer",
+            ItemType::File => "file",
+            ItemType::Script(_) => "script",
+            ItemType::Process(_) => "process",
+            ItemType::Web => "web",
+        }
+    }
 }
 
 pub struct ItemCache {
     pub items: Vec<LaunchItem>,
+    /// Why sources failed to list their items on the last update.
+    pub failures: Vec<String>,
     last_updated: Instant,
+    /// When the process list was last read, which refreshes on its own.
+    processes_updated: Instant,
     timeout: Duration,
+    generation: u64,
 }
 
 impl ItemCache {
     pub fn new(timeout_secs: u64) -> Self {
         Self {
             items: Vec::new(),
+            failures: Vec::new(),
             last_updated: Instant::now() - Duration::from_secs(timeout_secs + 1),
+            processes_updated: Instant::now(),
             timeout: Duration::from_secs(timeout_secs),
+            generation: 0,
         }
     }
 
@@ -43,9 +103,32 @@ impl ItemCache {
         self.last_updated.elapsed() > self.timeout
     }
 
-    pub fn update(&mut self, items: Vec<LaunchItem>) {
+    /// Whether the process list was last read more than `age` ago. It goes
+    /// stale sooner than the cache timeout.
+    pub fn processes_older_than(&self, age: Duration) -> bool {
+        self.processes_updated.elapsed() > age
+    }
+
+    pub fn update(&mut self, items: Vec<LaunchItem>, failures: Vec<String>) {
         self.items = items;
+        self.failures = failures;
         self.last_updated = Instant::now();
+        self.processes_updated = self.last_updated;
+        self.generation += 1;
+    }
+
+    /// Swaps in a fresh process list and leaves every other item alone.
+    pub fn replace_processes(&mut self, processes: Vec<LaunchItem>) {
+        self.items
+            .retain(|item| !matches!(item.item_type, ItemType::Process(_)));
+        self.items.extend(processes);
+        self.processes_updated = Instant::now();
+        self.generation += 1;
+    }
+
+    /// Bumped on every update, so callers can tell when the items changed.
+    pub fn generation(&self) -> u64 {
+        self.generation
     }
 
     pub fn get(&self) -> &[LaunchItem] {
@@ -53,11 +136,19 @@ impl ItemCache {
     }
 }
 
+/// Whether `item` is a program found on PATH, which may be a console
+/// program that wants a terminal. `--run` lists commands too, but those
+/// come without a source file.
+pub fn is_path_command(item: &LaunchItem) -> bool {
+    item.item_type == ItemType::Command && item.source.is_some()
+}
+
 pub fn collect_commands() -> Vec<LaunchItem> {
     let mut items = Vec::new();
     let mut seen = std::collections::HashSet::new();
 
-    if let Ok(path_var) = env::var("PATH") {
+    // RUFI_TEST_PATH lets tests and sandboxes scan a fixed set of directories
+    if let Ok(path_var) = env::var("RUFI_TEST_PATH").or_else(|_| env::var("PATH")) {
         for dir in path_var.split(':') {
             if dir.is_empty() {
                 continue;
@@ -73,7 +164,13 @@ pub fn collect_commands() -> Vec<LaunchItem> {
                                     display_name: name.to_string(),
                                     command: name.to_string(),
                                     description: None,
+                                    generic_name: None,
+                                    keywords: Vec::new(),
                                     icon: None,
+                                    working_dir: None,
+                                    source: Some(path.clone()),
+                                    wm_class: None,
+                                    needs_terminal: false,
                                     item_type: ItemType::Command,
                                 });
                             }
@@ -88,31 +185,58 @@ pub fn collect_commands() -> Vec<LaunchItem> {
     items
 }
 
-pub fn collect_applications() -> Vec<LaunchItem> {
-    let mut items = Vec::new();
-    let desktop_dirs = vec![
-        "/usr/share/applications".to_string(),
-        "/usr/local/share/applications".to_string(),
-        format!(
-            "{}/.local/share/applications",
-            env::var("HOME").unwrap_or_default()
-        ),
+fn desktop_dirs() -> Vec<String> {
+    // RUFI_TEST_APP_DIRS replaces the standard locations, colon-separated
+    if let Ok(dirs) = env::var("RUFI_TEST_APP_DIRS") {
+        return dirs
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(String::from)
+            .collect();
+    }
+
+    // Highest precedence first: a desktop-file ID seen here hides the same ID
+    // in every directory after it
+    let home = env::var("HOME").unwrap_or_default();
+    vec![
+        format!("{}/.local/share/applications", home),
+        format!("{}/.local/share/flatpak/exports/share/applications", home),
         "/var/lib/flatpak/exports/share/applications".to_string(),
-        format!(
-            "{}/.local/share/flatpak/exports/share/applications",
-            env::var("HOME").unwrap_or_default()
-        ),
-    ];
-
-    for dir in desktop_dirs {
-        if let Ok(entries) = fs::read_dir(&dir) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if path.extension() == Some(OsStr::new("desktop")) {
-                    if let Some(app) = parse_desktop_entry(&path) {
-                        items.push(app);
-                    }
-                }
+        "/usr/local/share/applications".to_string(),
+        "/usr/share/applications".to_string(),
+    ]
+}
+
+/// Desktop names used for `OnlyShowIn`/`NotShowIn`, from the config override
+/// or `$XDG_CURRENT_DESKTOP`. Empty means no filtering.
+pub fn current_desktops(config_override: Option<&str>) -> Vec<String> {
+    let value = match config_override {
+        Some(value) => value.to_string(),
+        None => env::var("XDG_CURRENT_DESKTOP").unwrap_or_default(),
+    };
+    split_list(&value)
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split([';', ':'])
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+pub fn collect_applications(desktops: &[String]) -> Vec<LaunchItem> {
+    let mut items = Vec::new();
+    let mut seen_ids = std::collections::HashSet::new();
+
+    for dir in desktop_dirs() {
+        let mut files = Vec::new();
+        desktop_files(Path::new(&dir), "", &mut files);
+        for (id, path) in files {
+            // Hidden or filtered entries still shadow lower-precedence copies
+            if seen_ids.insert(id) {
+                items.extend(parse_desktop_entry(&path, desktops));
             }
         }
     }
@@ -121,54 +245,281 @@ pub fn collect_applications() -> Vec<LaunchItem> {
     items
 }
 
-fn parse_desktop_entry(path: &Path) -> Option<LaunchItem> {
-    let content = fs::read_to_string(path).ok()?;
+/// Collects the `.desktop` files under `dir` with their desktop-file IDs:
+/// the path relative to the applications directory with `/` replaced by
+/// `-`, so `kde/foo.desktop` is `kde-foo.desktop`.
+fn desktop_files(dir: &Path, prefix: &str, out: &mut Vec<(String, PathBuf)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(name) = entry.file_name().to_str().map(String::from) else {
+            continue;
+        };
+        if path.is_dir() {
+            desktop_files(&path, &format!("{}{}-", prefix, name), out);
+        } else if path.extension() == Some(OsStr::new("desktop")) {
+            out.push((format!("{}{}", prefix, name), path));
+        }
+    }
+}
+
+/// A `[Desktop Action <id>]` group of a desktop entry.
+#[derive(Default)]
+struct DesktopAction {
+    name: Option<String>,
+    exec: Option<String>,
+    icon: Option<String>,
+    no_display: bool,
+}
+
+/// Parses a desktop entry into its application item, followed by one item
+/// per action listed in `Actions=`. Empty when the entry isn't shown.
+fn parse_desktop_entry(path: &Path, desktops: &[String]) -> Vec<LaunchItem> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
     let mut name = None;
     let mut exec = None;
     let mut comment = None;
+    let mut generic_name = None;
+    let mut keywords = Vec::new();
     let mut icon = None;
+    let mut try_exec = None;
+    let mut working_dir = None;
+    let mut wm_class = None;
+    let mut only_show_in = None;
+    let mut not_show_in = None;
     let mut no_display = false;
     let mut hidden = false;
+    let mut needs_terminal = false;
+    let mut action_ids = Vec::new();
+    let mut actions: HashMap<String, DesktopAction> = HashMap::new();
+    // None outside the groups we read, Some("") in [Desktop Entry]
+    let mut group: Option<String> = None;
 
     for line in content.lines() {
         let line = line.trim();
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            group = if header == "Desktop Entry" {
+                Some(String::new())
+            } else {
+                header.strip_prefix("Desktop Action ").map(String::from)
+            };
+            continue;
+        }
+        match group.as_deref() {
+            None => continue,
+            Some("") => {}
+            Some(id) => {
+                let action = actions.entry(id.to_string()).or_default();
+                if line.starts_with("NoDisplay=true") {
+                    action.no_display = true;
+                } else if line.starts_with("Name=") && action.name.is_none() {
+                    action.name = line.split_once('=').map(|(_, v)| v.to_string());
+                } else if line.starts_with("Exec=") {
+                    action.exec = line.split_once('=').map(|(_, v)| v.to_string());
+                } else if line.starts_with("Icon=") {
+                    action.icon = line
+                        .split_once('=')
+                        .map(|(_, v)| v.to_string())
+                        .filter(|v| !v.is_empty());
+                }
+                continue;
+            }
+        }
         if line.starts_with("NoDisplay=true") {
             no_display = true;
         } else if line.starts_with("Hidden=true") {
             hidden = true;
+        } else if line.starts_with("Terminal=true") {
+            needs_terminal = true;
         } else if line.starts_with("Name=") && name.is_none() {
             name = line.split_once('=').map(|(_, v)| v.to_string());
         } else if line.starts_with("Exec=") {
             exec = line.split_once('=').map(|(_, v)| v.to_string());
         } else if line.starts_with("Comment=") {
             comment = line.split_once('=').map(|(_, v)| v.to_string());
+        } else if line.starts_with("GenericName=") {
+            generic_name = line.split_once('=').map(|(_, v)| v.to_string());
+        } else if line.starts_with("Keywords=") {
+            keywords = line
+                .split_once('=')
+                .map(|(_, v)| split_list(v))
+                .unwrap_or_default();
         } else if line.starts_with("Icon=") {
             icon = line.split_once('=').map(|(_, v)| v.to_string());
+        } else if line.starts_with("Path=") {
+            working_dir = line
+                .split_once('=')
+                .map(|(_, v)| v.to_string())
+                .filter(|v| !v.is_empty());
+        } else if line.starts_with("StartupWMClass=") {
+            wm_class = line
+                .split_once('=')
+                .map(|(_, v)| v.to_string())
+                .filter(|v| !v.is_empty());
+        } else if line.starts_with("TryExec=") {
+            try_exec = line.split_once('=').map(|(_, v)| v.to_string());
+        } else if line.starts_with("OnlyShowIn=") {
+            only_show_in = line.split_once('=').map(|(_, v)| split_list(v));
+        } else if line.starts_with("NotShowIn=") {
+            not_show_in = line.split_once('=').map(|(_, v)| split_list(v));
+        } else if line.starts_with("Actions=") {
+            action_ids = line
+                .split_once('=')
+                .map(|(_, v)| split_list(v))
+                .unwrap_or_default();
+        }
+    }
+
+    if !desktops.is_empty() {
+        // Desktop names are matched case-insensitively, as some sessions
+        // report e.g. `gnome` where entries list `GNOME`
+        let shown_in = |list: &Vec<String>| {
+            list.iter()
+                .any(|d| desktops.iter().any(|cur| cur.eq_ignore_ascii_case(d)))
+        };
+        if only_show_in.as_ref().is_some_and(|list| !shown_in(list))
+            || not_show_in.as_ref().is_some_and(shown_in)
+        {
+            return Vec::new();
         }
     }
 
     if no_display || hidden {
-        return None;
+        return Vec::new();
     }
 
-    let name = name?;
-    let exec = exec?;
+    if let Some(try_exec) = try_exec {
+        if !binary_exists(&try_exec) {
+            return Vec::new();
+        }
+    }
 
-    // Clean up exec command (remove %u, %f, etc.)
-    let exec = exec
-        .split_whitespace()
-        .filter(|&arg| !arg.starts_with('%'))
-        .collect::<Vec<_>>()
-        .join(" ");
+    let (Some(name), Some(exec)) = (name, exec) else {
+        return Vec::new();
+    };
 
-    Some(LaunchItem {
+    let app = LaunchItem {
         name: name.clone(),
-        display_name: name,
-        command: exec,
+        display_name: name.clone(),
+        command: expand_field_codes(&exec, &name, icon.as_deref(), path),
         description: comment,
+        generic_name,
+        keywords,
         icon,
+        working_dir,
+        source: Some(path.to_path_buf()),
+        wm_class,
+        needs_terminal,
         item_type: ItemType::Application,
-    })
+    };
+
+    let mut items = Vec::with_capacity(1 + action_ids.len());
+    for id in &action_ids {
+        let Some(action) = actions.remove(id) else {
+            continue;
+        };
+        if action.no_display {
+            continue;
+        }
+        let (Some(action_name), Some(exec)) = (action.name, action.exec) else {
+            continue;
+        };
+        let icon = action.icon.or_else(|| app.icon.clone());
+        let display_name = format!("{} — {}", name, action_name);
+        items.push(LaunchItem {
+            name: display_name.clone(),
+            display_name,
+            command: expand_field_codes(&exec, &name, icon.as_deref(), path),
+            icon,
+            ..app.clone()
+        });
+    }
+    items.insert(0, app);
+    items
+}
+
+/// Quotes `value` for `sh -c` when it contains anything but safe characters.
+pub fn shell_quote(value: &str) -> String {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@".contains(c))
+    {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+/// Expands the Exec field codes from the desktop entry spec. We never pass
+/// files or URLs, so `%f %F %u %U` (and the deprecated codes) are dropped.
+fn expand_field_codes(exec: &str, name: &str, icon: Option<&str>, path: &Path) -> String {
+    let mut expanded = String::with_capacity(exec.len());
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => expanded.push('%'),
+            Some('c') => expanded.push_str(&shell_quote(name)),
+            Some('k') => expanded.push_str(&shell_quote(&path.to_string_lossy())),
+            Some('i') => {
+                if let Some(icon) = icon {
+                    expanded.push_str("--icon ");
+                    expanded.push_str(&shell_quote(icon));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    expanded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Terminals tried in order when neither `terminal_command` nor
+/// `$TERMINAL` is set, with how each runs a command.
+const TERMINALS: [(&str, &str); 4] = [
+    ("alacritty", "alacritty -e {cmd}"),
+    ("kitty", "kitty {cmd}"),
+    ("foot", "foot {cmd}"),
+    ("xterm", "xterm -e {cmd}"),
+];
+
+/// The terminal command template to use when none is configured: `$TERMINAL`,
+/// then the first of `TERMINALS` on the PATH, then Debian's alternative.
+pub fn detect_terminal() -> String {
+    if let Some(terminal) = env::var("TERMINAL").ok().filter(|t| !t.trim().is_empty()) {
+        return format!("{} -e {{cmd}}", terminal.trim());
+    }
+    TERMINALS
+        .iter()
+        .find(|(program, _)| binary_exists(program))
+        .map_or("x-terminal-emulator -e {cmd}", |(_, template)| template)
+        .to_string()
+}
+
+/// Checks that `binary` is an executable file, looking it up in `PATH`
+/// unless it is an absolute path.
+pub fn binary_exists(binary: &str) -> bool {
+    if binary.contains('/') {
+        return is_executable(Path::new(binary));
+    }
+
+    env::var("RUFI_TEST_PATH")
+        .or_else(|_| env::var("PATH"))
+        .map(|path_var| {
+            path_var
+                .split(':')
+                .filter(|dir| !dir.is_empty())
+                .any(|dir| is_executable(&Path::new(dir).join(binary)))
+        })
+        .unwrap_or(false)
 }
 
 #[cfg(unix)]
@@ -179,22 +530,540 @@ fn is_executable(path: &Path) -> bool {
         .unwrap_or(false)
 }
 
-pub fn launch_item(item: &LaunchItem) -> Result<(), LauncherError> {
+/// Makes the child a session leader so it isn't tied to rufi's session or
+/// controlling terminal and survives the launcher exiting.
+#[cfg(unix)]
+fn detach(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    // SAFETY: the closure runs in the forked child before exec, where only
+    // async-signal-safe calls are allowed. setsid and reading errno are, and
+    // nothing here allocates or touches state shared with the parent.
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Clipboard tools tried in order, with the arguments that make them read
+/// the new contents from stdin.
+const CLIPBOARD_TOOLS: [(&str, &[&str]); 3] = [
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("wl-copy", &[]),
+];
+
+/// Puts `text` on the CLIPBOARD selection by piping it to `tool`, a shell
+/// command, or when that is empty the first clipboard tool on the PATH.
+/// The tool keeps serving the selection after rufi exits.
+pub fn copy_to_clipboard(text: &str, tool: &str) -> Result<(), LauncherError> {
+    let mut command = if tool.trim().is_empty() {
+        let (program, args) = CLIPBOARD_TOOLS
+            .iter()
+            .find(|(program, _)| binary_exists(program))
+            .ok_or_else(|| {
+                LauncherError::Other("no clipboard tool found (install xclip or xsel)".to_string())
+            })?;
+        let mut command = Command::new(program);
+        command.args(*args);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(tool);
+        command
+    };
+    detach(&mut command);
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// One-line summary of a command from its man page, as printed by `whatis`.
+pub fn command_summary(name: &str) -> Option<String> {
+    let output = Command::new("whatis")
+        .arg(name)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // "ls (1)               - list directory contents"
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (_, summary) = stdout.lines().next()?.split_once(" - ")?;
+    let summary = summary.trim();
+    (!summary.is_empty()).then(|| summary.to_string())
+}
+
+/// Starts `item` detached from rufi, in the configured terminal when it
+/// needs one or `in_terminal` asks for it.
+pub fn launch_item(
+    item: &LaunchItem,
+    cfg: &Config,
+    in_terminal: bool,
+) -> Result<(), LauncherError> {
+    let wrapped;
+    let item_command = if in_terminal || item.needs_terminal {
+        wrapped = cfg.in_terminal(&item.command);
+        &wrapped
+    } else {
+        &item.command
+    };
     // Parse command for shell execution
-    if item.command.contains(' ') || item.command.contains('&') || item.command.contains(';') {
-        Command::new("sh")
-            .arg("-c")
-            .arg(&item.command)
-            .stdin(std::process::Stdio::null())
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .spawn()?;
+    let mut command = if item_command.contains(' ')
+        || item_command.contains('&')
+        || item_command.contains(';')
+        || item_command.contains('\'')
+    {
+        // `sh -c` spawns fine even when the program is missing, so check it
+        // up front to be able to report the failure
+        if let Some(program) = item_command.split_whitespace().next() {
+            let is_plain_word = !program.contains(['=', '\'', '"', '$']);
+            // `./run` is found from the entry's working directory, not rufi's
+            let resolved = match &item.working_dir {
+                Some(dir) if program.contains('/') && !program.starts_with('/') => {
+                    Path::new(dir).join(program).to_string_lossy().into_owned()
+                }
+                _ => program.to_string(),
+            };
+            if is_plain_word && !binary_exists(&resolved) {
+                return Err(LauncherError::Other(format!(
+                    "{}: command not found",
+                    program
+                )));
+            }
+        }
+
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(item_command);
+        command
     } else {
-        Command::new(&item.command)
-            .stdin(std::process::Stdio::null())
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .spawn()?;
+        Command::new(item_command)
+    };
+
+    if let Some(dir) = &item.working_dir {
+        command.current_dir(dir);
     }
+
+    detach(&mut command);
+
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::sync::{Mutex, MutexGuard};
+
+    /// Held by tests that set environment variables, which are shared by
+    /// every test thread.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    fn env_lock() -> MutexGuard<'static, ()> {
+        ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write_entry(dir: &Path, file_name: &str, keys: &str) -> PathBuf {
+        let path = dir.join(file_name);
+        fs::write(
+            &path,
+            format!("[Desktop Entry]\nType=Application\n{}", keys),
+        )
+        .unwrap();
+        path
+    }
+
+    #[test]
+    fn try_exec_with_a_missing_binary_hides_the_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_entry(
+            dir.path(),
+            "gone.desktop",
+            "Name=Gone\nExec=gone\nTryExec=/nonexistent/bin\n",
+        );
+        assert!(parse_desktop_entry(&path, &[]).is_empty());
+    }
+
+    #[test]
+    fn only_commands_from_the_path_are_path_commands() {
+        let _env = env_lock();
+        let dir = tempfile::tempdir().unwrap();
+        let tool = dir.path().join("tool");
+        fs::write(&tool, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+        env::set_var("RUFI_TEST_PATH", dir.path());
+        let scanned = collect_commands();
+        env::remove_var("RUFI_TEST_PATH");
+
+        assert_eq!(scanned.len(), 1);
+        assert!(is_path_command(&scanned[0]));
+        // What `--run` lists, like a bookmark opened with xdg-open
+        let listed = LaunchItem {
+            source: None,
+            command: "xdg-open https://example.org".to_string(),
+            ..scanned[0].clone()
+        };
+        assert!(!is_path_command(&listed));
+    }
+
+    #[test]
+    fn try_exec_is_looked_up_in_the_path() {
+        let _env = env_lock();
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("bin");
+        fs::create_dir(&bin).unwrap();
+        let tool = bin.join("tool");
+        fs::write(&tool, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+        let plain = bin.join("plain");
+        fs::write(&plain, "").unwrap();
+        fs::set_permissions(&plain, fs::Permissions::from_mode(0o644)).unwrap();
+        env::set_var("RUFI_TEST_PATH", &bin);
+
+        let found = write_entry(
+            dir.path(),
+            "a.desktop",
+            "Name=Tool\nExec=tool\nTryExec=tool\n",
+        );
+        let missing = write_entry(
+            dir.path(),
+            "b.desktop",
+            "Name=Other\nExec=other\nTryExec=other\n",
+        );
+        let not_executable = write_entry(
+            dir.path(),
+            "c.desktop",
+            "Name=Plain\nExec=plain\nTryExec=plain\n",
+        );
+        let shown = |path: &Path| !parse_desktop_entry(path, &[]).is_empty();
+        let results = (shown(&found), shown(&missing), shown(&not_executable));
+        env::remove_var("RUFI_TEST_PATH");
+        assert_eq!(results, (true, false, false));
+    }
+
+    fn desktops(list: &[&str]) -> Vec<String> {
+        list.iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn current_desktop_splits_on_colons() {
+        assert_eq!(
+            current_desktops(Some("ubuntu:GNOME")),
+            desktops(&["ubuntu", "GNOME"])
+        );
+        assert!(current_desktops(Some("")).is_empty());
+    }
+
+    #[test]
+    fn current_desktop_comes_from_the_environment() {
+        let _env = env_lock();
+        env::set_var("XDG_CURRENT_DESKTOP", "ubuntu:GNOME");
+        let from_env = current_desktops(None);
+        let overridden = current_desktops(Some("KDE"));
+        env::remove_var("XDG_CURRENT_DESKTOP");
+        assert_eq!(from_env, desktops(&["ubuntu", "GNOME"]));
+        assert_eq!(overridden, desktops(&["KDE"]));
+    }
+
+    #[test]
+    fn only_show_in_matches_any_current_desktop() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_entry(
+            dir.path(),
+            "gnome.desktop",
+            "Name=Settings\nExec=settings\nOnlyShowIn=GNOME;Unity;\n",
+        );
+        let current = desktops(&["ubuntu", "GNOME"]);
+        assert_eq!(parse_desktop_entry(&path, &current).len(), 1);
+        assert!(parse_desktop_entry(&path, &desktops(&["KDE"])).is_empty());
+    }
+
+    #[test]
+    fn not_show_in_hides_the_entry_on_any_current_desktop() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_entry(
+            dir.path(),
+            "not-kde.desktop",
+            "Name=Tool\nExec=tool\nNotShowIn=KDE;\n",
+        );
+        assert!(parse_desktop_entry(&path, &desktops(&["X-Cinnamon", "KDE"])).is_empty());
+        assert_eq!(parse_desktop_entry(&path, &desktops(&["GNOME"])).len(), 1);
+    }
+
+    #[test]
+    fn show_in_ignores_case() {
+        let dir = tempfile::tempdir().unwrap();
+        let only = write_entry(
+            dir.path(),
+            "only.desktop",
+            "Name=Only\nExec=only\nOnlyShowIn=GNOME;\n",
+        );
+        let not = write_entry(
+            dir.path(),
+            "not.desktop",
+            "Name=Not\nExec=not\nNotShowIn=GNOME;\n",
+        );
+        let current = desktops(&["gnome"]);
+        assert_eq!(parse_desktop_entry(&only, &current).len(), 1);
+        assert!(parse_desktop_entry(&not, &current).is_empty());
+    }
+
+    #[test]
+    fn entries_without_show_in_keys_are_always_shown() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_entry(dir.path(), "plain.desktop", "Name=Plain\nExec=plain\n");
+        assert_eq!(parse_desktop_entry(&path, &desktops(&["GNOME"])).len(), 1);
+        assert_eq!(parse_desktop_entry(&path, &[]).len(), 1);
+    }
+
+    #[test]
+    fn no_current_desktop_shows_restricted_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let only = write_entry(
+            dir.path(),
+            "only.desktop",
+            "Name=Only\nExec=only\nOnlyShowIn=GNOME;\n",
+        );
+        let not = write_entry(
+            dir.path(),
+            "not.desktop",
+            "Name=Not\nExec=not\nNotShowIn=GNOME;\n",
+        );
+        assert_eq!(parse_desktop_entry(&only, &[]).len(), 1);
+        assert_eq!(parse_desktop_entry(&not, &[]).len(), 1);
+    }
+
+    #[test]
+    fn earlier_directories_override_the_same_desktop_file_id() {
+        let _env = env_lock();
+        let dir = tempfile::tempdir().unwrap();
+        let user = dir.path().join("user");
+        let system = dir.path().join("system");
+        fs::create_dir(&user).unwrap();
+        fs::create_dir(&system).unwrap();
+        write_entry(&user, "foo.desktop", "Name=Foo (mine)\nExec=foo --mine\n");
+        write_entry(&system, "foo.desktop", "Name=Foo\nExec=foo\n");
+        write_entry(&system, "bar.desktop", "Name=Bar\nExec=bar\n");
+        env::set_var(
+            "RUFI_TEST_APP_DIRS",
+            format!("{}:{}", user.display(), system.display()),
+        );
+        let items = collect_applications(&[]);
+        env::remove_var("RUFI_TEST_APP_DIRS");
+
+        let names: Vec<&str> = items.iter().map(|i| i.display_name.as_str()).collect();
+        assert_eq!(names, ["Bar", "Foo (mine)"]);
+        assert_eq!(items[1].command, "foo --mine");
+    }
+
+    #[test]
+    fn subdirectories_prefix_the_desktop_file_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let kde = dir.path().join("kde");
+        fs::create_dir(&kde).unwrap();
+        write_entry(&kde, "foo.desktop", "Name=Foo\nExec=foo\n");
+        write_entry(dir.path(), "bar.desktop", "Name=Bar\nExec=bar\n");
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+        let mut files = Vec::new();
+        desktop_files(dir.path(), "", &mut files);
+        let mut ids: Vec<String> = files.into_iter().map(|(id, _)| id).collect();
+        ids.sort();
+        assert_eq!(ids, ["bar.desktop", "kde-foo.desktop"]);
+    }
+
+    #[test]
+    fn subdirectory_ids_override_flat_ones_in_later_directories() {
+        let _env = env_lock();
+        let dir = tempfile::tempdir().unwrap();
+        let user = dir.path().join("user");
+        let system = dir.path().join("system");
+        fs::create_dir_all(user.join("kde")).unwrap();
+        fs::create_dir(&system).unwrap();
+        write_entry(&user.join("kde"), "foo.desktop", "Name=Mine\nExec=foo\n");
+        write_entry(&system, "kde-foo.desktop", "Name=System\nExec=foo\n");
+        env::set_var(
+            "RUFI_TEST_APP_DIRS",
+            format!("{}:{}", user.display(), system.display()),
+        );
+        let items = collect_applications(&[]);
+        env::remove_var("RUFI_TEST_APP_DIRS");
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].display_name, "Mine");
+    }
+
+    #[test]
+    fn file_and_url_field_codes_are_dropped() {
+        let path = Path::new("/apps/viewer.desktop");
+        assert_eq!(
+            expand_field_codes("viewer %f %F %u %U --new", "Viewer", None, path),
+            "viewer --new"
+        );
+    }
+
+    #[test]
+    fn name_icon_and_location_field_codes_are_quoted() {
+        let path = Path::new("/apps/my viewer.desktop");
+        assert_eq!(
+            expand_field_codes("viewer %i %c %k", "Image Viewer", Some("viewer"), path),
+            "viewer --icon viewer 'Image Viewer' '/apps/my viewer.desktop'"
+        );
+    }
+
+    #[test]
+    fn icon_field_code_without_an_icon_is_dropped() {
+        let path = Path::new("/apps/viewer.desktop");
+        assert_eq!(
+            expand_field_codes("viewer %i --new", "Viewer", None, path),
+            "viewer --new"
+        );
+    }
+
+    #[test]
+    fn double_percent_is_a_literal_percent() {
+        let path = Path::new("/apps/calc.desktop");
+        assert_eq!(
+            expand_field_codes("calc 50%% %f", "Calc", None, path),
+            "calc 50%"
+        );
+    }
+
+    #[test]
+    fn deprecated_field_codes_are_dropped() {
+        let path = Path::new("/apps/old.desktop");
+        assert_eq!(
+            expand_field_codes("old %d %D %n %N %v %m -x", "Old", None, path),
+            "old -x"
+        );
+    }
+
+    #[test]
+    fn path_key_sets_the_working_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_entry(
+            dir.path(),
+            "game.desktop",
+            "Name=Game\nExec=./run %U\nPath=/opt/game\n",
+        );
+        let items = parse_desktop_entry(&path, &[]);
+        assert_eq!(items[0].command, "./run");
+        assert_eq!(items[0].working_dir.as_deref(), Some("/opt/game"));
+    }
+
+    #[test]
+    fn relative_programs_are_found_in_the_working_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("ran");
+        let script = dir.path().join("run");
+        fs::write(
+            &script,
+            format!("#!/bin/sh\ntouch '{}'\n", marker.display()),
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let item = LaunchItem {
+            name: "Game".to_string(),
+            display_name: "Game".to_string(),
+            command: "./run --fullscreen".to_string(),
+            description: None,
+            generic_name: None,
+            keywords: Vec::new(),
+            icon: None,
+            working_dir: Some(dir.path().display().to_string()),
+            source: None,
+            wm_class: None,
+            needs_terminal: false,
+            item_type: ItemType::Application,
+        };
+        launch_item(&item, &Config::default(), false).unwrap();
+        // The child is detached, so give it a moment to run
+        for _ in 0..100 {
+            if marker.exists() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert!(marker.exists());
+    }
+
+    #[test]
+    fn replacing_processes_keeps_the_other_items() {
+        let item = |name: &str, item_type: ItemType| LaunchItem {
+            name: name.to_string(),
+            display_name: name.to_string(),
+            command: name.to_string(),
+            description: None,
+            generic_name: None,
+            keywords: Vec::new(),
+            icon: None,
+            working_dir: None,
+            source: None,
+            wm_class: None,
+            needs_terminal: false,
+            item_type,
+        };
+        let mut cache = ItemCache::new(60);
+        cache.update(
+            vec![
+                item("firefox", ItemType::Application),
+                item("sleep", ItemType::Process(10)),
+            ],
+            vec!["a source failed".to_string()],
+        );
+        let generation = cache.generation();
+        cache.replace_processes(vec![item("vim", ItemType::Process(11))]);
+
+        let names: Vec<&str> = cache.get().iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, ["firefox", "vim"]);
+        assert_eq!(cache.failures, ["a source failed"]);
+        assert!(cache.generation() > generation);
+        assert!(!cache.processes_older_than(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn detached_children_lead_a_new_session() {
+        let mut command = Command::new("sleep");
+        command.arg("5");
+        detach(&mut command);
+        let mut child = command.spawn().unwrap();
+        let pid = child.id() as libc::pid_t;
+        // SAFETY: getsid only reads the session id of a process
+        let (child_sid, own_sid) = unsafe { (libc::getsid(pid), libc::getsid(0)) };
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(child_sid, pid);
+        assert_ne!(child_sid, own_sid);
+    }
+
+    #[test]
+    fn actions_are_named_after_the_app_with_an_em_dash() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_entry(
+            dir.path(),
+            "firefox.desktop",
+            "Name=Firefox\nExec=firefox %u\nActions=private;\n\n\
+             [Desktop Action private]\nName=New Private Window\nExec=firefox --private-window\n",
+        );
+        let items = parse_desktop_entry(&path, &[]);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].display_name, "Firefox — New Private Window");
+        assert_eq!(items[1].command, "firefox --private-window");
+    }
+}
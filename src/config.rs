@@ -1,86 +1,1122 @@
+use crate::commands;
+use crate::error::LauncherError;
 use crate::theme;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
 
 #[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(default)]
 pub struct ConfigTheme {
+    #[serde(with = "color")]
     pub bg_color: u32,
+    #[serde(with = "color")]
     pub fg_color: u32,
+    #[serde(with = "color")]
     pub selected_bg: u32,
+    #[serde(with = "color")]
     pub selected_fg: u32,
+    #[serde(with = "color")]
     pub border_color: u32,
+    #[serde(with = "color")]
     pub query_bg: u32,
+    #[serde(with = "color")]
     pub accent_color: u32,
+    /// Line between rows with `show_separators`; defaults to `border_color`.
+    #[serde(default, with = "optional_color", skip_serializing_if = "Option::is_none")]
+    pub separator_color: Option<u32>,
+    /// Every other row with `alternate_rows`; defaults to a tint of `bg_color`.
+    #[serde(default, with = "optional_color", skip_serializing_if = "Option::is_none")]
+    pub row_alt_bg: Option<u32>,
+    /// Type badge of applications; defaults to `accent_color`.
+    #[serde(default, with = "optional_color", skip_serializing_if = "Option::is_none")]
+    pub badge_app_color: Option<u32>,
+    /// Type badge of PATH commands; defaults to `border_color`.
+    #[serde(default, with = "optional_color", skip_serializing_if = "Option::is_none")]
+    pub badge_cmd_color: Option<u32>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+impl Default for ConfigTheme {
+    fn default() -> Self {
+        Self {
+            bg_color: 0x1e1e2e,      // catppuccin mocha base
+            fg_color: 0xcdd6f4,      // catppuccin mocha text
+            selected_bg: 0x89b4fa,   // catppuccin mocha blue
+            selected_fg: 0x1e1e2e,   // catppuccin mocha base
+            border_color: 0x6c7086,  // catppuccin mocha surface2
+            query_bg: 0x313244,      // catppuccin mocha surface0
+            accent_color: 0xf38ba8,  // catppuccin mocha pink
+            separator_color: None,
+            row_alt_bg: None,
+            badge_app_color: None,
+            badge_cmd_color: None,
+        }
+    }
+}
+
+/// Colors from the `[theme]` table. Each one that is set replaces the
+/// matching color of the named theme.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct ThemeOverride {
+    #[serde(default, with = "optional_color", skip_serializing_if = "Option::is_none")]
+    pub bg_color: Option<u32>,
+    #[serde(default, with = "optional_color", skip_serializing_if = "Option::is_none")]
+    pub fg_color: Option<u32>,
+    #[serde(default, with = "optional_color", skip_serializing_if = "Option::is_none")]
+    pub selected_bg: Option<u32>,
+    #[serde(default, with = "optional_color", skip_serializing_if = "Option::is_none")]
+    pub selected_fg: Option<u32>,
+    #[serde(default, with = "optional_color", skip_serializing_if = "Option::is_none")]
+    pub border_color: Option<u32>,
+    #[serde(default, with = "optional_color", skip_serializing_if = "Option::is_none")]
+    pub query_bg: Option<u32>,
+    #[serde(default, with = "optional_color", skip_serializing_if = "Option::is_none")]
+    pub accent_color: Option<u32>,
+    #[serde(default, with = "optional_color", skip_serializing_if = "Option::is_none")]
+    pub separator_color: Option<u32>,
+    #[serde(default, with = "optional_color", skip_serializing_if = "Option::is_none")]
+    pub row_alt_bg: Option<u32>,
+    #[serde(default, with = "optional_color", skip_serializing_if = "Option::is_none")]
+    pub badge_app_color: Option<u32>,
+    #[serde(default, with = "optional_color", skip_serializing_if = "Option::is_none")]
+    pub badge_cmd_color: Option<u32>,
+}
+
+impl ThemeOverride {
+    fn is_empty(&self) -> bool {
+        self.bg_color.is_none()
+            && self.fg_color.is_none()
+            && self.selected_bg.is_none()
+            && self.selected_fg.is_none()
+            && self.border_color.is_none()
+            && self.query_bg.is_none()
+            && self.accent_color.is_none()
+            && self.separator_color.is_none()
+            && self.row_alt_bg.is_none()
+            && self.badge_app_color.is_none()
+            && self.badge_cmd_color.is_none()
+    }
+
+    /// Returns `theme` with the colors set here in place of its own.
+    pub fn apply(&self, theme: ConfigTheme) -> ConfigTheme {
+        ConfigTheme {
+            bg_color: self.bg_color.unwrap_or(theme.bg_color),
+            fg_color: self.fg_color.unwrap_or(theme.fg_color),
+            selected_bg: self.selected_bg.unwrap_or(theme.selected_bg),
+            selected_fg: self.selected_fg.unwrap_or(theme.selected_fg),
+            border_color: self.border_color.unwrap_or(theme.border_color),
+            query_bg: self.query_bg.unwrap_or(theme.query_bg),
+            accent_color: self.accent_color.unwrap_or(theme.accent_color),
+            separator_color: self.separator_color.or(theme.separator_color),
+            row_alt_bg: self.row_alt_bg.or(theme.row_alt_bg),
+            badge_app_color: self.badge_app_color.or(theme.badge_app_color),
+            badge_cmd_color: self.badge_cmd_color.or(theme.badge_cmd_color),
+        }
+    }
+}
+
+/// Theme colors: read as an integer, `"#rrggbb"`, `"0xrrggbb"` or `"#rgb"`,
+/// written back as `"#rrggbb"`.
+mod color {
+    use serde::{Deserializer, Serializer, de};
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(color: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("#{:06x}", color & 0xffffff))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+        deserializer.deserialize_any(ColorVisitor)
+    }
+
+    struct ColorVisitor;
+
+    impl de::Visitor<'_> for ColorVisitor {
+        type Value = u32;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a color like \"#1e1e2e\", \"#fff\" or 0x1e1e2e")
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<u32, E> {
+            match u32::try_from(v) {
+                Ok(color) if color <= 0xffffff => Ok(color),
+                _ => Err(E::custom(format!("color {:#x} is out of range", v))),
+            }
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<u32, E> {
+            self.visit_i64(v.min(i64::MAX as u64) as i64)
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<u32, E> {
+            let trimmed = v.trim();
+            let hex = trimmed
+                .strip_prefix('#')
+                .or_else(|| trimmed.strip_prefix("0x"))
+                .or_else(|| trimmed.strip_prefix("0X"))
+                .unwrap_or(trimmed);
+            let invalid = || E::custom(format!("invalid color \"{}\"", v));
+            if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+            match hex.len() {
+                6 => u32::from_str_radix(hex, 16).map_err(|_| invalid()),
+                // #rgb doubles each digit, like CSS
+                3 => {
+                    let short = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+                    let (r, g, b) = ((short >> 8) & 0xf, (short >> 4) & 0xf, short & 0xf);
+                    Ok(((r * 0x11) << 16) | ((g * 0x11) << 8) | (b * 0x11))
+                }
+                _ => Err(invalid()),
+            }
+        }
+    }
+}
+
+/// Like `color`, for the optional theme colors.
+mod optional_color {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error> {
+        match color {
+            Some(color) => super::color::serialize(color, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u32>, D::Error> {
+        struct Color(u32);
+
+        impl<'de> Deserialize<'de> for Color {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                super::color::deserialize(deserializer).map(Color)
+            }
+        }
+
+        Ok(Option::<Color>::deserialize(deserializer)?.map(|Color(color)| color))
+    }
+}
+
+/// Where the window sits on its monitor before offsets are applied.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    /// Horizontally centered, a third of the way down.
+    #[default]
+    Center,
+    Top,
+    TopLeft,
+    TopRight,
+    Bottom,
+    BottomLeft,
+    BottomRight,
+    Left,
+    Right,
+}
+
+impl FromStr for Anchor {
+    type Err = String;
+
+    /// Parses the names used in the config, for the command line.
+    fn from_str(v: &str) -> Result<Self, String> {
+        use de::IntoDeserializer;
+        Anchor::deserialize(v.trim().into_deserializer())
+            .map_err(|e: de::value::Error| e.to_string())
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// One result per row below the query bar, or a grid with `columns`.
+    #[default]
+    #[serde(alias = "list")]
+    Vertical,
+    /// A single dmenu-style row with results side by side.
+    Horizontal,
+}
+
+/// A source of launcher items, named after rofi's modes. Ctrl+Tab cycles
+/// through the ones listed in `modes`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub enum LauncherMode {
+    /// Applications and commands together.
+    Combi,
+    /// Desktop applications.
+    Drun,
+    /// Commands on the PATH.
+    Run,
+    /// Open windows, focused when picked. Not part of `combi`.
+    Window,
+    /// Hosts from the ssh config. Not part of `combi`.
+    Ssh,
+    /// Files and directories under `file_roots`. Not part of `combi`.
+    Files,
+    /// Bundled emoji, copied when picked. Not part of `combi`.
+    Emoji,
+    /// The user's running processes, sent SIGTERM when picked. Not part of
+    /// `combi`.
+    Kill,
+    /// The session actions of the `[power]` section. Not part of `combi`.
+    Power,
+    /// A `[[script_modes]]` entry, by its name.
+    Script(String),
+}
+
+impl LauncherMode {
+    pub fn name(&self) -> &str {
+        match self {
+            LauncherMode::Combi => "combi",
+            LauncherMode::Drun => "drun",
+            LauncherMode::Run => "run",
+            LauncherMode::Window => "window",
+            LauncherMode::Ssh => "ssh",
+            LauncherMode::Files => "files",
+            LauncherMode::Emoji => "emoji",
+            LauncherMode::Kill => "kill",
+            LauncherMode::Power => "power",
+            LauncherMode::Script(name) => name,
+        }
+    }
+}
+
+impl FromStr for LauncherMode {
+    type Err = String;
+
+    /// Parses the names used in the config, for the command line. Names
+    /// other than the built-in ones refer to script modes.
+    fn from_str(v: &str) -> Result<Self, String> {
+        Ok(match v.trim() {
+            "" => return Err("empty mode name".to_string()),
+            "combi" => LauncherMode::Combi,
+            "drun" => LauncherMode::Drun,
+            "run" => LauncherMode::Run,
+            "window" => LauncherMode::Window,
+            "ssh" => LauncherMode::Ssh,
+            "files" => LauncherMode::Files,
+            "emoji" => LauncherMode::Emoji,
+            "kill" => LauncherMode::Kill,
+            "power" => LauncherMode::Power,
+            name => LauncherMode::Script(name.to_string()),
+        })
+    }
+}
+
+impl TryFrom<String> for LauncherMode {
+    type Error = String;
+
+    fn try_from(v: String) -> Result<Self, String> {
+        v.parse()
+    }
+}
+
+impl From<LauncherMode> for String {
+    fn from(mode: LauncherMode) -> String {
+        mode.name().to_string()
+    }
+}
+
+/// A launcher mode listing what a script prints, like rofi's script modes,
+/// so existing rofi scripts work unchanged.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ScriptMode {
+    /// What `modes` and `--modes` call it.
+    pub name: String,
+    /// Run through `sh` without arguments to list one entry per line, then
+    /// with the picked entry as its argument. Lines it prints then replace
+    /// the list; printing nothing closes rufi.
+    pub command: String,
+    /// Typing this at the start of the query searches only this mode, from
+    /// any launcher mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    /// Run instead of `command` when an entry is picked, with `{}` replaced
+    /// by the entry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_select: Option<String>,
+}
+
+/// Which result rows show their description wrapped over `desc_max_lines`;
+/// the others keep it on one line.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum DescriptionWrap {
+    #[default]
+    Selected,
+    All,
+}
+
+/// What Ctrl+C copies from the selected item.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum CopyField {
+    #[default]
+    Command,
+    Name,
+}
+
+/// A window dimension: absolute pixels (`800`), a share of the monitor
+/// (`"40%"`), or `"auto"` to size the height from `max_visible_lines`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dimension {
+    Pixels(u16),
+    Percent(f32),
+    Auto,
+}
+
+impl Dimension {
+    /// Resolves to pixels against the monitor extent; `Auto` yields `auto`.
+    pub fn resolve(self, monitor_extent: u16, auto: u16) -> u16 {
+        match self {
+            Dimension::Pixels(px) => px,
+            Dimension::Percent(pct) => (monitor_extent as f32 * pct / 100.0)
+                .round()
+                .clamp(1.0, u16::MAX as f32) as u16,
+            Dimension::Auto => auto,
+        }
+    }
+}
+
+impl Dimension {
+    /// Scales a pixel size by `factor`; percentages and `auto` are relative
+    /// already.
+    pub fn scaled(self, factor: f32) -> Self {
+        match self {
+            Dimension::Pixels(px) => Dimension::Pixels(scale_px(px, factor)),
+            other => other,
+        }
+    }
+}
+
+fn scale_px(px: u16, factor: f32) -> u16 {
+    (px as f32 * factor).round().clamp(0.0, u16::MAX as f32) as u16
+}
+
+impl FromStr for Dimension {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<Self, String> {
+        let v = v.trim();
+        if v.eq_ignore_ascii_case("auto") {
+            return Ok(Dimension::Auto);
+        }
+        if let Some(pct) = v.strip_suffix('%') {
+            return match pct.trim().parse::<f32>() {
+                Ok(pct) if pct > 0.0 && pct <= 100.0 => Ok(Dimension::Percent(pct)),
+                _ => Err(format!("invalid percentage \"{}\"", v)),
+            };
+        }
+        v.parse::<u16>()
+            .map(Dimension::Pixels)
+            .map_err(|_| format!("invalid dimension \"{}\"", v))
+    }
+}
+
+impl Serialize for Dimension {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Dimension::Pixels(px) => serializer.serialize_u16(*px),
+            Dimension::Percent(pct) => serializer.serialize_str(&format!("{}%", pct)),
+            Dimension::Auto => serializer.serialize_str("auto"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Dimension {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct DimensionVisitor;
+
+        impl de::Visitor<'_> for DimensionVisitor {
+            type Value = Dimension;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a pixel count, a percentage like \"40%\", or \"auto\"")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Dimension, E> {
+                u16::try_from(v)
+                    .map(Dimension::Pixels)
+                    .map_err(|_| E::custom(format!("{} pixels is out of range", v)))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Dimension, E> {
+                self.visit_i64(v.min(i64::MAX as u64) as i64)
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Dimension, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(DimensionVisitor)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Config {
     pub theme_name: Option<String>,
+    #[serde(default = "default_prompt")]
+    pub prompt: String,
+    #[serde(default = "default_placeholder")]
+    pub placeholder: String,
+    /// Shown when nothing matches; `{query}` is replaced with the query.
+    #[serde(default = "default_empty_message")]
+    pub empty_message: String,
+    #[serde(default = "default_font")]
     pub font: String,
+    #[serde(default = "default_font_size")]
     pub font_size: u16,
-    pub width: u16,
-    pub height: u16,
+    #[serde(default = "default_width")]
+    pub width: Dimension,
+    #[serde(default = "default_height")]
+    pub height: Dimension,
+    #[serde(default = "default_max_visible_lines")]
+    pub max_visible_lines: u16,
+    #[serde(default)]
+    pub dynamic_height: bool,
+    #[serde(default)]
+    pub layout: Layout,
+    /// More than one lays the vertical results out as a grid with large
+    /// icons above the names.
+    #[serde(default = "default_columns", alias = "grid_columns")]
+    pub columns: u16,
+    #[serde(default = "default_item_height")]
     pub item_height: u16,
+    #[serde(default = "default_padding")]
     pub padding: u16,
+    /// Inset of a result's name from the top of its row, which also sets the
+    /// gap above descriptions. Follows `padding` when unset.
+    #[serde(default)]
+    pub text_padding: Option<u16>,
+    /// Extra space left between result rows.
+    #[serde(default)]
+    pub row_spacing: u16,
+    #[serde(default = "default_border_width")]
     pub border_width: u16,
+    /// Border drawn by rufi itself inside the window edges, in `border_color`.
+    /// Unlike the X border it looks the same under every compositor.
+    #[serde(default)]
+    pub inner_border_width: u16,
+    /// Outline around the query bar, in `accent_color`.
+    #[serde(default)]
+    pub query_outline_width: u16,
+    #[serde(default = "default_corner_radius")]
     pub corner_radius: u16,
+    /// Let the window manager manage the window as a floating dialog instead
+    /// of using override_redirect and a keyboard grab.
+    #[serde(default)]
+    pub managed_window: bool,
+    /// Keyboard grab attempts before giving up, with a short growing pause
+    /// between them.
+    #[serde(default = "default_grab_attempts")]
+    pub grab_attempts: u32,
+    /// Fades the window in when it opens, or slides it in from its anchor
+    /// when no compositor is running.
+    #[serde(default)]
+    pub animations: bool,
+    /// Length of the opening animation in milliseconds.
+    #[serde(default = "default_animation_ms")]
+    pub animation_ms: u32,
+    /// Multiplier for every pixel size; `None` derives it from `Xft.dpi`.
+    #[serde(default)]
+    pub scale: Option<f32>,
+    #[serde(default)]
+    pub monitor: Option<String>,
+    #[serde(default, alias = "position")]
+    pub anchor: Anchor,
+    /// Gap in pixels kept between the window and the monitor edges it is
+    /// anchored to.
+    #[serde(default)]
+    pub margin: u16,
+    #[serde(default)]
+    pub x_offset: i16,
+    #[serde(default)]
+    pub y_offset: i16,
+    #[serde(default = "default_max_results")]
     pub max_results: usize,
+    /// Characters to type before any results are searched for; an empty
+    /// query still lists everything.
+    #[serde(default)]
+    pub min_query_length: usize,
+    #[serde(default = "default_true")]
     pub show_descriptions: bool,
+    /// Lines a long description wraps onto before it is cut off with "...".
+    #[serde(default = "default_desc_max_lines")]
+    pub desc_max_lines: u16,
+    #[serde(default)]
+    pub desc_wrap: DescriptionWrap,
+    #[serde(default = "default_true")]
     pub show_icons: bool,
+    /// Icon drawn for applications (and power actions) whose own icon is
+    /// missing; "" leaves the slot empty.
+    #[serde(default = "default_fallback_icon")]
+    pub fallback_icon: String,
+    /// Like `fallback_icon`, for commands.
+    #[serde(default = "default_command_fallback_icon")]
+    pub command_fallback_icon: String,
+    #[serde(default)]
+    pub show_footer: bool,
+    #[serde(default)]
+    pub show_preview: bool,
+    /// Row of key hints below the results.
+    #[serde(default)]
+    pub show_hints: bool,
+    #[serde(default = "default_true")]
+    pub show_type_badges: bool,
+    #[serde(default)]
+    pub show_separators: bool,
+    /// Draw the selected item's name in the bold face of the font.
+    #[serde(default)]
+    pub bold_selection: bool,
+    /// Shade every other row, in `row_alt_bg` or a tint of `bg_color`.
+    #[serde(default, alias = "zebra_stripes")]
+    pub alternate_rows: bool,
+    #[serde(default = "default_true")]
+    pub recent_on_empty: bool,
+    #[serde(default)]
+    pub escape_clears_first: bool,
+    #[serde(default = "default_cache_timeout")]
     pub cache_timeout: u64, // timeout in secs
+    #[serde(default)]
+    pub current_desktop: Option<String>,
+    /// Launcher modes Ctrl+Tab cycles through, starting with the first.
+    #[serde(default = "default_modes")]
+    pub modes: Vec<LauncherMode>,
+    /// Carry the query over and start at the top when Ctrl+Tab switches
+    /// modes, instead of coming back to where each mode was left.
+    #[serde(default)]
+    pub reset_on_mode_switch: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub script_modes: Vec<ScriptMode>,
+    /// Command that runs `{cmd}` in a terminal window; without the
+    /// placeholder the command is appended. Empty picks `$TERMINAL` or the
+    /// first known terminal installed.
+    #[serde(default)]
+    pub terminal_command: String,
+    /// Runs commands from the PATH in the terminal, as Shift+Enter does.
+    #[serde(default)]
+    pub commands_in_terminal: bool,
+    /// Also list the hosts of `~/.ssh/known_hosts` in the ssh mode. Hashed
+    /// entries are skipped.
+    #[serde(default)]
+    pub ssh_known_hosts: bool,
+    #[serde(default)]
+    pub copy_field: CopyField,
+    /// Command the copied text is piped to; "" uses xclip, xsel or wl-copy,
+    /// whichever is installed.
+    #[serde(default)]
+    pub clipboard_command: String,
+    /// Raise an application's open window, matched by `StartupWMClass` or
+    /// the desktop file name, instead of starting another instance.
+    #[serde(default)]
+    pub focus_running: bool,
+    /// Directories the files mode lists, `~` for the home directory.
+    #[serde(default = "default_file_roots")]
+    pub file_roots: Vec<String>,
+    /// Levels below each root the files mode descends; deeper directories
+    /// can still be browsed by typing their path.
+    #[serde(default = "default_file_max_depth")]
+    pub file_max_depth: usize,
+    #[serde(default = "default_file_max_entries")]
+    pub file_max_entries: usize,
+    /// File and directory names the files mode skips.
+    #[serde(default = "default_file_ignore")]
+    pub file_ignore: Vec<String>,
+    #[serde(default = "default_icon_cache_size")]
+    pub icon_cache_size: usize,
+    /// The `[theme]` table as written in the file.
+    #[serde(default, rename = "theme", skip_serializing_if = "ThemeOverride::is_empty")]
+    pub theme_overrides: ThemeOverride,
+    /// Colors in use: the named theme with `theme_overrides` applied.
+    #[serde(skip)]
     pub theme: ConfigTheme,
+    #[serde(default)]
+    pub power: PowerConfig,
+    #[serde(default)]
+    pub web_search: WebSearchConfig,
+}
+
+/// Sets `theme_name` in the config file at `path`, keeping the rest of the
+/// file, comments and ordering included, as it was.
+pub fn save_theme_name(path: &Path, theme_name: &str) -> Result<(), LauncherError> {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut doc: toml_edit::DocumentMut = data
+        .parse()
+        .map_err(|e| LauncherError::Other(format!("{}: {}", path.display(), e)))?;
+    doc["theme_name"] = toml_edit::value(theme_name);
+    fs::write(path, doc.to_string())?;
+    Ok(())
+}
+
+/// Commands run by the power menu (`rufi --power` or the `power` mode). An
+/// empty command hides that action.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct PowerConfig {
+    pub lock: String,
+    pub logout: String,
+    pub suspend: String,
+    pub reboot: String,
+    pub shutdown: String,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            lock: "loginctl lock-session".to_string(),
+            logout: "loginctl terminate-session ${XDG_SESSION_ID-}".to_string(),
+            suspend: "systemctl suspend".to_string(),
+            reboot: "systemctl reboot".to_string(),
+            shutdown: "systemctl poweroff".to_string(),
+        }
+    }
+}
+
+/// Search engines offered when nothing matches the query, or picked by
+/// name with a bang like `!gh rufi`. Each URL has `{query}` where the
+/// search terms go.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct WebSearchConfig {
+    /// Engine offered when nothing matches; "" turns the fallback off.
+    pub default: String,
+    #[serde(flatten)]
+    pub engines: BTreeMap<String, String>,
+}
+
+impl Default for WebSearchConfig {
+    fn default() -> Self {
+        let engines = [
+            ("duckduckgo", "https://duckduckgo.com/?q={query}"),
+            ("google", "https://www.google.com/search?q={query}"),
+            ("gh", "https://github.com/search?q={query}"),
+            ("wiki", "https://en.wikipedia.org/w/index.php?search={query}"),
+        ];
+        Self {
+            default: "duckduckgo".to_string(),
+            engines: engines
+                .iter()
+                .map(|(name, url)| (name.to_string(), url.to_string()))
+                .collect(),
+        }
+    }
+}
+
+fn default_font() -> String {
+    "JetBrains Mono".to_string()
+}
+
+fn default_font_size() -> u16 {
+    18
+}
+
+fn default_width() -> Dimension {
+    Dimension::Pixels(450)
+}
+
+fn default_height() -> Dimension {
+    Dimension::Pixels(350)
+}
+
+fn default_item_height() -> u16 {
+    30
+}
+
+fn default_padding() -> u16 {
+    15
+}
+
+fn default_border_width() -> u16 {
+    2
+}
+
+fn default_corner_radius() -> u16 {
+    12
+}
+
+fn default_max_results() -> usize {
+    50
+}
+
+fn default_modes() -> Vec<LauncherMode> {
+    vec![LauncherMode::Combi, LauncherMode::Drun, LauncherMode::Run]
+}
+
+fn default_file_roots() -> Vec<String> {
+    vec!["~".to_string()]
+}
+
+fn default_file_max_depth() -> usize {
+    3
+}
+
+fn default_file_max_entries() -> usize {
+    10000
+}
+
+fn default_file_ignore() -> Vec<String> {
+    [".git", "node_modules", ".cache", "target"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+}
+
+fn default_desc_max_lines() -> u16 {
+    1
+}
+
+fn default_cache_timeout() -> u64 {
+    300
+}
+
+fn default_fallback_icon() -> String {
+    "application-x-executable".to_string()
+}
+
+fn default_command_fallback_icon() -> String {
+    "utilities-terminal".to_string()
+}
+
+fn default_icon_cache_size() -> usize {
+    256
+}
+
+fn default_max_visible_lines() -> u16 {
+    8
+}
+
+fn default_prompt() -> String {
+    "❯".to_string()
+}
+
+fn default_placeholder() -> String {
+    "Search applications and commands...".to_string()
+}
+
+fn default_empty_message() -> String {
+    "No results".to_string()
+}
+
+fn default_grab_attempts() -> u32 {
+    20
+}
+
+fn default_animation_ms() -> u32 {
+    150
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_columns() -> u16 {
+    1
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             theme_name: Some("catppuccin-mocha".to_string()),
-            font: "JetBrains Mono".into(),
-            font_size: 18,
-            width: 450,
-            height:350,
-            item_height: 30,
-            padding: 15,
-            border_width: 2,
-            corner_radius: 12,
-            max_results: 50,
+            prompt: default_prompt(),
+            placeholder: default_placeholder(),
+            empty_message: default_empty_message(),
+            font: default_font(),
+            font_size: default_font_size(),
+            width: default_width(),
+            height: default_height(),
+            max_visible_lines: default_max_visible_lines(),
+            dynamic_height: false,
+            layout: Layout::Vertical,
+            columns: default_columns(),
+            item_height: default_item_height(),
+            padding: default_padding(),
+            text_padding: None,
+            row_spacing: 0,
+            border_width: default_border_width(),
+            inner_border_width: 0,
+            query_outline_width: 0,
+            corner_radius: default_corner_radius(),
+            managed_window: false,
+            grab_attempts: default_grab_attempts(),
+            animations: false,
+            animation_ms: default_animation_ms(),
+            scale: None,
+            monitor: None,
+            anchor: Anchor::Center,
+            margin: 0,
+            x_offset: 0,
+            y_offset: 0,
+            max_results: default_max_results(),
+            min_query_length: 0,
             show_descriptions: true,
+            desc_max_lines: default_desc_max_lines(),
+            desc_wrap: DescriptionWrap::Selected,
             show_icons: true,
-            cache_timeout: 300,
-            theme: ConfigTheme {
-                bg_color: 0x1e1e2e,      // catppuccin mocha base
-                fg_color: 0xcdd6f4,      // catppuccin mocha text
-                selected_bg: 0x89b4fa,   // catppuccin mocha blue
-                selected_fg: 0x1e1e2e,   // catppuccin mocha base
-                border_color: 0x6c7086,  // catppuccin mocha surface2
-                query_bg: 0x313244,      // catppuccin mocha surface0
-                accent_color: 0xf38ba8,  // catppuccin mocha pink
-            },
+            fallback_icon: default_fallback_icon(),
+            command_fallback_icon: default_command_fallback_icon(),
+            show_footer: false,
+            show_preview: false,
+            show_hints: false,
+            show_type_badges: true,
+            show_separators: false,
+            bold_selection: false,
+            alternate_rows: false,
+            recent_on_empty: true,
+            escape_clears_first: false,
+            cache_timeout: default_cache_timeout(),
+            current_desktop: None,
+            modes: default_modes(),
+            reset_on_mode_switch: false,
+            script_modes: Vec::new(),
+            terminal_command: String::new(),
+            commands_in_terminal: false,
+            ssh_known_hosts: false,
+            copy_field: CopyField::Command,
+            clipboard_command: String::new(),
+            focus_running: false,
+            file_roots: default_file_roots(),
+            file_max_depth: default_file_max_depth(),
+            file_max_entries: default_file_max_entries(),
+            file_ignore: default_file_ignore(),
+            icon_cache_size: default_icon_cache_size(),
+            theme_overrides: ThemeOverride::default(),
+            theme: ConfigTheme::default(),
+            power: PowerConfig::default(),
+            web_search: WebSearchConfig::default(),
         }
     }
 }
 
 impl Config {
-    pub fn load(path: &str) -> Self {
-        match fs::read_to_string(path) {
+    /// Reads the config at `path`, falling back to the defaults when the
+    /// file doesn't exist. A file that can't be read or parsed is an error.
+    pub fn load(path: &Path) -> Result<Self, LauncherError> {
+        let mut cfg = match fs::read_to_string(path) {
             Ok(data) => {
-                let mut cfg: Config = toml::from_str(&data).unwrap_or_default();
-                cfg.resolve_theme();
-                cfg
-            }
-            Err(_) => {
-                let mut cfg = Self::default();
-                cfg.resolve_theme();
+                let (cfg, unknown_keys) = Self::parse(&data)?;
+                for key in unknown_keys {
+                    eprintln!("Ignoring unknown key `{}` in {}", key, path.display());
+                }
                 cfg
             }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e.into()),
+        };
+        cfg.resolve_theme()?;
+        Ok(cfg)
+    }
+
+    /// Parses a config where every key is optional. Keys rufi doesn't know
+    /// (usually typos) are returned to warn about instead of rejecting the
+    /// file.
+    fn parse(data: &str) -> Result<(Self, Vec<String>), toml::de::Error> {
+        let deserializer = toml::Deserializer::parse(data)?;
+        let mut unknown_keys = Vec::new();
+        let cfg = serde_ignored::deserialize(deserializer, |key| {
+            unknown_keys.push(key.to_string());
+        })?;
+        Ok((cfg, unknown_keys))
+    }
+
+    /// Multiplies every pixel size by `factor`. Each value is rounded on its
+    /// own from the configured size, so repeated reloads don't drift.
+    pub fn apply_scale(&mut self, factor: f32) {
+        if !factor.is_finite() || factor <= 0.0 || factor == 1.0 {
+            return;
+        }
+        self.width = self.width.scaled(factor);
+        self.height = self.height.scaled(factor);
+        self.font_size = scale_px(self.font_size, factor);
+        self.item_height = scale_px(self.item_height, factor);
+        self.padding = scale_px(self.padding, factor);
+        self.text_padding = self.text_padding.map(|p| scale_px(p, factor));
+        self.row_spacing = scale_px(self.row_spacing, factor);
+        self.border_width = scale_px(self.border_width, factor);
+        self.inner_border_width = scale_px(self.inner_border_width, factor);
+        self.query_outline_width = scale_px(self.query_outline_width, factor);
+        self.corner_radius = scale_px(self.corner_radius, factor);
+        self.margin = scale_px(self.margin, factor);
+        self.x_offset = (self.x_offset as f32 * factor).round() as i16;
+        self.y_offset = (self.y_offset as f32 * factor).round() as i16;
+    }
+
+    /// Resolves `width`/`height` to pixels for a monitor of the given size.
+    /// An `auto` height fits the query bar plus `max_visible_lines` rows.
+    pub fn window_size(&self, monitor_width: u16, monitor_height: u16) -> (u16, u16) {
+        let row_height = if self.show_descriptions && self.item_height > 24 {
+            self.item_height + self.font_size + self.row_text_padding() / 2
+        } else {
+            self.item_height
+        } + self.row_spacing;
+        let query_h = self.item_height + self.padding;
+        let auto_height = query_h + self.padding * 2 + row_height * self.max_visible_lines;
+
+        // "auto" has no meaning for the width, so fall back to the default
+        let width = self.width.resolve(monitor_width, 450);
+        let height = self.height.resolve(monitor_height, auto_height);
+        (width, height)
+    }
+
+    /// `text_padding`, or `padding` when it isn't set.
+    pub fn row_text_padding(&self) -> u16 {
+        self.text_padding.unwrap_or(self.padding)
+    }
+
+    /// The launcher modes to cycle through; `combi` alone when none are set.
+    pub fn launcher_modes(&self) -> &[LauncherMode] {
+        if self.modes.is_empty() {
+            &[LauncherMode::Combi]
+        } else {
+            &self.modes
+        }
+    }
+
+    /// The `[[script_modes]]` entry called `name`.
+    pub fn script_mode(&self, name: &str) -> Option<&ScriptMode> {
+        self.script_modes.iter().find(|script| script.name == name)
+    }
+
+    /// The shell command running `command` in the configured terminal.
+    pub fn in_terminal(&self, command: &str) -> String {
+        let template = if self.terminal_command.trim().is_empty() {
+            commands::detect_terminal()
+        } else {
+            self.terminal_command.clone()
+        };
+        if template.contains("{cmd}") {
+            template.replace("{cmd}", command)
+        } else {
+            format!("{} {}", template, command)
+        }
+    }
+
+    /// Number of grid columns, or 1 when results are a plain list.
+    pub fn grid_columns(&self) -> usize {
+        match self.layout {
+            Layout::Vertical => self.columns.max(1) as usize,
+            Layout::Horizontal => 1,
         }
     }
 
-    pub fn resolve_theme(&mut self) {
-        if let Some(theme_name) = &self.theme_name {
-            if let Some(theme) = theme::get_theme(theme_name) {
-                self.theme = theme;
+    /// Sets the colors to the named built-in or user theme, or the default
+    /// one, with the `[theme]` overrides on top.
+    pub fn resolve_theme(&mut self) -> Result<(), LauncherError> {
+        let base = match &self.theme_name {
+            Some(theme_name) => theme::find_theme(theme_name)?.unwrap_or_else(|| {
+                eprintln!("Unknown theme {}, using the default", theme_name);
+                ConfigTheme::default()
+            }),
+            None => ConfigTheme::default(),
+        };
+        self.theme = self.theme_overrides.apply(base);
+        Ok(())
+    }
+
+    /// The config as TOML with the resolved colors written out in full, for
+    /// `--dump-config`.
+    pub fn to_effective_toml(&self) -> Result<String, LauncherError> {
+        let theme = &self.theme;
+        let effective = Config {
+            theme_overrides: ThemeOverride {
+                bg_color: Some(theme.bg_color),
+                fg_color: Some(theme.fg_color),
+                selected_bg: Some(theme.selected_bg),
+                selected_fg: Some(theme.selected_fg),
+                border_color: Some(theme.border_color),
+                query_bg: Some(theme.query_bg),
+                accent_color: Some(theme.accent_color),
+                separator_color: theme.separator_color,
+                row_alt_bg: theme.row_alt_bg,
+                badge_app_color: theme.badge_app_color,
+                badge_cmd_color: theme.badge_cmd_color,
+            },
+            ..self.clone()
+        };
+        Ok(toml::to_string(&effective)?)
+    }
+
+    /// The config as TOML. Without overrides the `[theme]` table is written
+    /// commented out, listing the colors in use as a starting point.
+    pub fn to_toml(&self) -> Result<String, LauncherError> {
+        let mut data = toml::to_string(self)?;
+        if self.theme_overrides.is_empty() {
+            data.push_str("\n# Colors set here replace those of theme_name\n# [theme]\n");
+            for line in toml::to_string(&self.theme)?.lines() {
+                data.push_str("# ");
+                data.push_str(line);
+                data.push('\n');
             }
         }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_file_gives_the_defaults() {
+        let (cfg, unknown_keys) = Config::parse("").unwrap();
+        assert!(unknown_keys.is_empty());
+        // Without `theme_name` the default colors apply, the same ones as the
+        // default theme's
+        let defaults = Config {
+            theme_name: None,
+            ..Config::default()
+        };
+        assert_eq!(toml::to_string(&cfg).unwrap(), toml::to_string(&defaults).unwrap());
+    }
+
+    #[test]
+    fn single_key_keeps_the_other_defaults() {
+        let (cfg, _) = Config::parse("font_size = 20").unwrap();
+        let defaults = Config::default();
+        assert_eq!(cfg.font_size, 20);
+        assert_eq!(cfg.item_height, defaults.item_height);
+        assert_eq!(cfg.padding, defaults.padding);
+    }
+
+    #[test]
+    fn unknown_keys_are_reported_without_failing() {
+        let data = "font_size = 20\nfont_szie = 30\n\n[theme]\nbg = \"#000000\"\n";
+        let (cfg, unknown_keys) = Config::parse(data).unwrap();
+        assert_eq!(cfg.font_size, 20);
+        assert_eq!(unknown_keys, ["font_szie", "theme.bg"]);
+    }
+
+    #[test]
+    fn invalid_color_is_an_error() {
+        assert!(Config::parse("[theme]\nbg_color = \"#12345z\"").is_err());
+        assert!(Config::parse("[theme]\nbg_color = 0x1000000").is_err());
+        let (cfg, _) = Config::parse("[theme]\nbg_color = \"#fff\"").unwrap();
+        assert_eq!(cfg.theme_overrides.bg_color, Some(0xffffff));
+    }
+
+    #[test]
+    fn malformed_toml_is_an_error() {
+        assert!(Config::parse("font_size = ").is_err());
+        assert!(Config::parse("font_size = \"big\"").is_err());
+    }
+
+    #[test]
+    fn load_accepts_paths_that_are_not_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(std::ffi::OsStr::from_bytes(b"rufi\xff.toml"));
+        fs::write(&path, "font_size = 20").unwrap();
+        assert_eq!(Config::load(&path).unwrap().font_size, 20);
     }
 }
@@ -1,5 +1,7 @@
 use thiserror::Error;
 use x11rb::rust_connection::ConnectError;
+#[cfg(feature = "wayland")]
+use smithay_client_toolkit::reexports::client as wayland_client;
 
 #[derive(Debug, Error)]
 pub enum LauncherError {
@@ -19,6 +21,12 @@ pub enum LauncherError {
     TomlSer(#[from] toml::ser::Error),
     #[error("X11 parsing error: {0}")]
     X11Parse(#[from] x11rb::errors::ParseError),
+    #[cfg(feature = "wayland")]
+    #[error("Wayland connect error: {0}")]
+    WaylandConnect(#[from] wayland_client::ConnectError),
+    #[cfg(feature = "wayland")]
+    #[error("Wayland dispatch error: {0}")]
+    WaylandDispatch(#[from] wayland_client::DispatchError),
     #[error("Error: {0}")]
     Other(String),
 }
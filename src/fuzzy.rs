@@ -1,18 +1,40 @@
 use crate::commands::{ItemType, LaunchItem};
+use std::collections::HashMap;
 
 const EXACT_MATCH_BONUS: i32 = 2000;
 const NAME_STARTS_WITH_BONUS: i32 = 1500;
 const COMMAND_STARTS_WITH_BONUS: i32 = 1400;
 const NAME_CONTAINS_BONUS: i32 = 1000;
 const COMMAND_CONTAINS_BONUS: i32 = 900;
+const GENERIC_NAME_CONTAINS_BONUS: i32 = 800;
+const KEYWORD_MATCH_BONUS: i32 = 700;
 const DESCRIPTION_CONTAINS_BONUS: i32 = 600;
 const APPLICATION_TYPE_BONUS: i32 = 50;
 
+/// Scores `items` against `query`. With an empty query, items named in
+/// `recent` (most recent first) lead and the rest keep their cache order.
 pub fn fuzzy_search(
     query: &str,
     items: &[LaunchItem],
     max_results: usize,
+    recent: &[String],
 ) -> Vec<(LaunchItem, i32)> {
+    if query.is_empty() && !recent.is_empty() {
+        let rank: HashMap<&str, usize> = recent
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.as_str(), i))
+            .collect();
+        let mut ordered: Vec<&LaunchItem> = items.iter().collect();
+        // Stable, so unranked items stay in their original order
+        ordered.sort_by_key(|item| rank.get(item.name.as_str()).copied().unwrap_or(usize::MAX));
+        return ordered
+            .into_iter()
+            .take(max_results)
+            .map(|item| (item.clone(), 0))
+            .collect();
+    }
+
     let mut scored: Vec<(LaunchItem, i32)> = items
         .iter()
         .filter_map(|item: &LaunchItem| fuzzy_score(query, item).map(|score| (item.clone(), score)))
@@ -34,7 +56,17 @@ fn fuzzy_score(query: &str, item: &LaunchItem) -> Option<i32> {
 
     let type_bonus = match item.item_type {
         ItemType::Application => APPLICATION_TYPE_BONUS,
-        ItemType::Command => 0,
+        ItemType::Command
+        | ItemType::Action
+        | ItemType::Character
+        | ItemType::Text
+        | ItemType::Window(_)
+        | ItemType::Host
+        | ItemType::Answer
+        | ItemType::File
+        | ItemType::Script(_)
+        | ItemType::Process(_)
+        | ItemType::Web => 0,
     };
 
     if name == query || command == query {
@@ -57,6 +89,20 @@ fn fuzzy_score(query: &str, item: &LaunchItem) -> Option<i32> {
         return Some(COMMAND_CONTAINS_BONUS - query.len() as i32 + type_bonus);
     }
 
+    if let Some(generic_name) = &item.generic_name {
+        if generic_name.to_lowercase().contains(&query) {
+            return Some(GENERIC_NAME_CONTAINS_BONUS - query.len() as i32 + type_bonus);
+        }
+    }
+
+    if item
+        .keywords
+        .iter()
+        .any(|keyword| keyword.to_lowercase().contains(&query))
+    {
+        return Some(KEYWORD_MATCH_BONUS - query.len() as i32 + type_bonus);
+    }
+
     if let Some(desc) = &item.description {
         let desc = desc.to_lowercase();
         if desc.contains(&query) {
@@ -105,3 +151,71 @@ fn fuzzy_match_score(query: &str, target: &str) -> Option<i32> {
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(name: &str) -> LaunchItem {
+        LaunchItem {
+            name: name.to_string(),
+            display_name: name.to_string(),
+            command: name.to_lowercase(),
+            description: None,
+            generic_name: None,
+            keywords: Vec::new(),
+            icon: None,
+            working_dir: None,
+            source: None,
+            wm_class: None,
+            needs_terminal: false,
+            item_type: ItemType::Application,
+        }
+    }
+
+    fn ranked(query: &str, items: &[LaunchItem]) -> Vec<String> {
+        fuzzy_search(query, items, 10, &[])
+            .into_iter()
+            .map(|(item, _)| item.display_name)
+            .collect()
+    }
+
+    #[test]
+    fn keyword_match_ranks_between_name_and_description() {
+        let by_name = app("Webcam");
+        let by_keyword = LaunchItem {
+            keywords: vec!["web".to_string(), "internet".to_string()],
+            ..app("Firefox")
+        };
+        let by_description = LaunchItem {
+            description: Some("Reads web feeds".to_string()),
+            ..app("Liferea")
+        };
+        let items = [by_description, by_keyword, by_name];
+        assert_eq!(ranked("web", &items), ["Webcam", "Firefox", "Liferea"]);
+    }
+
+    #[test]
+    fn generic_name_ranks_above_keywords() {
+        let by_keyword = LaunchItem {
+            keywords: vec!["browser".to_string()],
+            ..app("Lynx")
+        };
+        let by_generic_name = LaunchItem {
+            generic_name: Some("Web Browser".to_string()),
+            ..app("Firefox")
+        };
+        let items = [by_keyword, by_generic_name];
+        assert_eq!(ranked("browser", &items), ["Firefox", "Lynx"]);
+    }
+
+    #[test]
+    fn keywords_match_case_insensitively() {
+        let item = LaunchItem {
+            keywords: vec!["Internet".to_string()],
+            ..app("Firefox")
+        };
+        let score = fuzzy_score("INTER", &item).unwrap();
+        assert_eq!(score, KEYWORD_MATCH_BONUS - 5 + APPLICATION_TYPE_BONUS);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::error::LauncherError;
+use std::{fs, path::PathBuf};
+
+const MAX_ENTRIES: usize = 100;
+
+/// Names of launched items or past queries, most recent first, persisted
+/// one per line in `~/.local/share/rufi/history` and `queries`.
+pub struct History {
+    path: Option<PathBuf>,
+    entries: Vec<String>,
+}
+
+impl History {
+    /// Names of launched items.
+    pub fn load() -> Self {
+        Self::load_file("history")
+    }
+
+    /// Queries that launched something.
+    pub fn load_queries() -> Self {
+        Self::load_file("queries")
+    }
+
+    fn load_file(name: &str) -> Self {
+        let path = dirs::data_dir().map(|p| p.join("rufi").join(name));
+        let entries = path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .map(|data| {
+                data.lines()
+                    .filter(|line| !line.is_empty())
+                    .take(MAX_ENTRIES)
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { path, entries }
+    }
+
+    pub fn recent(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Moves `name` to the front and writes the history back to disk.
+    pub fn record(&mut self, name: &str) -> Result<(), LauncherError> {
+        self.entries.retain(|entry| entry != name);
+        self.entries.insert(0, name.to_string());
+        self.entries.truncate(MAX_ENTRIES);
+        self.save()
+    }
+
+    /// Forgets every entry, on disk too.
+    pub fn clear(&mut self) -> Result<(), LauncherError> {
+        self.entries.clear();
+        match &self.path {
+            Some(path) if path.exists() => Ok(fs::remove_file(path)?),
+            _ => Ok(()),
+        }
+    }
+
+    fn save(&self) -> Result<(), LauncherError> {
+        if let Some(path) = &self.path {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut data = self.entries.join("\n");
+            data.push('\n');
+            fs::write(path, data)?;
+        }
+        Ok(())
+    }
+}
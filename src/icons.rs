@@ -0,0 +1,415 @@
+use crate::error::LauncherError;
+use image::ImageReader;
+use resvg::tiny_skia::Pixmap;
+use resvg::tiny_skia::Transform;
+use resvg::usvg;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, OnceLock},
+    thread,
+    time::SystemTime,
+};
+
+/// Icon size the theme directories are ranked against: the nearest larger
+/// size first, so icons are scaled down rather than up.
+const PREFERRED_ICON_SIZE: u32 = 48;
+
+const ICON_EXTENSIONS: [&str; 2] = ["png", "svg"];
+
+/// Directories icon themes are installed in, most specific first.
+fn icon_base_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(home) = dirs::home_dir() {
+        dirs.push(home.join(".icons"));
+    }
+    if let Some(data) = dirs::data_dir() {
+        dirs.push(data.join("icons"));
+    }
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs.extend(
+        data_dirs
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| Path::new(dir).join("icons")),
+    );
+    dirs
+}
+
+/// Icon theme picked in the GTK settings, falling back to gsettings.
+fn configured_icon_theme() -> Option<String> {
+    let config_dir = dirs::config_dir()?;
+    for settings in ["gtk-3.0/settings.ini", "gtk-4.0/settings.ini"] {
+        let Ok(data) = fs::read_to_string(config_dir.join(settings)) else {
+            continue;
+        };
+        let theme = data.lines().find_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == "gtk-icon-theme-name")
+                .then(|| value.trim().trim_matches('"').to_string())
+        });
+        if let Some(theme) = theme.filter(|theme| !theme.is_empty()) {
+            return Some(theme);
+        }
+    }
+
+    let output = std::process::Command::new("gsettings")
+        .args(["get", "org.gnome.desktop.interface", "icon-theme"])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    let theme = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .trim_matches('\'')
+        .to_string();
+    (output.status.success() && !theme.is_empty()).then_some(theme)
+}
+
+/// The parts of a theme's `index.theme` that matter for lookups.
+#[derive(Default)]
+struct ThemeIndex {
+    inherits: Vec<String>,
+    /// Subdirectories with their nominal size.
+    directories: Vec<(String, u32)>,
+}
+
+fn read_theme_index(path: &Path) -> Option<ThemeIndex> {
+    let data = fs::read_to_string(path).ok()?;
+    let mut index = ThemeIndex::default();
+    let mut listed = Vec::new();
+    let mut sizes = HashMap::new();
+    let mut section = String::new();
+    for line in data.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.to_string();
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        let list = || {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        match (section.as_str(), key) {
+            ("Icon Theme", "Inherits") => index.inherits = list(),
+            ("Icon Theme", "Directories") => listed = list(),
+            (dir, "Size") => {
+                if let Ok(size) = value.parse::<u32>() {
+                    sizes.insert(dir.to_string(), size);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    index.directories = listed
+        .into_iter()
+        .map(|dir| {
+            let size = sizes.get(&dir).copied().unwrap_or(PREFERRED_ICON_SIZE);
+            (dir, size)
+        })
+        .collect();
+    index
+        .directories
+        .sort_by_key(|(_, size)| match size.checked_sub(PREFERRED_ICON_SIZE) {
+            Some(larger) => larger,
+            None => u32::MAX / 2 + (PREFERRED_ICON_SIZE - size),
+        });
+    Some(index)
+}
+
+/// Directories to look for icons in: those of the configured theme, the
+/// themes it inherits from and hicolor, then the unthemed locations.
+fn icon_search_path() -> &'static [PathBuf] {
+    static SEARCH_PATH: OnceLock<Vec<PathBuf>> = OnceLock::new();
+    SEARCH_PATH.get_or_init(|| {
+        let bases = icon_base_dirs();
+        let mut queue: Vec<String> = configured_icon_theme().into_iter().collect();
+        queue.push("hicolor".to_string());
+        let mut seen = HashSet::new();
+        let mut path = Vec::new();
+
+        while !queue.is_empty() {
+            let theme = queue.remove(0);
+            if !seen.insert(theme.clone()) {
+                continue;
+            }
+            // A theme can be spread over several base directories, with the
+            // index only in one of them
+            let Some(index) = bases
+                .iter()
+                .find_map(|base| read_theme_index(&base.join(&theme).join("index.theme")))
+            else {
+                continue;
+            };
+            for (dir, _) in &index.directories {
+                path.extend(bases.iter().map(|base| base.join(&theme).join(dir)));
+            }
+            let inherits = index.inherits;
+            // Breadth first, with hicolor kept as the last resort
+            let hicolor = queue.iter().position(|t| t == "hicolor");
+            let at = hicolor.unwrap_or(queue.len());
+            queue.splice(at..at, inherits);
+        }
+
+        path.extend(bases);
+        path.push(PathBuf::from("/usr/share/pixmaps"));
+        path.retain(|dir| dir.is_dir());
+        path
+    })
+}
+
+pub fn find_icon(icon_name: &str) -> Option<String> {
+    if icon_name.contains('/') {
+        if std::path::Path::new(icon_name).exists() {
+            return Some(icon_name.to_string());
+        }
+    }
+
+    for dir in icon_search_path() {
+        for ext in &ICON_EXTENSIONS {
+            let path = dir.join(format!("{}.{}", icon_name, ext));
+            if path.is_file() {
+                return path.to_str().map(String::from);
+            }
+        }
+    }
+
+    None
+}
+
+/// Decodes the icon at `icon_path` into a `size`x`size` RGBA buffer with
+/// straight (not premultiplied) alpha.
+fn load_icon_pixels(
+    icon_path: &str,
+    size: u16,
+    fontdb: &usvg::fontdb::Database,
+) -> Result<Vec<u8>, LauncherError> {
+    if size == 0 {
+        return Err(LauncherError::Other(
+            "icon size must be at least 1".to_string(),
+        ));
+    }
+    if icon_path.ends_with(".svg") {
+        let svg_data = std::fs::read(icon_path).map_err(|e| LauncherError::Io(e))?;
+        let mut options = usvg::Options::default();
+        options.default_size = usvg::Size::from_wh(size as f32, size as f32)
+            .ok_or_else(|| LauncherError::Other(format!("invalid icon size {}", size)))?;
+        let tree = usvg::Tree::from_data(&svg_data, &options, fontdb).map_err(|e| {
+            LauncherError::Io(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                e.to_string(),
+            ))
+        })?;
+
+        let mut pixmap = Pixmap::new(size as u32, size as u32)
+            .ok_or_else(|| LauncherError::Other(format!("cannot allocate a {0}x{0} icon", size)))?;
+        resvg::render(&tree, Transform::default(), &mut pixmap.as_mut());
+        // resvg renders premultiplied alpha; icons are blended as straight alpha
+        Ok(pixmap
+            .pixels()
+            .iter()
+            .flat_map(|px| {
+                let c = px.demultiply();
+                [c.red(), c.green(), c.blue(), c.alpha()]
+            })
+            .collect())
+    } else {
+        let img = ImageReader::open(icon_path)
+            .map_err(|e| LauncherError::Io(e))?
+            .decode()
+            .map_err(|e| {
+                LauncherError::Io(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    e.to_string(),
+                ))
+            })?;
+        let img = img.thumbnail(size as u32, size as u32).to_rgba8();
+        Ok(img.into_raw())
+    }
+}
+
+struct CachedIcon {
+    /// `None` when decoding failed, so the same file isn't retried every frame.
+    pixels: Option<Arc<Vec<u8>>>,
+    mtime: Option<SystemTime>,
+    last_used: u64,
+}
+
+pub enum IconState {
+    Ready(Arc<Vec<u8>>),
+    Pending,
+    Missing,
+}
+
+/// Decoded icon buffers keyed by resolved path and size, evicted least
+/// recently used first once `max_entries` is reached.
+pub struct IconCache {
+    entries: HashMap<(String, u16), CachedIcon>,
+    resolved: HashMap<String, Option<String>>,
+    max_entries: usize,
+    tick: u64,
+}
+
+impl IconCache {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            resolved: HashMap::new(),
+            max_entries: max_entries.max(1),
+            tick: 0,
+        }
+    }
+
+    /// Looks up an icon without touching anything but the file's mtime.
+    /// `Pending` means the icon still has to be resolved or (re)decoded.
+    pub fn lookup(&mut self, icon_name: &str, size: u16) -> IconState {
+        let icon_path = match self.resolved.get(icon_name) {
+            Some(Some(path)) => path.clone(),
+            Some(None) => return IconState::Missing,
+            None => return IconState::Pending,
+        };
+
+        self.tick += 1;
+        let mtime = fs::metadata(&icon_path).and_then(|m| m.modified()).ok();
+        match self.entries.get_mut(&(icon_path, size)) {
+            Some(entry) if entry.mtime == mtime => {
+                entry.last_used = self.tick;
+                match &entry.pixels {
+                    Some(pixels) => IconState::Ready(pixels.clone()),
+                    None => IconState::Missing,
+                }
+            }
+            _ => IconState::Pending,
+        }
+    }
+
+    fn insert(
+        &mut self,
+        key: (String, u16),
+        pixels: Option<Arc<Vec<u8>>>,
+        mtime: Option<SystemTime>,
+    ) {
+        self.tick += 1;
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.max_entries {
+            if let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.entries.insert(
+            key,
+            CachedIcon {
+                pixels,
+                mtime,
+                last_used: self.tick,
+            },
+        );
+    }
+}
+
+type IconQueue = Arc<(Mutex<Vec<(String, u16)>>, Condvar)>;
+
+/// Resolves and decodes icons on a worker thread so drawing never blocks on
+/// SVG rasterization. Rows render without their icon until it arrives.
+pub struct IconLoader {
+    cache: Arc<Mutex<IconCache>>,
+    queue: IconQueue,
+}
+
+impl IconLoader {
+    /// Starts the worker; `on_loaded` is called after each icon lands in the
+    /// cache so the UI can repaint.
+    pub fn spawn(max_entries: usize, on_loaded: impl Fn() + Send + 'static) -> Self {
+        let cache = Arc::new(Mutex::new(IconCache::new(max_entries)));
+        let queue: IconQueue = Arc::new((Mutex::new(Vec::new()), Condvar::new()));
+
+        let worker_cache = cache.clone();
+        let worker_queue = queue.clone();
+        thread::spawn(move || {
+            let mut fontdb = usvg::fontdb::Database::new();
+            fontdb.load_system_fonts();
+
+            loop {
+                let (icon_name, size) = {
+                    let (lock, ready) = &*worker_queue;
+                    let Ok(mut pending) = lock.lock() else { return };
+                    while pending.is_empty() {
+                        pending = match ready.wait(pending) {
+                            Ok(pending) => pending,
+                            Err(_) => return,
+                        };
+                    }
+                    pending.remove(0)
+                };
+
+                let resolved = worker_cache
+                    .lock()
+                    .ok()
+                    .and_then(|cache| cache.resolved.get(&icon_name).cloned());
+                let icon_path = match resolved {
+                    Some(path) => path,
+                    None => {
+                        let path = find_icon(&icon_name);
+                        if let Ok(mut cache) = worker_cache.lock() {
+                            cache.resolved.insert(icon_name.clone(), path.clone());
+                        }
+                        path
+                    }
+                };
+
+                if let Some(icon_path) = icon_path {
+                    let mtime = fs::metadata(&icon_path).and_then(|m| m.modified()).ok();
+                    let pixels = match load_icon_pixels(&icon_path, size, &fontdb) {
+                        Ok(pixels) => Some(Arc::new(pixels)),
+                        Err(e) => {
+                            eprintln!("Failed to load icon {}: {}", icon_path, e);
+                            None
+                        }
+                    };
+                    if let Ok(mut cache) = worker_cache.lock() {
+                        cache.insert((icon_path, size), pixels, mtime);
+                    }
+                }
+
+                on_loaded();
+            }
+        });
+
+        Self { cache, queue }
+    }
+
+    pub fn lookup(&self, icon_name: &str, size: u16) -> IconState {
+        match self.cache.lock() {
+            Ok(mut cache) => cache.lookup(icon_name, size),
+            Err(_) => IconState::Missing,
+        }
+    }
+
+    /// Replaces the pending work with `wanted`, in order. Called once per
+    /// frame with the visible rows so off-screen requests are dropped.
+    pub fn request(&self, wanted: Vec<(String, u16)>) {
+        let (lock, ready) = &*self.queue;
+        if let Ok(mut pending) = lock.lock() {
+            *pending = wanted;
+            if !pending.is_empty() {
+                ready.notify_one();
+            }
+        }
+    }
+}
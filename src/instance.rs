@@ -0,0 +1,112 @@
+use crate::error::LauncherError;
+use std::{
+    fs,
+    io::{self, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    thread,
+};
+
+/// What another invocation asks the running rufi to do.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Request {
+    /// Close the window, or open it when a daemon has it hidden.
+    Toggle,
+    /// Open the window of a daemon with an empty query (`rufi --show`).
+    Show,
+}
+
+impl Request {
+    fn as_str(self) -> &'static str {
+        match self {
+            Request::Toggle => "toggle",
+            Request::Show => "show",
+        }
+    }
+
+    fn parse(message: &str) -> Option<Self> {
+        match message.trim() {
+            "toggle" => Some(Request::Toggle),
+            "show" => Some(Request::Show),
+            _ => None,
+        }
+    }
+}
+
+/// Socket the running rufi listens on, in `$XDG_RUNTIME_DIR` when set.
+fn socket_path() -> PathBuf {
+    match dirs::runtime_dir() {
+        Some(dir) => dir.join("rufi.sock"),
+        // The temporary directory is shared between users
+        None => {
+            // SAFETY: getuid has no preconditions and cannot fail.
+            let uid = unsafe { libc::getuid() };
+            std::env::temp_dir().join(format!("rufi-{}.sock", uid))
+        }
+    }
+}
+
+/// Sends `request` to the running rufi. Fails when none is running.
+pub fn send(request: Request) -> io::Result<()> {
+    let mut stream = UnixStream::connect(socket_path())?;
+    stream.write_all(request.as_str().as_bytes())
+}
+
+/// Marks this process as the running rufi for as long as it lives. The
+/// socket file is removed on drop, including while unwinding from a panic.
+pub struct InstanceLock {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl InstanceLock {
+    /// Calls `on_request` from a background thread for each request sent by
+    /// another invocation.
+    pub fn listen(
+        &self,
+        on_request: impl Fn(Request) + Send + 'static,
+    ) -> Result<(), LauncherError> {
+        let listener = self.listener.try_clone()?;
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut message = String::new();
+                if stream.read_to_string(&mut message).is_err() {
+                    continue;
+                }
+                if let Some(request) = Request::parse(&message) {
+                    on_request(request);
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Takes the single-instance socket, or returns `None` when another rufi is
+/// already running and holds it.
+pub fn acquire() -> Result<Option<InstanceLock>, LauncherError> {
+    let path = socket_path();
+    if UnixStream::connect(&path).is_ok() {
+        return Ok(None);
+    }
+
+    // Nobody answered, so any socket file is left over from a crash
+    match fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    match UnixListener::bind(&path) {
+        Ok(listener) => Ok(Some(InstanceLock { listener, path })),
+        // Another instance started between the connect and the bind
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -1,24 +1,182 @@
 use clap::Parser;
 use std::fs;
+use std::path::PathBuf;
 use x11rb::rust_connection::RustConnection;
 
+mod calc;
 mod commands;
 mod config;
 mod error;
 mod fuzzy;
+mod history;
+mod icons;
+mod instance;
+mod modes;
+mod monitor;
 mod theme;
 mod ui;
+#[cfg(feature = "wayland")]
+mod wayland;
+mod web_search;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
+#[command(group = clap::ArgGroup::new("menu").args(["dmenu", "json_input"]).multiple(true))]
 struct Args {
+    /// Theme to use for this run
     #[arg(long)]
     theme: Option<String>,
+    /// Also store the --theme choice in the config file
+    #[arg(long = "save-theme", requires = "theme")]
+    save_theme: bool,
     #[arg(long = "available-themes")]
     available_themes: bool,
+    /// Use this config file instead of $RUFI_CONFIG or ~/.config/rufi/rufirc.toml
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Create the config file given with --config or $RUFI_CONFIG if it is missing
+    #[arg(long = "write-default-config", conflicts_with = "no_config")]
+    write_default_config: bool,
+    /// Run with the built-in defaults without reading or writing any config file
+    #[arg(long = "no-config", conflicts_with = "config")]
+    no_config: bool,
+    /// Exit with an error instead of falling back to defaults when the config is invalid
+    #[arg(long = "strict-config")]
+    strict_config: bool,
+    /// Exit with an error instead of skipping script modes and file roots that are missing
+    #[arg(long)]
+    strict: bool,
+    /// Monitor to open on: pointer, primary, focused, an output name or an index
+    #[arg(long, value_name = "MONITOR")]
+    monitor: Option<String>,
+    /// Show the power menu (lock, logout, suspend, reboot, shutdown)
+    #[arg(long)]
+    power: bool,
+    /// List the lines printed by a command, as name<TAB>icon<TAB>exec
+    #[arg(long, value_name = "CMD", conflicts_with = "power")]
+    run: Option<String>,
+    /// Search emoji by name and copy the picked one to the clipboard
+    #[arg(long, conflicts_with_all = ["power", "run"])]
+    emoji: bool,
+    /// Pick from the lines on stdin and print the chosen one, like dmenu
+    #[arg(long, conflicts_with_all = ["power", "run", "emoji", "daemon", "show"])]
+    dmenu: bool,
+    /// Split stdin on NUL bytes instead of newlines in --dmenu mode
+    #[arg(short = '0', long, requires = "dmenu")]
+    read0: bool,
+    /// What --dmenu prints: s (the line), i (its zero-based index, -1 for
+    /// typed text) or f (the typed text)
+    #[arg(long, value_name = "FORMAT", requires = "dmenu")]
+    format: Option<modes::dmenu::Format>,
+    /// Print the index of the picked line, same as --format i
+    #[arg(long, requires = "dmenu", conflicts_with = "format")]
+    index: bool,
+    /// Print the picked item as a JSON object with its name, command and type
+    #[arg(long, requires = "menu", conflicts_with_all = ["format", "index"])]
+    json: bool,
+    /// Pick from a JSON array of items on stdin, each with a name and optionally
+    /// a command, icon and description; implies --dmenu
+    #[arg(
+        long = "json-input",
+        conflicts_with_all = ["power", "run", "emoji", "daemon", "show", "read0"]
+    )]
+    json_input: bool,
+    /// Text shown before the query, overriding `prompt` from the config
+    #[arg(short, long, value_name = "TEXT")]
+    prompt: Option<String>,
+    /// Hint shown while the query is empty, overriding `placeholder`
+    #[arg(long, value_name = "TEXT")]
+    placeholder: Option<String>,
+    /// Window width in pixels or a share of the monitor like 40%
+    #[arg(long, value_name = "SIZE")]
+    width: Option<config::Dimension>,
+    /// Window height in pixels, a percentage or auto
+    #[arg(long, value_name = "SIZE")]
+    height: Option<config::Dimension>,
+    /// Number of result rows; sizes the window to fit them unless --height is given
+    #[arg(long, value_name = "N")]
+    lines: Option<u16>,
+    #[arg(long = "font-size", value_name = "PX")]
+    font_size: Option<u16>,
+    /// Launcher modes to cycle through with Ctrl+Tab, e.g. drun,run or a script mode's name;
+    /// the first is active
+    #[arg(long, value_name = "MODES", value_delimiter = ',')]
+    modes: Vec<config::LauncherMode>,
+    /// Where the window sits: center, top, bottom-left, ...
+    #[arg(long, value_name = "POSITION")]
+    anchor: Option<config::Anchor>,
+    #[arg(long = "no-icons")]
+    no_icons: bool,
+    #[arg(long = "no-descriptions")]
+    no_descriptions: bool,
+    /// Print the configuration a run would use, after the theme and all
+    /// overrides are applied, and exit
+    #[arg(long = "dump-config")]
+    dump_config: bool,
+    /// Stay running in the background with the items loaded, hidden until `rufi --show`
+    #[arg(long, conflicts_with = "dump_config")]
+    daemon: bool,
+    /// Open the window of a running daemon, or start normally when none is running
+    #[arg(long, conflicts_with = "daemon")]
+    show: bool,
+    /// Forget the queries recalled with Ctrl+Up/Down and exit
+    #[arg(long = "clear-query-history")]
+    clear_query_history: bool,
 }
 
-fn load_or_create_config(cfg_path: Option<std::path::PathBuf>) -> Result<config::Config, error::LauncherError> {
+/// Applies the command-line settings, which win over the config file.
+fn apply_cli_overrides(cfg: &mut config::Config, args: &Args) {
+    if let Some(monitor) = &args.monitor {
+        cfg.monitor = Some(monitor.clone());
+    }
+    if let Some(prompt) = &args.prompt {
+        cfg.prompt = prompt.clone();
+    }
+    if let Some(placeholder) = &args.placeholder {
+        cfg.placeholder = placeholder.clone();
+    }
+    if let Some(lines) = args.lines {
+        cfg.max_visible_lines = lines;
+        cfg.height = config::Dimension::Auto;
+    }
+    if let Some(width) = args.width {
+        cfg.width = width;
+    }
+    if let Some(height) = args.height {
+        cfg.height = height;
+    }
+    if let Some(font_size) = args.font_size {
+        cfg.font_size = font_size;
+    }
+    if let Some(anchor) = args.anchor {
+        cfg.anchor = anchor;
+    }
+    if !args.modes.is_empty() {
+        cfg.modes = args.modes.clone();
+    }
+    if args.no_icons {
+        cfg.show_icons = false;
+    }
+    if args.no_descriptions {
+        cfg.show_descriptions = false;
+    }
+}
+
+/// The `--theme` choice and the other command-line settings, for the UI to
+/// lay over the config again each time it is reloaded.
+fn reload_overrides(args: Args) -> Box<dyn Fn(&mut config::Config)> {
+    Box::new(move |cfg| {
+        if let Some(theme_name) = &args.theme {
+            cfg.theme_name = Some(theme_name.clone());
+            if let Err(e) = cfg.resolve_theme() {
+                eprintln!("Could not apply theme {}: {}", theme_name, e);
+            }
+        }
+        apply_cli_overrides(cfg, &args);
+    })
+}
+
+fn load_or_create_config(cfg_path: Option<PathBuf>, strict: bool) -> Result<config::Config, error::LauncherError> {
     if let Some(path) = &cfg_path {
         if let Some(parent) = path.parent() {
             if !parent.exists() {
@@ -28,49 +186,344 @@ fn load_or_create_config(cfg_path: Option<std::path::PathBuf>) -> Result<config:
 
         if !path.exists() {
             let default_cfg = config::Config::default();
-            let toml_str = toml::to_string(&default_cfg)?;
+            let toml_str = default_cfg.to_toml()?;
             fs::write(path, toml_str)?;
         }
     }
 
     let mut cfg = if let Some(path) = &cfg_path {
-        config::Config::load(path.to_str().expect("Could not convert config path to string"))
+        match config::Config::load(path) {
+            Ok(cfg) => cfg,
+            Err(e) if strict => {
+                // Printed here since main's Err return would show the Debug form
+                eprintln!("error: invalid config {}", path.display());
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("warning: ignoring invalid config {}", path.display());
+                eprintln!("{}", e);
+                eprintln!("warning: using the default configuration instead (pass --strict-config to exit)");
+                let mut cfg = config::Config::default();
+                // The default theme is built in, so this can't fail
+                let _ = cfg.resolve_theme();
+                cfg
+            }
+        }
     } else {
         config::Config::default()
     };
     Ok(cfg)
 }
 
+/// Whether this run stays out of the single-instance socket. dmenu runs come
+/// from scripts, and a toggle only ever opens the running launcher, which
+/// would lose a mode asked for with --power, --run, --emoji or --modes.
+fn runs_alone(args: &Args) -> bool {
+    let own_mode = args.power || args.run.is_some() || args.emoji || !args.modes.is_empty();
+    args.dmenu || args.json_input || (own_mode && !args.daemon)
+}
+
+/// Warns about the script modes and file roots that are missing and will be
+/// skipped, or returns them as errors with `--strict`.
+fn check_sources(cfg: &config::Config, strict: bool) -> Result<(), Vec<String>> {
+    let missing = modes::missing_sources(cfg);
+    if strict && !missing.is_empty() {
+        return Err(missing);
+    }
+    for problem in &missing {
+        eprintln!("warning: {}, skipping it (pass --strict to exit)", problem);
+    }
+    Ok(())
+}
+
 fn main() -> Result<(), error::LauncherError> {
     let args = Args::parse();
 
+    if args.clear_query_history {
+        history::History::load_queries().clear()?;
+        println!("Query history cleared.");
+        return Ok(());
+    }
+
     if args.available_themes {
-        println!("Available themes:");
+        println!("Built-in themes:");
         for theme in theme::list_themes() {
             println!("- {}", theme);
         }
+        let user_themes = theme::list_user_themes();
+        if !user_themes.is_empty() {
+            if let Some(dir) = theme::user_themes_dir() {
+                println!("User themes ({}):", dir.display());
+            }
+            for theme in user_themes {
+                println!("- {}", theme);
+            }
+        }
         return Ok(());
     }
 
-    let cfg_path = dirs::config_dir().map(|p| p.join("rufi").join("rufirc.toml"));
+    let explicit_path = args.config.clone().or_else(|| {
+        std::env::var_os("RUFI_CONFIG")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    });
+    let cfg_path = if args.no_config {
+        None
+    } else if let Some(path) = explicit_path {
+        // Only the default location is created on demand, a typo here shouldn't be
+        if !path.exists() && !args.write_default_config {
+            eprintln!("error: config file {} does not exist", path.display());
+            eprintln!("Pass --write-default-config to create it with the defaults.");
+            std::process::exit(1);
+        }
+        Some(path)
+    } else {
+        dirs::config_dir().map(|p| p.join("rufi").join("rufirc.toml"))
+    };
 
-    let mut cfg = load_or_create_config(cfg_path.clone())?;
+    let mut cfg = load_or_create_config(cfg_path.clone(), args.strict_config)?;
 
-    if let Some(theme_name) = args.theme {
+    if let Some(theme_name) = args.theme.clone() {
         cfg.theme_name = Some(theme_name);
-        cfg.resolve_theme();
+        if let Err(e) = cfg.resolve_theme() {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
 
-        // Save the theme to the config file
-        if let Some(path) = &cfg_path {
-            let toml_str = toml::to_string(&cfg)?;
-            fs::write(path, toml_str)?;
-            println!("Theme '{}' saved to {}", cfg.theme_name.clone().expect("Theme name should be set if we are saving it"), path.display());
-        } else {
-            eprintln!("Could not determine config path to save theme.");
+        // Only touch the config file when asked to
+        if args.save_theme {
+            if let Some(path) = &cfg_path {
+                let theme_name = cfg.theme_name.as_deref().expect("Theme name should be set if we are saving it");
+                config::save_theme_name(path, theme_name)?;
+                println!("Theme '{}' saved to {}", theme_name, path.display());
+            } else if args.no_config {
+                eprintln!("Not saving the theme with --no-config.");
+            } else {
+                eprintln!("Could not determine config path to save theme.");
+            }
         }
         // Do not return here, continue to launch UI
     }
 
+    let mode = if args.power {
+        modes::Mode::Power(cfg.power.clone())
+    } else if let Some(command) = args.run.clone() {
+        modes::Mode::Run(command)
+    } else if args.emoji {
+        modes::Mode::Emoji
+    } else if args.dmenu || args.json_input {
+        let format = if args.index {
+            modes::dmenu::Format::Index
+        } else if args.json {
+            modes::dmenu::Format::Json
+        } else {
+            args.format.unwrap_or_default()
+        };
+        let (lines, json_items) = if args.json_input {
+            match modes::dmenu::read_json_stdin() {
+                Ok(items) => (Vec::new(), Some(items)),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            (modes::dmenu::read_stdin(args.read0), None)
+        };
+        modes::Mode::Dmenu(modes::dmenu::Dmenu {
+            lines,
+            json_items,
+            format,
+        })
+    } else {
+        modes::Mode::Launcher
+    };
+    mode.apply_prompt(&mut cfg);
+    apply_cli_overrides(&mut cfg, &args);
+
+    if args.dump_config {
+        print!("{}", cfg.to_effective_toml()?);
+        return Ok(());
+    }
+
+    if matches!(mode, modes::Mode::Launcher) {
+        if let Err(missing) = check_sources(&cfg, args.strict) {
+            for problem in missing {
+                eprintln!("error: {}", problem);
+            }
+            std::process::exit(1);
+        }
+    }
+
+    let has_var = |name| std::env::var_os(name).is_some_and(|v| !v.is_empty());
+    #[cfg(feature = "wayland")]
+    if has_var("WAYLAND_DISPLAY") {
+        if args.daemon || args.show {
+            eprintln!("error: --daemon and --show are not available on Wayland yet.");
+            std::process::exit(1);
+        }
+        let accepted = wayland::run_ui(cfg, mode)?;
+        if (args.dmenu || args.json_input) && !accepted {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    // Without the wayland feature a Wayland session needs XWayland
+    if !has_var("DISPLAY") && has_var("WAYLAND_DISPLAY") {
+        eprintln!("error: rufi needs an X11 display, but only WAYLAND_DISPLAY is set.");
+        eprintln!("Enable XWayland in your compositor so DISPLAY is available,");
+        eprintln!("or build rufi with `--features wayland`.");
+        std::process::exit(1);
+    }
+
+    if args.show && instance::send(instance::Request::Show).is_ok() {
+        return Ok(());
+    }
+
+    // A second invocation closes the running window instead of stacking on it
+    let instance = if runs_alone(&args) {
+        None
+    } else {
+        match instance::acquire() {
+            Ok(Some(lock)) => Some(lock),
+            Ok(None) if args.daemon => {
+                eprintln!("error: rufi is already running");
+                std::process::exit(1);
+            }
+            Ok(None) => {
+                if let Err(e) = instance::send(instance::Request::Toggle) {
+                    eprintln!("Could not reach the running rufi: {}", e);
+                }
+                return Ok(());
+            }
+            Err(e) if args.daemon => return Err(e),
+            Err(e) => {
+                eprintln!("Could not set up the single-instance socket: {}", e);
+                None
+            }
+        }
+    };
+
     let (conn, screen_num) = RustConnection::connect(None)?;
-    ui::run_ui(cfg, conn, screen_num)
+    let source = ui::ConfigSource {
+        path: cfg_path,
+        overrides: reload_overrides(args.clone()),
+    };
+    let accepted = ui::run_ui(cfg, source, mode, instance, args.daemon, conn, screen_num)?;
+    // dmenu scripts tell a cancelled menu from a pick by the exit status
+    if (args.dmenu || args.json_input) && !accepted {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strict_turns_missing_sources_into_errors() {
+        let cfg = config::Config {
+            modes: vec![config::LauncherMode::Files],
+            file_roots: vec!["/nonexistent/rufi-root".to_string()],
+            ..config::Config::default()
+        };
+        assert!(check_sources(&cfg, false).is_ok());
+        let missing = check_sources(&cfg, true).unwrap_err();
+        assert_eq!(missing, ["file root /nonexistent/rufi-root does not exist"]);
+        assert!(check_sources(&config::Config::default(), true).is_ok());
+    }
+
+    fn overridden(cfg: config::Config, argv: &[&str]) -> config::Config {
+        let args = Args::try_parse_from([&["rufi"], argv].concat()).unwrap();
+        let mut cfg = cfg;
+        apply_cli_overrides(&mut cfg, &args);
+        cfg
+    }
+
+    #[test]
+    fn command_line_wins_over_the_config() {
+        let cfg = config::Config {
+            prompt: "run:".to_string(),
+            font_size: 14,
+            width: config::Dimension::Pixels(600),
+            show_icons: true,
+            ..config::Config::default()
+        };
+        let argv = ["--prompt", "go>", "--font-size", "20", "--width", "40%", "--no-icons"];
+        let cfg = overridden(cfg, &argv);
+        assert_eq!(cfg.prompt, "go>");
+        assert_eq!(cfg.font_size, 20);
+        assert_eq!(cfg.width, config::Dimension::Percent(40.0));
+        assert!(!cfg.show_icons);
+    }
+
+    #[test]
+    fn config_is_kept_without_flags() {
+        let cfg = config::Config {
+            prompt: "run:".to_string(),
+            modes: vec![config::LauncherMode::Run],
+            ..config::Config::default()
+        };
+        let cfg = overridden(cfg, &[]);
+        assert_eq!(cfg.prompt, "run:");
+        assert_eq!(cfg.modes, [config::LauncherMode::Run]);
+        assert!(cfg.show_icons);
+    }
+
+    #[test]
+    fn lines_switches_the_height_to_auto() {
+        let cfg = config::Config {
+            height: config::Dimension::Pixels(500),
+            ..config::Config::default()
+        };
+        let cfg = overridden(cfg, &["--lines", "5"]);
+        assert_eq!(cfg.max_visible_lines, 5);
+        assert_eq!(cfg.height, config::Dimension::Auto);
+    }
+
+    #[test]
+    fn height_flag_wins_over_lines() {
+        let cfg = overridden(config::Config::default(), &["--lines", "5", "--height", "300"]);
+        assert_eq!(cfg.max_visible_lines, 5);
+        assert_eq!(cfg.height, config::Dimension::Pixels(300));
+    }
+
+    #[test]
+    fn modes_flag_replaces_the_configured_modes() {
+        let cfg = overridden(config::Config::default(), &["--modes", "drun,run"]);
+        assert_eq!(cfg.modes, [config::LauncherMode::Drun, config::LauncherMode::Run]);
+    }
+
+    #[test]
+    fn reload_overrides_lay_the_flags_over_a_fresh_config() {
+        let args = Args::try_parse_from(["rufi", "--lines", "4", "--prompt", "go>"]).unwrap();
+        let overrides = reload_overrides(args);
+        let mut reloaded = config::Config {
+            prompt: "run:".to_string(),
+            height: config::Dimension::Pixels(500),
+            ..config::Config::default()
+        };
+        overrides(&mut reloaded);
+        assert_eq!(reloaded.prompt, "go>");
+        assert_eq!(reloaded.max_visible_lines, 4);
+        assert_eq!(reloaded.height, config::Dimension::Auto);
+    }
+
+    #[test]
+    fn other_modes_stay_out_of_the_single_instance_socket() {
+        let alone = |argv: &[&str]| {
+            runs_alone(&Args::try_parse_from([&["rufi"], argv].concat()).unwrap())
+        };
+        assert!(!alone(&[]));
+        assert!(!alone(&["--theme", "nord"]));
+        assert!(alone(&["--dmenu"]));
+        assert!(alone(&["--power"]));
+        assert!(alone(&["--emoji"]));
+        assert!(alone(&["--run", "cat bookmarks"]));
+        assert!(alone(&["--modes", "ssh"]));
+        // The daemon itself still holds the socket, whatever it lists
+        assert!(!alone(&["--daemon", "--modes", "drun,run"]));
+    }
 }
@@ -0,0 +1,267 @@
+use crate::commands::{ItemType, LaunchItem};
+use crate::error::LauncherError;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read};
+use std::str::FromStr;
+
+/// What `--dmenu` prints on accept, named after rofi's `-format` codes.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Format {
+    /// The picked line, or the typed text when it matches none (`s`).
+    #[default]
+    String,
+    /// The zero-based input position of the picked line, or -1 (`i`).
+    Index,
+    /// The text typed in the query bar (`f`).
+    Filter,
+    /// The picked item as a JSON object, from `--json`.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<Self, String> {
+        match v.trim() {
+            "s" => Ok(Format::String),
+            "i" => Ok(Format::Index),
+            "f" => Ok(Format::Filter),
+            _ => Err(format!("unknown format \"{}\", expected s, i or f", v)),
+        }
+    }
+}
+
+/// An item as `--json-input` reads it and `--json` prints it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonItem {
+    pub name: String,
+    /// What is printed when the item is picked; the name when missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The kind of item, see `ItemType::name`. Ignored on input.
+    #[serde(rename = "type", default, skip_serializing_if = "String::is_empty")]
+    pub item_type: String,
+}
+
+impl From<&LaunchItem> for JsonItem {
+    fn from(item: &LaunchItem) -> Self {
+        Self {
+            name: item.display_name.clone(),
+            command: Some(item.command.clone()),
+            icon: item.icon.clone(),
+            description: item.description.clone(),
+            item_type: item.item_type.name().to_string(),
+        }
+    }
+}
+
+/// Lines read from stdin and how to report the pick.
+#[derive(Debug, Clone)]
+pub struct Dmenu {
+    pub lines: Vec<String>,
+    /// Items from `--json-input`, listed instead of the lines.
+    pub json_items: Option<Vec<JsonItem>>,
+    pub format: Format,
+}
+
+impl Dmenu {
+    /// What to print for the `picked` item, or for `query` when nothing
+    /// matched.
+    pub fn output(&self, picked: Option<&LaunchItem>, query: &str) -> String {
+        match (self.format, picked) {
+            (Format::String, Some(item)) => item.command.clone(),
+            (Format::String, None) | (Format::Filter, _) => query.to_string(),
+            (Format::Index, Some(item)) => item.name.clone(),
+            (Format::Index, None) => "-1".to_string(),
+            (Format::Json, Some(item)) => to_json(&JsonItem::from(item)),
+            // Typed text is reported as an item of its own kind
+            (Format::Json, None) => to_json(&JsonItem {
+                name: query.to_string(),
+                command: Some(query.to_string()),
+                icon: None,
+                description: None,
+                item_type: "query".to_string(),
+            }),
+        }
+    }
+}
+
+fn to_json(item: &JsonItem) -> String {
+    // Strings and options only, which always serialize
+    serde_json::to_string(item).unwrap_or_default()
+}
+
+/// Reads a JSON array of items from stdin for `--json-input`.
+pub fn read_json_stdin() -> Result<Vec<JsonItem>, LauncherError> {
+    let mut data = String::new();
+    io::stdin()
+        .lock()
+        .read_to_string(&mut data)
+        .map_err(|e| LauncherError::Other(format!("Failed to read stdin: {}", e)))?;
+    serde_json::from_str(&data).map_err(|e| {
+        LauncherError::Other(format!(
+            "stdin is not a JSON array of items with a \"name\": {}",
+            e
+        ))
+    })
+}
+
+/// Reads the entries to choose from from stdin, one per line, or separated
+/// by NUL bytes with `null_separated` so entries can span lines.
+pub fn read_stdin(null_separated: bool) -> Vec<String> {
+    let mut data = Vec::new();
+    if let Err(e) = io::stdin().lock().read_to_end(&mut data) {
+        eprintln!("Failed to read stdin: {}", e);
+    }
+    let separator = if null_separated { b'\0' } else { b'\n' };
+    // A trailing separator ends the last entry rather than starting one
+    if data.last() == Some(&separator) {
+        data.pop();
+    }
+    if data.is_empty() {
+        return Vec::new();
+    }
+    data.split(|&byte| byte == separator)
+        .map(|entry| {
+            let entry = String::from_utf8_lossy(entry);
+            entry.strip_suffix('\r').unwrap_or(&entry).to_string()
+        })
+        .collect()
+}
+
+/// One item per non-empty entry, in input order. The `name` holds the
+/// entry's position in the input so `--format i` can print it.
+pub fn items(dmenu: &Dmenu) -> Vec<LaunchItem> {
+    if let Some(json_items) = &dmenu.json_items {
+        return json_items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| !item.name.is_empty())
+            .map(|(index, item)| LaunchItem {
+                name: index.to_string(),
+                display_name: item.name.replace(['\n', '\t'], " "),
+                command: item.command.clone().unwrap_or_else(|| item.name.clone()),
+                description: item.description.clone(),
+                generic_name: None,
+                keywords: Vec::new(),
+                icon: item.icon.clone(),
+                working_dir: None,
+                source: None,
+                wm_class: None,
+                needs_terminal: false,
+                item_type: ItemType::Text,
+            })
+            .collect();
+    }
+    dmenu
+        .lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(index, line)| LaunchItem {
+            name: index.to_string(),
+            // Rows are a single line of text
+            display_name: line.replace(['\n', '\t'], " "),
+            command: line.clone(),
+            description: None,
+            generic_name: None,
+            keywords: Vec::new(),
+            icon: None,
+            working_dir: None,
+            source: None,
+            wm_class: None,
+            needs_terminal: false,
+            item_type: ItemType::Text,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dmenu(format: Format) -> Dmenu {
+        Dmenu {
+            lines: vec!["alpha".to_string(), "beta".to_string()],
+            json_items: None,
+            format,
+        }
+    }
+
+    #[test]
+    fn each_format_prints_the_pick_or_the_typed_text() {
+        let picked = &items(&dmenu(Format::String))[1];
+        let cases = [
+            (Format::String, Some(picked), "beta"),
+            (Format::String, None, "gamma"),
+            (Format::Index, Some(picked), "1"),
+            (Format::Index, None, "-1"),
+            (Format::Filter, Some(picked), "gamma"),
+            (Format::Filter, None, "gamma"),
+            (
+                Format::Json,
+                Some(picked),
+                r#"{"name":"beta","command":"beta","type":"text"}"#,
+            ),
+            (
+                Format::Json,
+                None,
+                r#"{"name":"gamma","command":"gamma","type":"query"}"#,
+            ),
+        ];
+        for (format, picked, expected) in cases {
+            let output = dmenu(format).output(picked, "gamma");
+            assert_eq!(
+                output,
+                expected,
+                "{:?} with {:?}",
+                format,
+                picked.map(|i| &i.name)
+            );
+        }
+    }
+
+    #[test]
+    fn index_counts_the_skipped_empty_lines() {
+        let input = Dmenu {
+            lines: vec!["alpha".to_string(), String::new(), "beta".to_string()],
+            json_items: None,
+            format: Format::Index,
+        };
+        let listed = items(&input);
+        assert_eq!(listed.len(), 2);
+        assert_eq!(input.output(Some(&listed[1]), "b"), "2");
+    }
+
+    #[test]
+    fn json_input_prints_the_command_it_was_given() {
+        let input = Dmenu {
+            lines: Vec::new(),
+            json_items: Some(vec![JsonItem {
+                name: "Docs".to_string(),
+                command: Some("xdg-open https://docs.rs".to_string()),
+                icon: Some("web-browser".to_string()),
+                description: None,
+                item_type: String::new(),
+            }]),
+            format: Format::String,
+        };
+        let listed = items(&input);
+        assert_eq!(
+            input.output(Some(&listed[0]), "do"),
+            "xdg-open https://docs.rs"
+        );
+        let json = Dmenu {
+            format: Format::Json,
+            ..input
+        };
+        assert_eq!(
+            json.output(Some(&listed[0]), "do"),
+            r#"{"name":"Docs","command":"xdg-open https://docs.rs","icon":"web-browser","type":"text"}"#
+        );
+    }
+}
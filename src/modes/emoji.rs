@@ -0,0 +1,270 @@
+use crate::commands::{ItemType, LaunchItem};
+
+/// Glyph, CLDR short name and extra search words of each bundled emoji.
+const EMOJI: &[(&str, &str, &str)] = &[
+    ("😀", "grinning face", "smile happy"),
+    ("😃", "grinning face with big eyes", "smile happy"),
+    ("😄", "grinning face with smiling eyes", "smile happy laugh"),
+    ("😁", "beaming face", "grin teeth"),
+    ("😆", "grinning squinting face", "laugh"),
+    ("😅", "grinning face with sweat", "relief nervous"),
+    ("🤣", "rolling on the floor laughing", "rofl lol"),
+    ("😂", "face with tears of joy", "lol laugh cry"),
+    ("🙂", "slightly smiling face", "smile"),
+    ("🙃", "upside-down face", "silly sarcasm"),
+    ("😉", "winking face", "wink"),
+    ("😊", "smiling face with smiling eyes", "blush happy"),
+    ("😇", "smiling face with halo", "angel innocent"),
+    ("🥰", "smiling face with hearts", "love adore"),
+    ("😍", "smiling face with heart-eyes", "love crush"),
+    ("🤩", "star-struck", "wow amazed"),
+    ("😘", "face blowing a kiss", "kiss love"),
+    ("😋", "face savoring food", "yum delicious"),
+    ("😛", "face with tongue", "tongue playful"),
+    ("😜", "winking face with tongue", "crazy joke"),
+    ("🤪", "zany face", "crazy goofy"),
+    ("🤑", "money-mouth face", "rich money"),
+    ("🤗", "hugging face", "hug"),
+    ("🤭", "face with hand over mouth", "oops giggle"),
+    ("🤫", "shushing face", "quiet secret"),
+    ("🤔", "thinking face", "hmm think"),
+    ("🤐", "zipper-mouth face", "secret quiet"),
+    ("🤨", "face with raised eyebrow", "skeptical suspicious"),
+    ("😐", "neutral face", "meh"),
+    ("😑", "expressionless face", "blank"),
+    ("😶", "face without mouth", "silent speechless"),
+    ("😏", "smirking face", "smirk smug"),
+    ("😒", "unamused face", "meh annoyed"),
+    ("🙄", "face with rolling eyes", "eyeroll whatever"),
+    ("😬", "grimacing face", "awkward eek"),
+    ("😌", "relieved face", "calm content"),
+    ("😔", "pensive face", "sad thoughtful"),
+    ("😪", "sleepy face", "tired"),
+    ("😴", "sleeping face", "sleep zzz"),
+    ("😷", "face with medical mask", "sick ill"),
+    ("🤒", "face with thermometer", "sick fever"),
+    ("🤢", "nauseated face", "sick gross"),
+    ("🤮", "face vomiting", "sick puke"),
+    ("🥵", "hot face", "heat sweat"),
+    ("🥶", "cold face", "freezing"),
+    ("😵", "dizzy face", "dizzy"),
+    ("🤯", "exploding head", "mind blown shocked"),
+    ("🥳", "partying face", "party celebrate birthday"),
+    ("😎", "smiling face with sunglasses", "cool"),
+    ("🤓", "nerd face", "geek glasses"),
+    ("😕", "confused face", "confused"),
+    ("😟", "worried face", "worried concern"),
+    ("😮", "face with open mouth", "surprised wow"),
+    ("😲", "astonished face", "shocked amazed"),
+    ("😳", "flushed face", "embarrassed blush"),
+    ("🥺", "pleading face", "puppy eyes please"),
+    ("😢", "crying face", "sad tear"),
+    ("😭", "loudly crying face", "sob sad"),
+    ("😱", "face screaming in fear", "scream scared"),
+    ("😤", "face with steam from nose", "angry triumph"),
+    ("😡", "pouting face", "angry mad rage"),
+    ("🤬", "face with symbols on mouth", "swearing cursing"),
+    ("💀", "skull", "dead death"),
+    ("💩", "pile of poo", "poop"),
+    ("🤡", "clown face", "clown"),
+    ("👻", "ghost", "halloween spooky"),
+    ("👽", "alien", "ufo space"),
+    ("🤖", "robot", "bot machine"),
+    ("🤷", "person shrugging", "shrug dunno whatever"),
+    ("🤦", "person facepalming", "facepalm"),
+    ("🙏", "folded hands", "please thanks pray"),
+    ("👍", "thumbs up", "like yes approve +1"),
+    ("👎", "thumbs down", "dislike no -1"),
+    ("👌", "ok hand", "okay perfect"),
+    ("✌️", "victory hand", "peace"),
+    ("🤞", "crossed fingers", "luck hope"),
+    ("🤘", "sign of the horns", "rock metal"),
+    ("👋", "waving hand", "wave hello bye"),
+    ("👏", "clapping hands", "clap applause"),
+    ("🙌", "raising hands", "hooray celebrate"),
+    ("💪", "flexed biceps", "strong muscle"),
+    ("👀", "eyes", "look see"),
+    ("🧠", "brain", "smart think"),
+    ("❤️", "red heart", "love"),
+    ("🧡", "orange heart", "love"),
+    ("💛", "yellow heart", "love"),
+    ("💚", "green heart", "love"),
+    ("💙", "blue heart", "love"),
+    ("💜", "purple heart", "love"),
+    ("🖤", "black heart", "love"),
+    ("💔", "broken heart", "heartbreak sad"),
+    ("💯", "hundred points", "100 perfect score"),
+    ("💥", "collision", "boom explosion"),
+    ("✨", "sparkles", "shiny new magic"),
+    ("🔥", "fire", "hot lit flame"),
+    ("⭐", "star", "favorite"),
+    ("🌟", "glowing star", "shine"),
+    ("⚡", "high voltage", "lightning zap electric"),
+    ("☀️", "sun", "sunny weather"),
+    ("🌙", "crescent moon", "night"),
+    ("☁️", "cloud", "weather"),
+    ("🌧️", "cloud with rain", "rain weather"),
+    ("❄️", "snowflake", "snow cold winter"),
+    ("🌈", "rainbow", "pride weather"),
+    ("🌊", "water wave", "ocean sea"),
+    ("🌍", "globe showing europe-africa", "earth world"),
+    ("🌸", "cherry blossom", "flower spring"),
+    ("🌹", "rose", "flower love"),
+    ("🌻", "sunflower", "flower"),
+    ("🌲", "evergreen tree", "tree forest"),
+    ("🍀", "four leaf clover", "luck"),
+    ("🍁", "maple leaf", "autumn fall canada"),
+    ("🐶", "dog face", "dog puppy pet"),
+    ("🐱", "cat face", "cat kitten pet"),
+    ("🦊", "fox", "animal"),
+    ("🐻", "bear", "animal"),
+    ("🐼", "panda", "animal"),
+    ("🐧", "penguin", "linux tux bird"),
+    ("🦀", "crab", "rust ferris"),
+    ("🐍", "snake", "python"),
+    ("🐢", "turtle", "slow"),
+    ("🐛", "bug", "insect debug"),
+    ("🦄", "unicorn", "magic"),
+    ("🐝", "honeybee", "bee insect"),
+    ("🍎", "red apple", "fruit"),
+    ("🍌", "banana", "fruit"),
+    ("🍕", "pizza", "food"),
+    ("🍔", "hamburger", "burger food"),
+    ("🌮", "taco", "food"),
+    ("🍣", "sushi", "food japanese"),
+    ("🍰", "shortcake", "cake dessert"),
+    ("🎂", "birthday cake", "cake party"),
+    ("🍩", "doughnut", "donut dessert"),
+    ("🍿", "popcorn", "movie snack"),
+    ("☕", "hot beverage", "coffee tea"),
+    ("🍺", "beer mug", "beer drink"),
+    ("🍷", "wine glass", "wine drink"),
+    ("🥂", "clinking glasses", "cheers toast"),
+    ("🎉", "party popper", "party celebrate tada"),
+    ("🎊", "confetti ball", "party celebrate"),
+    ("🎁", "wrapped gift", "present birthday"),
+    ("🎈", "balloon", "party"),
+    ("🏆", "trophy", "win award"),
+    ("🥇", "1st place medal", "gold winner"),
+    ("⚽", "soccer ball", "football sport"),
+    ("🏀", "basketball", "sport"),
+    ("🎮", "video game", "gaming controller"),
+    ("🎲", "game die", "dice random"),
+    ("🎵", "musical note", "music"),
+    ("🎸", "guitar", "music rock"),
+    ("🎧", "headphone", "music audio"),
+    ("📷", "camera", "photo"),
+    ("🎬", "clapper board", "movie film"),
+    ("📚", "books", "library read"),
+    ("📝", "memo", "note write"),
+    ("✏️", "pencil", "write edit"),
+    ("📌", "pushpin", "pin location"),
+    ("📎", "paperclip", "attach"),
+    ("📅", "calendar", "date schedule"),
+    ("📈", "chart increasing", "growth up stonks"),
+    ("📉", "chart decreasing", "down loss"),
+    ("📦", "package", "box delivery"),
+    ("📧", "e-mail", "email mail"),
+    ("📱", "mobile phone", "phone smartphone"),
+    ("💻", "laptop", "computer"),
+    ("🖥️", "desktop computer", "computer monitor"),
+    ("⌨️", "keyboard", "typing"),
+    ("🖱️", "computer mouse", "mouse"),
+    ("💾", "floppy disk", "save"),
+    ("🔋", "battery", "power"),
+    ("🔌", "electric plug", "power"),
+    ("💡", "light bulb", "idea"),
+    ("🔍", "magnifying glass tilted left", "search find"),
+    ("🔒", "locked", "lock secure"),
+    ("🔓", "unlocked", "unlock"),
+    ("🔑", "key", "password"),
+    ("🔨", "hammer", "tool build"),
+    ("🔧", "wrench", "tool fix settings"),
+    ("⚙️", "gear", "settings cog"),
+    ("🧪", "test tube", "test science"),
+    ("🔗", "link", "chain url"),
+    ("🚀", "rocket", "launch space ship"),
+    ("✈️", "airplane", "travel flight"),
+    ("🚗", "automobile", "car drive"),
+    ("🚲", "bicycle", "bike"),
+    ("🚨", "police car light", "alarm siren alert"),
+    ("🏠", "house", "home"),
+    ("⏰", "alarm clock", "time wake"),
+    ("⌛", "hourglass done", "time wait"),
+    ("💰", "money bag", "money rich"),
+    ("💸", "money with wings", "spend pay"),
+    ("🛒", "shopping cart", "shop buy"),
+    ("✅", "check mark button", "done yes ok"),
+    ("✔️", "check mark", "done yes"),
+    ("❌", "cross mark", "no wrong delete"),
+    ("❓", "red question mark", "question"),
+    ("❗", "red exclamation mark", "important warning"),
+    ("⚠️", "warning", "caution alert"),
+    ("🚫", "prohibited", "forbidden no"),
+    ("♻️", "recycling symbol", "recycle"),
+    ("➕", "plus", "add"),
+    ("➖", "minus", "subtract"),
+    ("➡️", "right arrow", "next"),
+    ("⬅️", "left arrow", "back"),
+    ("⬆️", "up arrow", "up"),
+    ("⬇️", "down arrow", "down"),
+    (
+        "🔄",
+        "counterclockwise arrows button",
+        "refresh sync reload",
+    ),
+    ("🆗", "ok button", "okay"),
+    ("🆕", "new button", "new"),
+    ("🏳️‍🌈", "rainbow flag", "pride"),
+    ("🏁", "chequered flag", "finish race"),
+    ("🚩", "triangular flag", "red flag"),
+    ("👑", "crown", "king queen"),
+    ("💎", "gem stone", "diamond"),
+    ("🎯", "direct hit", "target bullseye goal"),
+    ("🧩", "puzzle piece", "puzzle"),
+    ("🪄", "magic wand", "magic"),
+    ("🛠️", "hammer and wrench", "tools build"),
+    ("🐳", "spouting whale", "docker"),
+    ("🍻", "clinking beer mugs", "cheers"),
+    ("😈", "smiling face with horns", "devil evil"),
+    ("🙈", "see-no-evil monkey", "monkey embarrassed"),
+    ("🙉", "hear-no-evil monkey", "monkey"),
+    ("🙊", "speak-no-evil monkey", "monkey oops"),
+    ("👉", "backhand index pointing right", "point right"),
+    ("👈", "backhand index pointing left", "point left"),
+    ("👆", "backhand index pointing up", "point up"),
+    ("👇", "backhand index pointing down", "point down"),
+    ("🫡", "saluting face", "salute respect"),
+    ("🫠", "melting face", "melt hot embarrassed"),
+];
+
+/// The bundled emoji; picking one copies the glyph rather than running it.
+pub fn items() -> Vec<LaunchItem> {
+    EMOJI
+        .iter()
+        .map(|(glyph, name, keywords)| LaunchItem {
+            name: name.to_string(),
+            // The core X font can't draw the glyph, so the name comes first
+            display_name: format!("{} {}", name, glyph),
+            command: glyph.to_string(),
+            description: Some(codepoints(glyph)),
+            generic_name: None,
+            keywords: keywords.split_whitespace().map(String::from).collect(),
+            icon: None,
+            working_dir: None,
+            source: None,
+            wm_class: None,
+            needs_terminal: false,
+            item_type: ItemType::Character,
+        })
+        .collect()
+}
+
+/// `U+1F680`, space separated for sequences.
+fn codepoints(glyph: &str) -> String {
+    glyph
+        .chars()
+        .map(|c| format!("U+{:04X}", c as u32))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,160 @@
+use crate::commands::{ItemType, LaunchItem, shell_quote};
+use crate::config::Config;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files and directories under `file_roots`, shallowest first, at most
+/// `file_max_depth` levels down and `file_max_entries` in all.
+pub fn items(cfg: &Config) -> Vec<LaunchItem> {
+    let mut items = Vec::new();
+    let mut queue: VecDeque<(PathBuf, usize)> = cfg
+        .file_roots
+        .iter()
+        .map(|root| (expand_home(root), 1))
+        .collect();
+    while let Some((dir, depth)) = queue.pop_front() {
+        for path in list_dir(&dir, cfg) {
+            if items.len() >= cfg.file_max_entries {
+                return items;
+            }
+            let is_dir = path.is_dir();
+            // Symlink loops end at the depth limit too
+            if is_dir && depth < cfg.file_max_depth {
+                queue.push_back((path.clone(), depth + 1));
+            }
+            let display_name = display_path(&path, is_dir);
+            items.push(item(path, is_dir, display_name));
+        }
+    }
+    items
+}
+
+/// The configured `file_roots` that aren't directories.
+pub fn missing_roots(cfg: &Config) -> Vec<&str> {
+    cfg.file_roots
+        .iter()
+        .filter(|root| !expand_home(root).is_dir())
+        .map(String::as_str)
+        .collect()
+}
+
+/// The entries of the directory a path-like query (`~/Doc`, `/etc/`) is
+/// in, named by file name, and the part after the last `/` to match them
+/// against. `None` when the query isn't a path.
+pub fn browse<'a>(query: &'a str, cfg: &Config) -> Option<(Vec<LaunchItem>, &'a str)> {
+    if !query.starts_with('/') && !query.starts_with('~') {
+        return None;
+    }
+    let (dir, leaf) = match query.rfind('/') {
+        Some(i) => (&query[..=i], &query[i + 1..]),
+        None => (query, ""),
+    };
+    let entries = list_dir(&expand_home(dir), cfg)
+        .into_iter()
+        .filter_map(|path| {
+            let is_dir = path.is_dir();
+            let name = path.file_name()?.to_string_lossy().into_owned();
+            let display_name = if is_dir { format!("{}/", name) } else { name };
+            Some(item(path, is_dir, display_name))
+        })
+        .collect();
+    Some((entries, leaf))
+}
+
+/// Whether picking `item` should descend into it rather than open it.
+pub fn is_dir(item: &LaunchItem) -> bool {
+    item.item_type == ItemType::File && item.source.as_deref().is_some_and(Path::is_dir)
+}
+
+/// The query that lists the contents of the directory `item`.
+pub fn descend_query(item: &LaunchItem) -> Option<String> {
+    item.source.as_deref().map(|path| display_path(path, true))
+}
+
+/// Sorted entries of `dir`, leaving out the names in `file_ignore`.
+fn list_dir(dir: &Path, cfg: &Config) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            !cfg.file_ignore
+                .iter()
+                .any(|ignored| name == ignored.as_str())
+        })
+        .map(|entry| entry.path())
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn item(path: PathBuf, is_dir: bool, display_name: String) -> LaunchItem {
+    let path_text = path.to_string_lossy().into_owned();
+    LaunchItem {
+        name: format!("file:{}", path_text),
+        display_name,
+        command: format!("xdg-open {}", shell_quote(&path_text)),
+        description: None,
+        generic_name: None,
+        keywords: Vec::new(),
+        icon: Some(icon_name(&path, is_dir).to_string()),
+        working_dir: None,
+        source: Some(path),
+        wm_class: None,
+        needs_terminal: false,
+        item_type: ItemType::File,
+    }
+}
+
+/// `path` with the home directory shortened to `~`, and a trailing `/` for
+/// directories.
+fn display_path(path: &Path, is_dir: bool) -> String {
+    let home = dirs::home_dir();
+    let text = match home
+        .as_deref()
+        .and_then(|home| path.strip_prefix(home).ok())
+    {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.to_string_lossy()),
+        None => path.to_string_lossy().into_owned(),
+    };
+    if is_dir && !text.ends_with('/') {
+        format!("{}/", text)
+    } else {
+        text
+    }
+}
+
+/// `path` with a leading `~` replaced by the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    let rest = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest.trim_start_matches('/'),
+        _ => return PathBuf::from(path),
+    };
+    match dirs::home_dir() {
+        Some(home) => home.join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+/// A generic icon from the icon theme for the kind of file.
+fn icon_name(path: &Path, is_dir: bool) -> &'static str {
+    if is_dir {
+        return "folder";
+    }
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "pdf" => "application-pdf",
+        "png" | "jpg" | "jpeg" | "gif" | "svg" | "webp" | "bmp" => "image-x-generic",
+        "mp3" | "flac" | "ogg" | "opus" | "wav" | "m4a" => "audio-x-generic",
+        "mp4" | "mkv" | "webm" | "avi" | "mov" => "video-x-generic",
+        "zip" | "tar" | "gz" | "xz" | "bz2" | "zst" | "7z" | "rar" => "package-x-generic",
+        _ => "text-x-generic",
+    }
+}
@@ -0,0 +1,233 @@
+pub mod dmenu;
+pub mod emoji;
+pub mod files;
+pub mod power;
+pub mod process;
+pub mod run;
+pub mod script;
+pub mod ssh;
+pub mod window;
+
+use crate::commands::{LaunchItem, collect_applications, collect_commands};
+use crate::config::{Config, LauncherMode, PowerConfig};
+
+/// What rufi lists.
+#[derive(Debug, Clone)]
+pub enum Mode {
+    /// Desktop applications and commands on the PATH.
+    Launcher,
+    /// Session actions with the commands from the `[power]` config section.
+    Power(PowerConfig),
+    /// Items printed by a user command (`rufi --run`).
+    Run(String),
+    /// Bundled emoji, copied to the clipboard when picked.
+    Emoji,
+    /// Lines read from stdin, the picked one printed to stdout (`rufi --dmenu`).
+    Dmenu(dmenu::Dmenu),
+}
+
+impl Mode {
+    /// Replaces the launcher prompt and placeholder from the config with
+    /// ones fitting this mode.
+    pub fn apply_prompt(&self, cfg: &mut Config) {
+        let (prompt, placeholder) = match self {
+            Mode::Launcher => return,
+            Mode::Power(_) => ("power", "Lock, log out, suspend, reboot or shut down"),
+            Mode::Run(_) => ("run", "Filter items..."),
+            Mode::Emoji => ("emoji", "Search emoji by name..."),
+            // Like dmenu, no prompt unless one is given with -p
+            Mode::Dmenu(_) => ("", ""),
+        };
+        cfg.prompt = prompt.to_string();
+        cfg.placeholder = placeholder.to_string();
+    }
+
+    /// Collects the items to show, along with why any source failed. The
+    /// launcher only scans for the kinds of items its `modes` can list.
+    /// Scanning can be slow, so this runs off the UI thread.
+    pub fn collect(&self, desktops: &[String], cfg: &Config) -> (Vec<LaunchItem>, Vec<String>) {
+        let mut failures = Vec::new();
+        let items = match self {
+            Mode::Launcher => {
+                let sources = cfg.launcher_modes();
+                let wants =
+                    |mode| sources.contains(&LauncherMode::Combi) || sources.contains(&mode);
+                let mut items = Vec::new();
+                if wants(LauncherMode::Run) {
+                    items.extend(collect_commands());
+                }
+                if wants(LauncherMode::Drun) {
+                    items.extend(collect_applications(desktops));
+                }
+                if sources.contains(&LauncherMode::Window) {
+                    items.extend(window::items());
+                }
+                if sources.contains(&LauncherMode::Ssh) {
+                    items.extend(ssh::items(cfg));
+                }
+                if sources.contains(&LauncherMode::Files) {
+                    items.extend(files::items(cfg));
+                }
+                if sources.contains(&LauncherMode::Emoji) {
+                    items.extend(emoji::items());
+                }
+                if sources.contains(&LauncherMode::Kill) {
+                    items.extend(process::items());
+                }
+                if sources.contains(&LauncherMode::Power) {
+                    items.extend(power::items(&cfg.power));
+                }
+                for name in sources.iter().filter_map(|mode| match mode {
+                    LauncherMode::Script(name) => Some(name),
+                    _ => None,
+                }) {
+                    if cfg.script_mode(name).is_none() {
+                        failures.push(format!("unknown mode {}", name));
+                    }
+                }
+                if sources.contains(&LauncherMode::Files) {
+                    failures.extend(
+                        files::missing_roots(cfg)
+                            .into_iter()
+                            .map(|root| format!("file root {} does not exist", root)),
+                    );
+                }
+                // Prefixed scripts are searchable from every mode
+                for script in &cfg.script_modes {
+                    let listed = sources.contains(&LauncherMode::Script(script.name.clone()));
+                    if listed || script.prefix.is_some() {
+                        // Skipped rather than run only to fail in `sh`
+                        if let Err(e) = script::check(script) {
+                            failures.push(e);
+                            continue;
+                        }
+                        match script::items(script) {
+                            Ok(entries) => items.extend(entries),
+                            Err(e) => failures.push(e.to_string()),
+                        }
+                    }
+                }
+                items
+            }
+            Mode::Power(power) => power::items(power),
+            Mode::Run(command) => run::items(command),
+            Mode::Emoji => emoji::items(),
+            Mode::Dmenu(dmenu) => dmenu::items(dmenu),
+        };
+        (items, failures)
+    }
+}
+
+/// Describes the script modes and file roots the launcher would use but
+/// can't, which `collect` skips and reports.
+pub fn missing_sources(cfg: &Config) -> Vec<String> {
+    let sources = cfg.launcher_modes();
+    let mut missing: Vec<String> = cfg
+        .script_modes
+        .iter()
+        .filter(|s| s.prefix.is_some() || sources.contains(&LauncherMode::Script(s.name.clone())))
+        .filter_map(|s| script::check(s).err())
+        .collect();
+    if sources.contains(&LauncherMode::Files) {
+        missing.extend(
+            files::missing_roots(cfg)
+                .into_iter()
+                .map(|root| format!("file root {} does not exist", root)),
+        );
+    }
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ScriptMode;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    fn write_script(dir: &Path, name: &str, mode: u32) -> String {
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\necho {}\n", name)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn script_mode(name: &str, command: String) -> ScriptMode {
+        ScriptMode {
+            name: name.to_string(),
+            command,
+            prefix: None,
+            on_select: None,
+        }
+    }
+
+    /// Two working script modes and one whose script was moved away.
+    fn mixed_config(dir: &Path) -> Config {
+        Config {
+            script_modes: vec![
+                script_mode("first", write_script(dir, "first", 0o755)),
+                script_mode("moved", dir.join("moved").to_string_lossy().into_owned()),
+                script_mode("second", write_script(dir, "second", 0o755)),
+            ],
+            modes: ["first", "moved", "second"]
+                .iter()
+                .map(|name| LauncherMode::Script(name.to_string()))
+                .collect(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn missing_script_is_reported_and_the_others_are_not() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = missing_sources(&mixed_config(dir.path()));
+        assert_eq!(missing.len(), 1);
+        assert!(
+            missing[0].starts_with("script mode moved:"),
+            "{}",
+            missing[0]
+        );
+    }
+
+    #[test]
+    fn collect_skips_the_missing_script_and_lists_the_others() {
+        let dir = tempfile::tempdir().unwrap();
+        let (items, failures) = Mode::Launcher.collect(&[], &mixed_config(dir.path()));
+        let names: Vec<&str> = items
+            .iter()
+            .map(|item| item.display_name.as_str())
+            .collect();
+        assert_eq!(names, ["first", "second"]);
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].contains("moved"));
+    }
+
+    #[test]
+    fn script_that_is_not_executable_is_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let command = write_script(dir.path(), "plain", 0o644);
+        assert!(script::check(&script_mode("plain", command)).is_err());
+        // Shell syntax can't be checked up front
+        let command = "$HOME/bin/menu".to_string();
+        assert!(script::check(&script_mode("var", command)).is_ok());
+    }
+
+    #[test]
+    fn file_roots_count_only_for_the_files_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = Config {
+            file_roots: vec![
+                dir.path().to_string_lossy().into_owned(),
+                "/nonexistent/rufi-root".to_string(),
+            ],
+            ..Config::default()
+        };
+        assert!(missing_sources(&cfg).is_empty());
+        cfg.modes = vec![LauncherMode::Files];
+        assert_eq!(
+            missing_sources(&cfg),
+            ["file root /nonexistent/rufi-root does not exist"]
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use crate::commands::{ItemType, LaunchItem};
+use crate::config::PowerConfig;
+
+struct Action {
+    id: &'static str,
+    label: &'static str,
+    description: &'static str,
+    icon: &'static str,
+    /// Asks Yes/No before it runs.
+    destructive: bool,
+}
+
+const ACTIONS: [Action; 5] = [
+    Action {
+        id: "power-lock",
+        label: "Lock",
+        description: "Lock the screen",
+        icon: "system-lock-screen",
+        destructive: false,
+    },
+    Action {
+        id: "power-logout",
+        label: "Logout",
+        description: "End the session",
+        icon: "system-log-out",
+        destructive: true,
+    },
+    Action {
+        id: "power-suspend",
+        label: "Suspend",
+        description: "Suspend to RAM",
+        icon: "system-suspend",
+        destructive: false,
+    },
+    Action {
+        id: "power-reboot",
+        label: "Reboot",
+        description: "Restart the machine",
+        icon: "system-reboot",
+        destructive: true,
+    },
+    Action {
+        id: "power-shutdown",
+        label: "Shutdown",
+        description: "Power off the machine",
+        icon: "system-shutdown",
+        destructive: true,
+    },
+];
+
+fn command_for<'a>(power: &'a PowerConfig, id: &str) -> &'a str {
+    match id {
+        "power-lock" => &power.lock,
+        "power-logout" => &power.logout,
+        "power-suspend" => &power.suspend,
+        "power-reboot" => &power.reboot,
+        _ => &power.shutdown,
+    }
+}
+
+/// The session actions, skipping any whose command is set to "".
+pub fn items(power: &PowerConfig) -> Vec<LaunchItem> {
+    ACTIONS
+        .iter()
+        .filter(|action| !command_for(power, action.id).trim().is_empty())
+        .map(|action| LaunchItem {
+            name: action.id.to_string(),
+            display_name: action.label.to_string(),
+            command: command_for(power, action.id).to_string(),
+            description: Some(action.description.to_string()),
+            generic_name: None,
+            keywords: Vec::new(),
+            icon: Some(action.icon.to_string()),
+            working_dir: None,
+            source: None,
+            wm_class: None,
+            needs_terminal: false,
+            item_type: ItemType::Action,
+        })
+        .collect()
+}
+
+/// Name of the item backing out of a confirmation.
+const CANCEL: &str = "power-cancel";
+
+/// The Yes/No choice shown in place of the list before `action` runs. Yes
+/// is the action itself under another label, so picking it runs it.
+pub fn confirmation_items(action: &LaunchItem) -> Vec<LaunchItem> {
+    let yes = LaunchItem {
+        display_name: format!("Yes, {}", action.display_name.to_lowercase()),
+        ..action.clone()
+    };
+    let no = LaunchItem {
+        name: CANCEL.to_string(),
+        display_name: "No".to_string(),
+        command: String::new(),
+        description: Some("Back to the power menu".to_string()),
+        generic_name: None,
+        keywords: Vec::new(),
+        icon: Some("dialog-cancel".to_string()),
+        working_dir: None,
+        source: None,
+        wm_class: None,
+        needs_terminal: false,
+        item_type: ItemType::Action,
+    };
+    vec![yes, no]
+}
+
+/// Whether `item` is the No of a confirmation.
+pub fn is_cancel(item: &LaunchItem) -> bool {
+    item.item_type == ItemType::Action && item.name == CANCEL
+}
+
+/// Whether `item` is a power action that must be confirmed before it runs.
+pub fn needs_confirmation(item: &LaunchItem) -> bool {
+    item.item_type == ItemType::Action
+        && ACTIONS
+            .iter()
+            .any(|action| action.destructive && action.id == item.name)
+}
@@ -0,0 +1,111 @@
+use crate::commands::{ItemType, LaunchItem};
+use crate::error::LauncherError;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::time::Duration;
+
+/// How often the process list is read again while the kill mode is shown.
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+struct Process {
+    pid: u32,
+    name: String,
+    cmdline: String,
+    /// Resident memory in KiB.
+    rss: u64,
+}
+
+/// The current user's processes from `/proc`, biggest resident set first.
+pub fn items() -> Vec<LaunchItem> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    // SAFETY: getuid has no preconditions and cannot fail.
+    let uid = unsafe { libc::getuid() };
+    let own_pid = std::process::id();
+
+    let mut processes: Vec<Process> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+            if pid == own_pid || entry.metadata().ok()?.uid() != uid {
+                return None;
+            }
+            // Processes can exit while we read them
+            read_process(pid)
+        })
+        .collect();
+    processes.sort_by_key(|process| std::cmp::Reverse(process.rss));
+
+    processes
+        .into_iter()
+        .map(|process| LaunchItem {
+            name: format!("process:{}", process.pid),
+            display_name: format!("{} {}", process.pid, process.name),
+            command: process.name.clone(),
+            description: Some(format!("{} - {}", format_kib(process.rss), process.cmdline)),
+            generic_name: None,
+            keywords: Vec::new(),
+            icon: None,
+            working_dir: None,
+            source: None,
+            wm_class: None,
+            needs_terminal: false,
+            item_type: ItemType::Process(process.pid),
+        })
+        .collect()
+}
+
+fn read_process(pid: u32) -> Option<Process> {
+    let dir = format!("/proc/{}", pid);
+    let name = fs::read_to_string(format!("{}/comm", dir))
+        .ok()?
+        .trim_end()
+        .to_string();
+    // Arguments are NUL separated; kernel threads have none
+    let cmdline = fs::read(format!("{}/cmdline", dir)).ok()?;
+    let cmdline = String::from_utf8_lossy(&cmdline)
+        .split('\0')
+        .filter(|arg| !arg.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let cmdline = if cmdline.is_empty() {
+        format!("[{}]", name)
+    } else {
+        cmdline
+    };
+    // The second field of statm is the resident set, in pages
+    let statm = fs::read_to_string(format!("{}/statm", dir)).ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    // SAFETY: sysconf has no preconditions.
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as u64;
+    Some(Process {
+        pid,
+        name,
+        cmdline,
+        rss: pages * page_size / 1024,
+    })
+}
+
+fn format_kib(kib: u64) -> String {
+    if kib >= 1024 * 1024 {
+        format!("{:.1} GiB", kib as f64 / (1024.0 * 1024.0))
+    } else if kib >= 1024 {
+        format!("{:.1} MiB", kib as f64 / 1024.0)
+    } else {
+        format!("{} KiB", kib)
+    }
+}
+
+/// Sends SIGKILL with `force`, SIGTERM otherwise, to the process `pid`.
+pub fn kill(pid: u32, force: bool) -> Result<(), LauncherError> {
+    let signal = if force { libc::SIGKILL } else { libc::SIGTERM };
+    let pid = libc::pid_t::try_from(pid)
+        .map_err(|_| LauncherError::Other(format!("invalid pid {}", pid)))?;
+    // SAFETY: kill only takes plain integers.
+    if unsafe { libc::kill(pid, signal) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().into())
+    }
+}
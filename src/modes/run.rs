@@ -0,0 +1,54 @@
+use crate::commands::{ItemType, LaunchItem};
+use std::process::{Command, Stdio};
+
+/// Runs `command` through `sh -c` and reads one item per line of its output,
+/// as `name<TAB>icon<TAB>exec`. The icon and exec are optional; an item
+/// without an exec prints its name when picked.
+pub fn items(command: &str) -> Vec<LaunchItem> {
+    let output = match Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("Failed to run {}: {}", command, e);
+            return Vec::new();
+        }
+    };
+    if !output.status.success() {
+        eprintln!("{} exited with {}", command, output.status);
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse_line)
+        .collect()
+}
+
+fn parse_line(line: &str) -> Option<LaunchItem> {
+    let mut fields = line.trim_end_matches('\r').split('\t');
+    let name = fields.next()?.trim();
+    if name.is_empty() {
+        return None;
+    }
+    let icon = fields.next().map(str::trim).filter(|icon| !icon.is_empty());
+    let exec = fields.next().map(str::trim).unwrap_or("");
+
+    Some(LaunchItem {
+        name: name.to_string(),
+        display_name: name.to_string(),
+        command: exec.to_string(),
+        description: None,
+        generic_name: None,
+        keywords: Vec::new(),
+        icon: icon.map(String::from),
+        working_dir: None,
+        source: None,
+        wm_class: None,
+        needs_terminal: false,
+        item_type: ItemType::Command,
+    })
+}
@@ -0,0 +1,162 @@
+use crate::commands::{ItemType, LaunchItem, binary_exists, launch_item, shell_quote};
+use crate::config::{Config, ScriptMode};
+use crate::error::LauncherError;
+use crate::modes::files::expand_home;
+use std::process::{Command, Stdio};
+
+/// Why `script` can't run, when the program its command starts with is
+/// missing or not executable. Commands starting with shell syntax, like a
+/// variable, aren't checked.
+pub fn check(script: &ScriptMode) -> Result<(), String> {
+    let Some(program) = script.command.split_whitespace().next() else {
+        return Err(format!("script mode {}: the command is empty", script.name));
+    };
+    if program.contains(['=', '\'', '"', '$', '(', '`']) {
+        return Ok(());
+    }
+    let path = expand_home(program);
+    if binary_exists(&path.to_string_lossy()) {
+        Ok(())
+    } else {
+        Err(format!(
+            "script mode {}: {} is missing or not executable",
+            script.name, program
+        ))
+    }
+}
+
+/// The entries `script` lists when run without arguments.
+pub fn items(script: &ScriptMode) -> Result<Vec<LaunchItem>, LauncherError> {
+    run(script, None)
+}
+
+/// Hands the picked `item` back to its script. Returns the entries the
+/// script printed in reply, which replace the list, or `None` when it
+/// printed nothing and rufi can close.
+pub fn select(
+    script: &ScriptMode,
+    item: &LaunchItem,
+    cfg: &Config,
+) -> Result<Option<Vec<LaunchItem>>, LauncherError> {
+    let entry = &item.command;
+    if let Some(template) = &script.on_select {
+        launch_item(
+            &LaunchItem {
+                command: template.replace("{}", &shell_quote(entry)),
+                ..item.clone()
+            },
+            cfg,
+            false,
+        )?;
+        return Ok(None);
+    }
+    let entries = run(script, Some(entry))?;
+    Ok((!entries.is_empty()).then_some(entries))
+}
+
+/// Runs the script like rofi does: with `ROFI_RETV` 0 to list, or 1 and
+/// the picked entry as `$1`.
+fn run(script: &ScriptMode, entry: Option<&str>) -> Result<Vec<LaunchItem>, LauncherError> {
+    let mut command = Command::new("sh");
+    match entry {
+        Some(entry) => command
+            .arg("-c")
+            .arg(format!("{} \"$@\"", script.command))
+            .arg("sh")
+            .arg(entry)
+            .env("ROFI_RETV", "1"),
+        None => command.arg("-c").arg(&script.command).env("ROFI_RETV", "0"),
+    };
+    let output = command
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| LauncherError::Other(format!("{}: {}", script.name, e)))?;
+    if !output.status.success() {
+        return Err(LauncherError::Other(format!(
+            "{} exited with {}",
+            script.name, output.status
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| parse_line(&script.name, line))
+        .collect())
+}
+
+/// Reads an entry in rofi's format, `text\0icon\x1fname`, with further
+/// `key\x1fvalue` pairs after the icon. Lines starting with `\0` set
+/// options of rofi's own and are skipped.
+fn parse_line(mode: &str, line: &str) -> Option<LaunchItem> {
+    let mut parts = line.trim_end_matches('\r').splitn(2, '\0');
+    let text = parts.next()?;
+    if text.trim().is_empty() {
+        return None;
+    }
+    let mut icon = None;
+    let mut keywords = Vec::new();
+    let mut properties = parts.next().unwrap_or("").split('\x1f');
+    while let (Some(key), Some(value)) = (properties.next(), properties.next()) {
+        match key {
+            "icon" if !value.is_empty() => icon = Some(value.to_string()),
+            "meta" => keywords.extend(value.split_whitespace().map(String::from)),
+            _ => {}
+        }
+    }
+
+    Some(LaunchItem {
+        name: format!("{}:{}", mode, text),
+        display_name: text.to_string(),
+        command: text.to_string(),
+        description: None,
+        generic_name: None,
+        keywords,
+        icon,
+        working_dir: None,
+        source: None,
+        wm_class: None,
+        needs_terminal: false,
+        item_type: ItemType::Script(mode.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_lines_are_entries_of_their_mode() {
+        let item = parse_line("notes", "Buy milk\r").unwrap();
+        assert_eq!(item.name, "notes:Buy milk");
+        assert_eq!(item.display_name, "Buy milk");
+        assert_eq!(item.command, "Buy milk");
+        assert_eq!(item.icon, None);
+        assert_eq!(item.item_type, ItemType::Script("notes".to_string()));
+        assert!(parse_line("notes", "   ").is_none());
+    }
+
+    #[test]
+    fn row_options_set_the_icon_and_keywords() {
+        let item = parse_line("apps", "Editor\0icon\x1fgedit\x1fmeta\x1ftext notes").unwrap();
+        assert_eq!(item.display_name, "Editor");
+        assert_eq!(item.icon.as_deref(), Some("gedit"));
+        assert_eq!(item.keywords, ["text", "notes"]);
+    }
+
+    #[test]
+    fn malformed_options_are_ignored() {
+        // A key without a value, an empty icon and an unknown key
+        for line in [
+            "Editor\0icon",
+            "Editor\0icon\x1f",
+            "Editor\0nonsense\x1fvalue",
+        ] {
+            let item = parse_line("apps", line).unwrap();
+            assert_eq!(item.display_name, "Editor", "{:?}", line);
+            assert_eq!(item.icon, None, "{:?}", line);
+        }
+        // Lines of rofi's own options have no text
+        assert!(parse_line("apps", "\0prompt\x1fPick").is_none());
+    }
+}
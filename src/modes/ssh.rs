@@ -0,0 +1,260 @@
+use crate::commands::{ItemType, LaunchItem};
+use crate::config::Config;
+use std::fs;
+use std::path::Path;
+
+/// A host to connect to and what its config block says about it.
+struct Host {
+    name: String,
+    port: Option<String>,
+    hostname: Option<String>,
+    user: Option<String>,
+}
+
+/// Hosts from `~/.ssh/config`, plus those in `~/.ssh/known_hosts` with
+/// `ssh_known_hosts`, each opening `ssh` in the configured terminal.
+pub fn items(cfg: &Config) -> Vec<LaunchItem> {
+    let Some(ssh_dir) = dirs::home_dir().map(|home| home.join(".ssh")) else {
+        return Vec::new();
+    };
+
+    let mut hosts = read_file(&ssh_dir.join("config"))
+        .map(|text| parse_config(&text))
+        .unwrap_or_default();
+    if cfg.ssh_known_hosts {
+        let known = read_file(&ssh_dir.join("known_hosts"))
+            .map(|text| parse_known_hosts(&text))
+            .unwrap_or_default();
+        for host in known {
+            if !hosts.iter().any(|h| h.name == host.name) {
+                hosts.push(host);
+            }
+        }
+    }
+
+    hosts
+        .into_iter()
+        .map(|host| {
+            let ssh = match &host.port {
+                Some(port) => format!("ssh -p {} {}", port, host.name),
+                None => format!("ssh {}", host.name),
+            };
+            let description = match (&host.user, &host.hostname) {
+                (Some(user), Some(hostname)) => Some(format!("{}@{}", user, hostname)),
+                (Some(user), None) => Some(format!("{}@{}", user, host.name)),
+                (None, Some(hostname)) => Some(hostname.clone()),
+                (None, None) => None,
+            };
+            LaunchItem {
+                name: format!("ssh:{}", host.name),
+                display_name: host.name,
+                command: cfg.in_terminal(&ssh),
+                description,
+                generic_name: None,
+                keywords: Vec::new(),
+                icon: None,
+                working_dir: None,
+                source: None,
+                wm_class: None,
+                needs_terminal: false,
+                item_type: ItemType::Host,
+            }
+        })
+        .collect()
+}
+
+fn read_file(path: &Path) -> Option<String> {
+    match fs::read_to_string(path) {
+        Ok(text) => Some(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Host names end up in a shell command, so anything unusual is skipped.
+fn is_plain_host(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | ':'))
+}
+
+/// The `Host` entries of an ssh config without wildcard patterns, with the
+/// `HostName` and `User` of their block.
+fn parse_config(text: &str) -> Vec<Host> {
+    let mut hosts: Vec<Host> = Vec::new();
+    // Hosts named by the `Host` line of the block being read
+    let mut block_start = hosts.len();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.split_once(|c: char| c.is_whitespace() || c == '=') {
+            Some((key, value)) => (key, value.trim_start_matches([' ', '\t', '=']).trim()),
+            None => (line, ""),
+        };
+        match key.to_ascii_lowercase().as_str() {
+            "host" => {
+                block_start = hosts.len();
+                for name in value.split_whitespace() {
+                    if is_plain_host(name) && !hosts.iter().any(|h| h.name == name) {
+                        hosts.push(Host {
+                            name: name.to_string(),
+                            port: None,
+                            hostname: None,
+                            user: None,
+                        });
+                    }
+                }
+            }
+            // Settings in a Match block don't belong to the Host before it
+            "match" => block_start = hosts.len(),
+            "hostname" => {
+                for host in &mut hosts[block_start..] {
+                    host.hostname.get_or_insert_with(|| value.to_string());
+                }
+            }
+            "user" => {
+                for host in &mut hosts[block_start..] {
+                    host.user.get_or_insert_with(|| value.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    hosts
+}
+
+/// Hosts of a known_hosts file. Hashed entries can't be read back and are
+/// skipped, as are revoked keys.
+fn parse_known_hosts(text: &str) -> Vec<Host> {
+    let mut hosts: Vec<Host> = Vec::new();
+    for line in text.lines() {
+        let mut fields = line.split_whitespace();
+        let Some(mut patterns) = fields.next() else {
+            continue;
+        };
+        if patterns.starts_with('#') || patterns.starts_with("|1|") || patterns == "@revoked" {
+            continue;
+        }
+        if patterns == "@cert-authority" {
+            let Some(next) = fields.next() else {
+                continue;
+            };
+            patterns = next;
+        }
+        for pattern in patterns.split(',') {
+            // Non-default ports are written as [host]:port
+            let (name, port) = match pattern.strip_prefix('[').and_then(|p| p.split_once("]:")) {
+                Some((name, port)) => (name, Some(port.to_string())),
+                None => (pattern, None),
+            };
+            let valid_port = port
+                .as_ref()
+                .is_none_or(|port| port.chars().all(|c| c.is_ascii_digit()));
+            if valid_port && is_plain_host(name) && !hosts.iter().any(|h| h.name == name) {
+                hosts.push(Host {
+                    name: name.to_string(),
+                    port,
+                    hostname: None,
+                    user: None,
+                });
+            }
+        }
+    }
+    hosts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Name, port, HostName and User of a host.
+    type Summary<'a> = (&'a str, Option<&'a str>, Option<&'a str>, Option<&'a str>);
+
+    fn summary(hosts: &[Host]) -> Vec<Summary<'_>> {
+        hosts
+            .iter()
+            .map(|host| {
+                (
+                    host.name.as_str(),
+                    host.port.as_deref(),
+                    host.hostname.as_deref(),
+                    host.user.as_deref(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_pattern_of_a_host_line_is_listed_with_its_block() {
+        let hosts = parse_config(
+            "Host web web-backup\n  HostName 10.0.0.5\n  User deploy\n\n\
+             Host db\n    hostname=db.internal\n",
+        );
+        assert_eq!(
+            summary(&hosts),
+            [
+                ("web", None, Some("10.0.0.5"), Some("deploy")),
+                ("web-backup", None, Some("10.0.0.5"), Some("deploy")),
+                ("db", None, Some("db.internal"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn wildcard_hosts_are_skipped() {
+        let hosts = parse_config("Host *\n  User everyone\nHost *.lan dev?\nHost box !bad\n");
+        assert_eq!(summary(&hosts), [("box", None, None, None)]);
+    }
+
+    #[test]
+    fn settings_only_apply_after_their_host_line() {
+        let hosts = parse_config(
+            "User nobody\n\
+             Host first\n  User alice\n  User ignored\n\
+             Match host second\n  User matched\n\
+             Host second\n  HostName second.example\n",
+        );
+        assert_eq!(
+            summary(&hosts),
+            [
+                // The first value wins, as in ssh
+                ("first", None, None, Some("alice")),
+                ("second", None, Some("second.example"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn hashed_and_revoked_known_hosts_are_skipped() {
+        let hosts = parse_known_hosts(
+            "|1|c2FsdA==|aGFzaA== ssh-ed25519 AAAA\n\
+             # a comment\n\
+             @revoked old.example ssh-rsa AAAA\n\
+             plain.example,10.0.0.9 ssh-ed25519 AAAA\n\
+             @cert-authority *.corp ssh-rsa AAAA\n",
+        );
+        assert_eq!(
+            summary(&hosts),
+            [
+                ("plain.example", None, None, None),
+                ("10.0.0.9", None, None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn bracketed_known_hosts_keep_their_port() {
+        let hosts = parse_known_hosts(
+            "[git.example]:2222 ssh-ed25519 AAAA\n\
+             [odd.example]:22x ssh-ed25519 AAAA\n\
+             git.example ssh-ed25519 AAAA\n",
+        );
+        assert_eq!(summary(&hosts), [("git.example", Some("2222"), None, None)]);
+    }
+}
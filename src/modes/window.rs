@@ -0,0 +1,205 @@
+use crate::commands::{ItemType, LaunchItem};
+use crate::error::LauncherError;
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{AtomEnum, ClientMessageEvent, ConnectionExt as _, EventMask, Window},
+    rust_connection::RustConnection,
+};
+
+x11rb::atom_manager! {
+    /// Atoms of the EWMH properties the window switcher reads.
+    Atoms: AtomsCookie {
+        UTF8_STRING,
+        _NET_CLIENT_LIST,
+        _NET_WM_NAME,
+        _NET_WM_DESKTOP,
+        _NET_ACTIVE_WINDOW,
+    }
+}
+
+/// Longest title or class read, in 4-byte units.
+const MAX_PROPERTY_LEN: u32 = 1024;
+
+/// `_NET_WM_DESKTOP` of windows shown on every desktop.
+const ALL_DESKTOPS: u32 = 0xFFFF_FFFF;
+
+/// Open windows from the window manager's `_NET_CLIENT_LIST`, titled by
+/// their name, with the class and desktop as the description.
+pub fn items() -> Vec<LaunchItem> {
+    match collect_windows() {
+        Ok(items) => items,
+        Err(e) => {
+            eprintln!("Failed to list windows: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+fn collect_windows() -> Result<Vec<LaunchItem>, LauncherError> {
+    // A connection of its own, since this runs off the UI thread
+    let (conn, screen_num) = RustConnection::connect(None)?;
+    let atoms = Atoms::new(&conn)?.reply()?;
+
+    Ok(client_list(&conn, screen_num, &atoms)?
+        .into_iter()
+        // Windows can close while we look at them
+        .filter_map(|window| window_item(&conn, &atoms, window).ok().flatten())
+        .collect())
+}
+
+/// The first open window whose WM_CLASS class or instance is one of
+/// `classes`, ignoring case, to raise instead of starting the application
+/// again.
+pub fn find_by_class(classes: &[&str]) -> Result<Option<Window>, LauncherError> {
+    let (conn, screen_num) = RustConnection::connect(None)?;
+    let atoms = Atoms::new(&conn)?.reply()?;
+
+    for window in client_list(&conn, screen_num, &atoms)? {
+        let Ok((instance, class)) = read_class(&conn, window) else {
+            continue;
+        };
+        let matches = |name: &str| {
+            !name.is_empty()
+                && classes
+                    .iter()
+                    .any(|wanted| wanted.eq_ignore_ascii_case(name))
+        };
+        if matches(&class) || matches(&instance) {
+            return Ok(Some(window));
+        }
+    }
+    Ok(None)
+}
+
+/// The managed windows from the root's `_NET_CLIENT_LIST`.
+fn client_list(
+    conn: &RustConnection,
+    screen_num: usize,
+    atoms: &Atoms,
+) -> Result<Vec<Window>, LauncherError> {
+    let root = conn.setup().roots[screen_num].root;
+    let clients = conn
+        .get_property(
+            false,
+            root,
+            atoms._NET_CLIENT_LIST,
+            AtomEnum::WINDOW,
+            0,
+            u32::MAX,
+        )?
+        .reply()?;
+    let Some(windows) = clients.value32() else {
+        eprintln!("The window manager doesn't publish _NET_CLIENT_LIST");
+        return Ok(Vec::new());
+    };
+    Ok(windows.collect())
+}
+
+/// The instance and class names of `window`'s WM_CLASS.
+fn read_class(conn: &RustConnection, window: Window) -> Result<(String, String), LauncherError> {
+    let wm_class = conn
+        .get_property(
+            false,
+            window,
+            AtomEnum::WM_CLASS,
+            AtomEnum::STRING,
+            0,
+            MAX_PROPERTY_LEN,
+        )?
+        .reply()?
+        .value;
+    // WM_CLASS is "instance\0class\0"
+    let mut parts = wm_class
+        .split(|&byte| byte == 0)
+        .map(|part| String::from_utf8_lossy(part).into_owned());
+    let instance = parts.next().unwrap_or_default();
+    let class = parts.next().unwrap_or_default();
+    Ok((instance, class))
+}
+
+fn window_item(
+    conn: &RustConnection,
+    atoms: &Atoms,
+    window: Window,
+) -> Result<Option<LaunchItem>, LauncherError> {
+    let read = |property, type_| -> Result<Vec<u8>, LauncherError> {
+        Ok(conn
+            .get_property(false, window, property, type_, 0, MAX_PROPERTY_LEN)?
+            .reply()?
+            .value)
+    };
+
+    let (instance, class) = read_class(conn, window)?;
+    if class == "rufi" {
+        return Ok(None);
+    }
+
+    let mut title =
+        String::from_utf8_lossy(&read(atoms._NET_WM_NAME, atoms.UTF8_STRING)?).into_owned();
+    if title.is_empty() {
+        title = String::from_utf8_lossy(&read(AtomEnum::WM_NAME.into(), AtomEnum::STRING.into())?)
+            .into_owned();
+    }
+    if title.is_empty() {
+        title = class.clone();
+    }
+
+    let desktop = conn
+        .get_property(
+            false,
+            window,
+            atoms._NET_WM_DESKTOP,
+            AtomEnum::CARDINAL,
+            0,
+            1,
+        )?
+        .reply()?
+        .value32()
+        .and_then(|mut values| values.next());
+    let description = match desktop {
+        Some(ALL_DESKTOPS) => format!("{} - all desktops", class),
+        Some(desktop) => format!("{} - desktop {}", class, desktop + 1),
+        None => class.clone(),
+    };
+
+    Ok(Some(LaunchItem {
+        name: format!("window:{:#x}", window),
+        display_name: title,
+        // Searched like an exec, so typing the class finds its windows
+        command: class.clone(),
+        description: Some(description),
+        generic_name: None,
+        keywords: Vec::new(),
+        // The instance name is usually also the application's icon name
+        icon: Some(instance.to_lowercase()).filter(|icon| !icon.is_empty()),
+        working_dir: None,
+        source: None,
+        wm_class: Some(class),
+        needs_terminal: false,
+        item_type: ItemType::Window(window),
+    }))
+}
+
+/// Asks the window manager to switch to `window`, changing desktops if
+/// needed, and focus it.
+pub fn activate(window: Window) -> Result<(), LauncherError> {
+    let (conn, screen_num) = RustConnection::connect(None)?;
+    let root = conn.setup().roots[screen_num].root;
+    let atoms = Atoms::new(&conn)?.reply()?;
+
+    // Source indication 2: a pager or similar tool acting for the user
+    let event = ClientMessageEvent::new(
+        32,
+        window,
+        atoms._NET_ACTIVE_WINDOW,
+        [2, x11rb::CURRENT_TIME, 0, 0, 0],
+    );
+    conn.send_event(
+        false,
+        root,
+        EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+        event,
+    )?;
+    conn.flush()?;
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use crate::error::LauncherError;
+use x11rb::{
+    connection::RequestConnection,
+    protocol::{randr, randr::ConnectionExt as _, xproto::*},
+    rust_connection::RustConnection,
+};
+
+#[derive(Debug, Clone)]
+pub struct Monitor {
+    pub name: String,
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+    pub primary: bool,
+}
+
+impl Monitor {
+    fn contains(&self, x: i16, y: i16) -> bool {
+        let (x, y) = (x as i32, y as i32);
+        x >= self.x as i32
+            && x < self.x as i32 + self.width as i32
+            && y >= self.y as i32
+            && y < self.y as i32 + self.height as i32
+    }
+}
+
+/// Enumerates the active CRTCs through RandR. Returns an empty list when the
+/// extension is unavailable.
+pub fn list_monitors(conn: &RustConnection, root: Window) -> Result<Vec<Monitor>, LauncherError> {
+    if conn
+        .extension_information(randr::X11_EXTENSION_NAME)?
+        .is_none()
+    {
+        return Ok(Vec::new());
+    }
+
+    let resources = conn.randr_get_screen_resources_current(root)?.reply()?;
+    let primary = conn.randr_get_output_primary(root)?.reply()?.output;
+
+    let mut monitors = Vec::new();
+    for crtc in &resources.crtcs {
+        let info = conn
+            .randr_get_crtc_info(*crtc, resources.config_timestamp)?
+            .reply()?;
+        if info.width == 0 || info.height == 0 || info.outputs.is_empty() {
+            continue;
+        }
+
+        let output = info.outputs[0];
+        let name = conn
+            .randr_get_output_info(output, resources.config_timestamp)?
+            .reply()
+            .map(|o| String::from_utf8_lossy(&o.name).into_owned())
+            .unwrap_or_default();
+
+        monitors.push(Monitor {
+            name,
+            x: info.x,
+            y: info.y,
+            width: info.width,
+            height: info.height,
+            primary: info.outputs.contains(&primary),
+        });
+    }
+
+    Ok(monitors)
+}
+
+/// Reads `Xft.dpi` from the root window's RESOURCE_MANAGER property, which
+/// desktop environments set for HiDPI screens.
+pub fn xft_dpi(conn: &RustConnection, root: Window) -> Option<f32> {
+    let reply = conn
+        .get_property(
+            false,
+            root,
+            AtomEnum::RESOURCE_MANAGER,
+            AtomEnum::STRING,
+            0,
+            u32::MAX,
+        )
+        .ok()?
+        .reply()
+        .ok()?;
+    String::from_utf8_lossy(&reply.value)
+        .lines()
+        .find_map(|line| line.strip_prefix("Xft.dpi:"))
+        .and_then(|dpi| dpi.trim().parse::<f32>().ok())
+        .filter(|dpi| *dpi > 0.0)
+}
+
+fn pointer_position(conn: &RustConnection, root: Window) -> Result<(i16, i16), LauncherError> {
+    let pointer = conn.query_pointer(root)?.reply()?;
+    Ok((pointer.root_x, pointer.root_y))
+}
+
+fn focused_window_center(conn: &RustConnection, root: Window) -> Result<(i16, i16), LauncherError> {
+    let focus = conn.get_input_focus()?.reply()?.focus;
+    if focus == x11rb::NONE || focus == root || focus == u32::from(InputFocus::POINTER_ROOT) {
+        return pointer_position(conn, root);
+    }
+    let geometry = conn.get_geometry(focus)?.reply()?;
+    let origin = conn.translate_coordinates(focus, root, 0, 0)?.reply()?;
+    Ok((
+        origin.dst_x + (geometry.width / 2) as i16,
+        origin.dst_y + (geometry.height / 2) as i16,
+    ))
+}
+
+/// Picks the monitor to place the window on. `selector` is `pointer`
+/// (default), `primary`, `focused`, an output name like `HDMI-1`, or a
+/// zero-based index. Falls back to the whole screen when RandR reports
+/// nothing usable.
+pub fn select_monitor(conn: &RustConnection, screen: &Screen, selector: Option<&str>) -> Monitor {
+    let whole_screen = Monitor {
+        name: String::new(),
+        x: 0,
+        y: 0,
+        width: screen.width_in_pixels,
+        height: screen.height_in_pixels,
+        primary: true,
+    };
+
+    let monitors = match list_monitors(conn, screen.root) {
+        Ok(monitors) if !monitors.is_empty() => monitors,
+        Ok(_) => return whole_screen,
+        Err(e) => {
+            eprintln!("RandR query failed, using the whole screen: {}", e);
+            return whole_screen;
+        }
+    };
+
+    let at = |pos: Result<(i16, i16), LauncherError>| {
+        pos.ok()
+            .and_then(|(x, y)| monitors.iter().find(|m| m.contains(x, y)).cloned())
+    };
+
+    let chosen = match selector.unwrap_or("pointer") {
+        "pointer" => at(pointer_position(conn, screen.root)),
+        "focused" => at(focused_window_center(conn, screen.root)),
+        "primary" => monitors.iter().find(|m| m.primary).cloned(),
+        other => match other.parse::<usize>() {
+            Ok(index) => monitors.get(index).cloned(),
+            Err(_) => monitors.iter().find(|m| m.name == other).cloned(),
+        },
+    };
+
+    chosen
+        .or_else(|| monitors.iter().find(|m| m.primary).cloned())
+        .unwrap_or_else(|| monitors[0].clone())
+}
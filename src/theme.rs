@@ -1,4 +1,56 @@
 use crate::config::ConfigTheme;
+use crate::error::LauncherError;
+use std::{fs, io, path::PathBuf};
+
+/// Where user themes live, one `<name>.toml` holding the `[theme]` colors
+/// per theme.
+pub fn user_themes_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|p| p.join("rufi").join("themes"))
+}
+
+/// Looks `name` up among the built-in themes, then in the user themes
+/// directory. A user theme that can't be read or parsed is an error naming
+/// its file.
+pub fn find_theme(name: &str) -> Result<Option<ConfigTheme>, LauncherError> {
+    if let Some(theme) = get_theme(name) {
+        return Ok(Some(theme));
+    }
+    let Some(path) = user_theme_path(name) else {
+        return Ok(None);
+    };
+    let data = match fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(LauncherError::Other(format!("{}: {}", path.display(), e))),
+    };
+    toml::from_str(&data)
+        .map(Some)
+        .map_err(|e| LauncherError::Other(format!("invalid theme {}: {}", path.display(), e)))
+}
+
+/// File a user theme called `name` would be read from. `None` for built-in
+/// themes and names that aren't plain file names.
+pub fn user_theme_path(name: &str) -> Option<PathBuf> {
+    if get_theme(name).is_some() || name.contains('/') {
+        return None;
+    }
+    user_themes_dir().map(|dir| dir.join(format!("{}.toml", name)))
+}
+
+/// Names of the themes in the user themes directory, sorted.
+pub fn list_user_themes() -> Vec<String> {
+    let mut names: Vec<String> = user_themes_dir()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .filter_map(|path| path.file_stem()?.to_str().map(String::from))
+        .collect();
+    names.sort();
+    names
+}
 
 pub fn get_theme(name: &str) -> Option<ConfigTheme> {
     match name {
@@ -10,6 +62,10 @@ pub fn get_theme(name: &str) -> Option<ConfigTheme> {
             border_color: 0x6c7086,
             query_bg: 0x313244,
             accent_color: 0xf38ba8,
+            separator_color: None,
+            row_alt_bg: None,
+            badge_app_color: None,
+            badge_cmd_color: None,
         }),
         "catppuccin-latte" => Some(ConfigTheme {
             bg_color: 0xeff1f5,
@@ -19,6 +75,10 @@ pub fn get_theme(name: &str) -> Option<ConfigTheme> {
             border_color: 0xacb0be,
             query_bg: 0xccd0da,
             accent_color: 0xd20f39,
+            separator_color: None,
+            row_alt_bg: None,
+            badge_app_color: None,
+            badge_cmd_color: None,
         }),
         "nord-dark" => Some(ConfigTheme {
             bg_color: 0x2E3440,
@@ -28,6 +88,10 @@ pub fn get_theme(name: &str) -> Option<ConfigTheme> {
             border_color: 0x4C566A,
             query_bg: 0x3B4252,
             accent_color: 0x8FBCBB,
+            separator_color: None,
+            row_alt_bg: None,
+            badge_app_color: None,
+            badge_cmd_color: None,
         }),
         "nord-light" => Some(ConfigTheme {
             bg_color: 0xECEFF4,
@@ -37,6 +101,10 @@ pub fn get_theme(name: &str) -> Option<ConfigTheme> {
             border_color: 0xD8DEE9,
             query_bg: 0xE5E9F0,
             accent_color: 0x81A1C1,
+            separator_color: None,
+            row_alt_bg: None,
+            badge_app_color: None,
+            badge_cmd_color: None,
         }),
         "dracula" => Some(ConfigTheme {
             bg_color: 0x282a36,
@@ -46,6 +114,10 @@ pub fn get_theme(name: &str) -> Option<ConfigTheme> {
             border_color: 0x44475a,
             query_bg: 0x44475a,
             accent_color: 0xff79c6,
+            separator_color: None,
+            row_alt_bg: None,
+            badge_app_color: None,
+            badge_cmd_color: None,
         }),
         "tokyonight-dark" => Some(ConfigTheme {
             bg_color: 0x1a1b26,
@@ -55,6 +127,10 @@ pub fn get_theme(name: &str) -> Option<ConfigTheme> {
             border_color: 0x414868,
             query_bg: 0x24283b,
             accent_color: 0xbb9af7,
+            separator_color: None,
+            row_alt_bg: None,
+            badge_app_color: None,
+            badge_cmd_color: None,
         }),
         "tokyonight-light" => Some(ConfigTheme {
             bg_color: 0xd5d6db,
@@ -64,6 +140,10 @@ pub fn get_theme(name: &str) -> Option<ConfigTheme> {
             border_color: 0x9699a3,
             query_bg: 0xc8c9ce,
             accent_color: 0x8c73cc,
+            separator_color: None,
+            row_alt_bg: None,
+            badge_app_color: None,
+            badge_cmd_color: None,
         }),
         "gruvbox-dark" => Some(ConfigTheme {
             bg_color: 0x282828,
@@ -73,6 +153,10 @@ pub fn get_theme(name: &str) -> Option<ConfigTheme> {
             border_color: 0x504945,
             query_bg: 0x3c3836,
             accent_color: 0xfe8019,
+            separator_color: None,
+            row_alt_bg: None,
+            badge_app_color: None,
+            badge_cmd_color: None,
         }),
         "gruvbox-light" => Some(ConfigTheme {
             bg_color: 0xfbf1c7,
@@ -82,6 +166,10 @@ pub fn get_theme(name: &str) -> Option<ConfigTheme> {
             border_color: 0xbdae93,
             query_bg: 0xebdbb2,
             accent_color: 0xd65d0e,
+            separator_color: None,
+            row_alt_bg: None,
+            badge_app_color: None,
+            badge_cmd_color: None,
         }),
         _ => None,
     }
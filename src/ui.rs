@@ -1,138 +1,230 @@
 use crate::{
-    commands::{ItemCache, collect_applications, collect_commands, launch_item},
-    config::Config,
+    calc,
+    commands::{
+        ItemCache, ItemType, LaunchItem, command_summary, copy_to_clipboard, current_desktops,
+        is_path_command, launch_item,
+    },
+    config::{Anchor, Config, ConfigTheme, CopyField, DescriptionWrap, LauncherMode, Layout},
     error::LauncherError,
     fuzzy,
+    history::History,
+    icons::{IconLoader, IconState},
+    instance::{InstanceLock, Request},
+    modes::{Mode, files, power, process, script, window},
+    monitor::{Monitor, select_monitor, xft_dpi},
+    theme, web_search,
 };
-use image::ImageReader;
-use resvg::tiny_skia::Pixmap;
-use resvg::tiny_skia::Transform;
-use resvg::usvg;
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex},
+    fs,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
     thread,
+    time::{Duration, Instant, SystemTime},
 };
 use x11rb::{
     COPY_FROM_PARENT,
-    connection::Connection,
+    connection::{Connection, RequestConnection},
     protocol::{Event, xproto::*},
     rust_connection::RustConnection,
+    wrapper::ConnectionExt as _,
 };
 
-fn find_icon(icon_name: &str) -> Option<String> {
-    if icon_name.contains('/') {
-        if std::path::Path::new(icon_name).exists() {
-            return Some(icon_name.to_string());
-        }
+/// How the screen stores a pixel: the root visual's channel masks, and the
+/// size and row padding the server uses for images of its depth.
+struct PixelFormat {
+    red_mask: u32,
+    green_mask: u32,
+    blue_mask: u32,
+    depth: u8,
+    bits_per_pixel: u8,
+    scanline_pad: u8,
+    big_endian: bool,
+}
+
+/// Set once the window's screen is known; plain 24-bit TrueColor until then.
+static PIXEL_FORMAT: OnceLock<PixelFormat> = OnceLock::new();
+
+fn pixel_format() -> &'static PixelFormat {
+    PIXEL_FORMAT.get_or_init(|| PixelFormat {
+        red_mask: 0xFF0000,
+        green_mask: 0x00FF00,
+        blue_mask: 0x0000FF,
+        depth: 24,
+        bits_per_pixel: 32,
+        scanline_pad: 32,
+        big_endian: false,
+    })
+}
+
+/// Reads the pixel format of screen `screen_num`. Colors are drawn without
+/// a colormap, so only TrueColor visuals with 16, 24 or 32 bits per pixel
+/// are supported; others get an error rather than garbled output.
+fn init_pixel_format(setup: &Setup, screen_num: usize) -> Result<(), LauncherError> {
+    let screen = &setup.roots[screen_num];
+    let visual = screen
+        .allowed_depths
+        .iter()
+        .flat_map(|depth| &depth.visuals)
+        .find(|visual| visual.visual_id == screen.root_visual)
+        .ok_or_else(|| LauncherError::Other("the root visual is not listed".to_string()))?;
+    if visual.class != VisualClass::TRUE_COLOR {
+        return Err(LauncherError::Other(format!(
+            "unsupported {:?} visual of depth {}; rufi needs a TrueColor display",
+            visual.class, screen.root_depth
+        )));
+    }
+    let image_format = setup
+        .pixmap_formats
+        .iter()
+        .find(|format| format.depth == screen.root_depth)
+        .ok_or_else(|| {
+            LauncherError::Other(format!("no image format for depth {}", screen.root_depth))
+        })?;
+    if !matches!(image_format.bits_per_pixel, 16 | 24 | 32) {
+        return Err(LauncherError::Other(format!(
+            "unsupported depth {} with {} bits per pixel",
+            screen.root_depth, image_format.bits_per_pixel
+        )));
     }
+    let format = PixelFormat {
+        red_mask: visual.red_mask,
+        green_mask: visual.green_mask,
+        blue_mask: visual.blue_mask,
+        depth: screen.root_depth,
+        bits_per_pixel: image_format.bits_per_pixel,
+        scanline_pad: image_format.scanline_pad,
+        big_endian: setup.image_byte_order == ImageOrder::MSB_FIRST,
+    };
+    // Only the first screen asked for counts, like the window
+    let _ = PIXEL_FORMAT.set(format);
+    Ok(())
+}
 
-    let home_dir = std::env::var("HOME").unwrap_or_default();
-    let icon_themes = [
-        format!("{}/.local/share/icons", home_dir),
-        "/usr/share/icons/hicolor".to_string(),
-        "/usr/share/pixmaps".to_string(),
-    ];
+/// Packs one 8-bit channel into the bits selected by `mask`.
+fn pack_channel(value: u8, mask: u32) -> u32 {
+    if mask == 0 {
+        return 0;
+    }
+    let shift = mask.trailing_zeros();
+    let bits = (mask >> shift).count_ones().min(8);
+    ((value as u32) >> (8 - bits)) << shift
+}
 
-    let sizes = [
-        "256x256", "128x128", "64x64", "48x48", "32x32", "16x16", "scalable",
-    ];
-    let exts = [".png", ".svg"];
+/// The screen's pixel value for a `0xrrggbb` color from the theme.
+fn pixel(color: u32) -> u32 {
+    let format = pixel_format();
+    let [_, r, g, b] = color.to_be_bytes();
+    pack_channel(r, format.red_mask)
+        | pack_channel(g, format.green_mask)
+        | pack_channel(b, format.blue_mask)
+}
 
-    for theme in &icon_themes {
-        for size in &sizes {
-            for ext in &exts {
-                let path = format!("{}/{}/apps/{}{}", theme, size, icon_name, ext);
-                if std::path::Path::new(&path).exists() {
-                    return Some(path);
-                }
-                let path = format!("{}/{}/devices/{}{}", theme, size, icon_name, ext);
-                if std::path::Path::new(&path).exists() {
-                    return Some(path);
-                }
-            }
-        }
+/// Blends one straight-alpha channel over the background's.
+fn blend_channel(value: u8, background: u8, alpha: u8) -> u8 {
+    let (value, background, alpha) = (value as u32, background as u32, alpha as u32);
+    ((value * alpha + background * (255 - alpha) + 127) / 255) as u8
+}
 
-        for ext in &exts {
-            let path = format!("{}/{}{}", theme, icon_name, ext);
-            if std::path::Path::new(&path).exists() {
-                return Some(path);
-            }
-        }
+/// Converts RGBA rows of `width` pixels to Z_PIXMAP data laid out for the
+/// server in `format`: translucent pixels blended over the `0xrrggbb`
+/// `background`, channels placed by the visual's masks, pixels as wide as
+/// its depth needs, bytes in its image byte order and rows padded to its
+/// scanline unit. Returns the data and the length of one row.
+/// Without this, red and blue come out swapped on little-endian servers.
+fn rgba_to_zpixmap(
+    rgba: &[u8],
+    width: usize,
+    background: u32,
+    format: &PixelFormat,
+) -> (Vec<u8>, usize) {
+    let [_, bg_r, bg_g, bg_b] = background.to_be_bytes();
+    let pixel_bytes = format.bits_per_pixel as usize / 8;
+    let pad_bits = (format.scanline_pad as usize).max(8);
+    let bytes_per_line = (width * format.bits_per_pixel as usize).div_ceil(pad_bits) * pad_bits / 8;
+    if width == 0 {
+        return (Vec::new(), 0);
     }
 
-    None
+    let mut out = Vec::with_capacity(rgba.len() / 4 / width * bytes_per_line);
+    for row in rgba.chunks_exact(width * 4) {
+        for px in row.chunks_exact(4) {
+            let alpha = px[3];
+            let value = pack_channel(blend_channel(px[0], bg_r, alpha), format.red_mask)
+                | pack_channel(blend_channel(px[1], bg_g, alpha), format.green_mask)
+                | pack_channel(blend_channel(px[2], bg_b, alpha), format.blue_mask);
+            let bytes = if format.big_endian {
+                &value.to_be_bytes()[4 - pixel_bytes..]
+            } else {
+                &value.to_le_bytes()[..pixel_bytes]
+            };
+            out.extend_from_slice(bytes);
+        }
+        out.resize(out.len() + bytes_per_line - width * pixel_bytes, 0);
+    }
+    (out, bytes_per_line)
 }
 
+/// Draws a `size`x`size` RGBA icon at `x`,`y` over the `background` color.
 fn draw_icon(
-    conn: &RustConnection,
-    window: Window,
+    frame: &Frame,
     x: i16,
     y: i16,
     size: u16,
-    icon_name: &str,
+    img_data: &[u8],
+    background: u32,
 ) -> Result<(), LauncherError> {
-    if let Some(icon_path) = find_icon(icon_name) {
-        let img_data = if icon_path.ends_with(".svg") {
-            let mut fontdb = usvg::fontdb::Database::new();
-            fontdb.load_system_fonts();
-            let svg_data = std::fs::read(&icon_path).map_err(|e| LauncherError::Io(e))?;
-            let mut options = usvg::Options::default();
-            options.default_size = usvg::Size::from_wh(size as f32, size as f32).unwrap();
-            let tree = usvg::Tree::from_data(&svg_data, &options, &fontdb).map_err(|e| {
-                LauncherError::Io(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    e.to_string(),
-                ))
-            })?;
-
-            let mut pixmap = Pixmap::new(size as u32, size as u32).unwrap();
-            resvg::render(&tree, Transform::default(), &mut pixmap.as_mut());
-            pixmap.data().to_vec()
-        } else {
-            let img = ImageReader::open(&icon_path)
-                .map_err(|e| LauncherError::Io(e))?
-                .decode()
-                .map_err(|e| {
-                    LauncherError::Io(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        e.to_string(),
-                    ))
-                })?;
-            let img = img.thumbnail(size as u32, size as u32).to_rgba8();
-            img.into_raw()
-        };
-
-        let gc = conn.generate_id()?;
-        conn.create_gc(gc, window, &CreateGCAux::new().foreground(0))?;
+    let Frame { conn, win, gc, .. } = *frame;
+    let (pixels, bytes_per_line) =
+        rgba_to_zpixmap(img_data, size as usize, background, pixel_format());
+    if bytes_per_line == 0 {
+        return Ok(());
+    }
 
+    // Large icons can exceed the maximum request length, so send them as
+    // strips of whole rows that each fit in one request
+    let rows_per_strip = strip_rows(conn.maximum_request_bytes(), bytes_per_line);
+    for (strip, data) in pixels.chunks(rows_per_strip * bytes_per_line).enumerate() {
+        let rows = (data.len() / bytes_per_line) as u16;
         conn.put_image(
             ImageFormat::Z_PIXMAP,
-            window,
+            win,
             gc,
-            size as u16,
-            size as u16,
+            size,
+            rows,
             x,
-            y,
+            y + (strip * rows_per_strip) as i16,
             0,
-            conn.setup().roots[0].root_depth,
-            &img_data,
+            pixel_format().depth,
+            data,
         )?;
     }
     Ok(())
 }
 
+/// Number of image rows of `bytes_per_line` that fit in one PutImage request
+/// of at most `max_request_bytes`, never less than one.
+fn strip_rows(max_request_bytes: usize, bytes_per_line: usize) -> usize {
+    const PUT_IMAGE_HEADER: usize = 24;
+    (max_request_bytes.saturating_sub(PUT_IMAGE_HEADER) / bytes_per_line.max(1)).max(1)
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn draw_rect(
     conn: &RustConnection,
     window: Window,
+    gc: Gcontext,
     x: i16,
     y: i16,
     width: u16,
     height: u16,
     color: u32,
 ) -> Result<(), LauncherError> {
-    let gc = conn.generate_id()?;
-    conn.create_gc(gc, window, &CreateGCAux::new().foreground(color))?;
+    conn.change_gc(gc, &ChangeGCAux::new().foreground(pixel(color)))?;
     conn.poly_fill_rectangle(
         window,
         gc,
@@ -143,30 +235,160 @@ pub fn draw_rect(
             height,
         }],
     )?;
-    conn.free_gc(gc)?;
     Ok(())
 }
 
+/// Strokes a `line_width` px outline just inside the given rectangle.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_outline(
+    conn: &RustConnection,
+    window: Window,
+    gc: Gcontext,
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+    line_width: u16,
+    color: u32,
+) -> Result<(), LauncherError> {
+    if line_width == 0 || width <= line_width || height <= line_width {
+        return Ok(());
+    }
+    conn.change_gc(
+        gc,
+        &ChangeGCAux::new()
+            .foreground(pixel(color))
+            .line_width(line_width as u32),
+    )?;
+    // X centers wide lines on the path, so inset it by half the width
+    let inset = (line_width / 2) as i16;
+    conn.poly_rectangle(
+        window,
+        gc,
+        &[Rectangle {
+            x: x + inset,
+            y: y + inset,
+            width: width - line_width,
+            height: height - line_width,
+        }],
+    )?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn draw_text(
     conn: &RustConnection,
     window: Window,
+    gc: Gcontext,
     x: i16,
     y: i16,
     text: &str,
     fg_color: u32,
     bg_color: u32,
 ) -> Result<(), LauncherError> {
-    let gc = conn.generate_id()?;
-    conn.create_gc(
+    conn.change_gc(
         gc,
-        window,
-        &CreateGCAux::new().foreground(fg_color).background(bg_color),
+        &ChangeGCAux::new()
+            .foreground(pixel(fg_color))
+            .background(pixel(bg_color)),
     )?;
-    conn.image_text8(window, gc, x, y, text.as_bytes())?;
-    conn.free_gc(gc)?;
+    let bytes: Vec<u8> = text.chars().map(latin1_byte).collect();
+    conn.image_text8(window, gc, x, y, &bytes)?;
     Ok(())
 }
 
+/// The core-font byte for `c`. Core fonts are Latin-1: dashes and curly
+/// quotes, as in action names like "Firefox — New Window", fall back to
+/// their ASCII look-alikes and anything else beyond Latin-1 is drawn as '?'.
+fn latin1_byte(c: char) -> u8 {
+    match c {
+        '\u{2013}' | '\u{2014}' => b'-',
+        '\u{2018}' | '\u{2019}' => b'\'',
+        '\u{201C}' | '\u{201D}' => b'"',
+        _ => u8::try_from(u32::from(c)).unwrap_or(b'?'),
+    }
+}
+
+/// Metrics of the core font the GCs draw with. It is monospaced, so widths
+/// are just a multiple of `char_width`.
+#[derive(Debug, Clone, Copy)]
+struct FontMetrics {
+    char_width: u16,
+    ascent: u16,
+    descent: u16,
+}
+
+impl FontMetrics {
+    fn text_width(&self, text: &str) -> u16 {
+        (text.chars().count() as u32 * self.char_width as u32).min(u16::MAX as u32) as u16
+    }
+}
+
+fn query_font_metrics(conn: &RustConnection, window: Window) -> Result<FontMetrics, LauncherError> {
+    let gc = conn.generate_id()?;
+    conn.create_gc(gc, window, &CreateGCAux::new())?;
+    let reply = conn.query_font(gc)?.reply();
+    conn.free_gc(gc)?;
+
+    Ok(match reply {
+        Ok(font) => FontMetrics {
+            char_width: font.max_bounds.character_width.max(1) as u16,
+            ascent: font.font_ascent.max(0) as u16,
+            descent: font.font_descent.max(0) as u16,
+        },
+        Err(_) => FontMetrics {
+            char_width: 6,
+            ascent: 10,
+            descent: 3,
+        },
+    })
+}
+
+/// Opens the bold variant of the core font the GCs draw with, found by
+/// swapping the weight in its XLFD name. Returns `None` when the server has
+/// no such face, or one that is wider and would break the layout.
+fn open_bold_font(
+    conn: &RustConnection,
+    window: Window,
+    regular: FontMetrics,
+) -> Result<Option<Font>, LauncherError> {
+    let gc = conn.generate_id()?;
+    conn.create_gc(gc, window, &CreateGCAux::new())?;
+    let reply = conn.query_font(gc)?.reply();
+    conn.free_gc(gc)?;
+    let Ok(reply) = reply else {
+        return Ok(None);
+    };
+    let Some(name_prop) = reply
+        .properties
+        .iter()
+        .find(|prop| prop.name == u32::from(AtomEnum::FONT))
+    else {
+        return Ok(None);
+    };
+    let name = conn.get_atom_name(name_prop.value)?.reply()?.name;
+    let name = String::from_utf8_lossy(&name);
+
+    // -foundry-family-weight-slant-...
+    let mut fields: Vec<&str> = name.split('-').collect();
+    if fields.len() < 4 {
+        return Ok(None);
+    }
+    fields[3] = "bold";
+    let bold_name = fields.join("-");
+
+    let font = conn.generate_id()?;
+    if conn.open_font(font, bold_name.as_bytes())?.check().is_err() {
+        return Ok(None);
+    }
+    let bold = conn.query_font(font)?.reply()?;
+    if bold.max_bounds.character_width.max(1) as u16 != regular.char_width {
+        conn.close_font(font)?;
+        return Ok(None);
+    }
+    Ok(Some(font))
+}
+
 const KEYCODE_A: u8 = 38;
 const KEYCODE_0: u8 = 10;
 const KEYCODE_SPACE: u8 = 65;
@@ -176,9 +398,9 @@ const KEYCODE_COMMA: u8 = 51;
 const KEYCODE_DOT: u8 = 52;
 const KEYCODE_SLASH: u8 = 53;
 
-pub fn setup_keyboard_map(
-    conn: &RustConnection,
-) -> Result<HashMap<u8, Vec<String>>, LauncherError> {
+/// Keysyms of every keycode, one per column of the core keyboard mapping
+/// (0 for NoSymbol).
+pub fn setup_keyboard_map(conn: &RustConnection) -> Result<HashMap<u8, Vec<u32>>, LauncherError> {
     let mut map = HashMap::new();
 
     let min_keycode = conn.setup().min_keycode;
@@ -188,25 +410,15 @@ pub fn setup_keyboard_map(
         conn.get_keyboard_mapping(min_keycode, (max_keycode - min_keycode + 1) as u8)?;
 
     if let Ok(keyboard_mapping) = keyboard_mapping_cookie.reply() {
+        let syms_per_keycode = keyboard_mapping.keysyms_per_keycode as usize;
         for keycode in min_keycode..=max_keycode {
-            let index = (keycode - min_keycode) as usize;
-            let syms_per_keycode = keyboard_mapping.keysyms_per_keycode as usize;
-
-            if index * syms_per_keycode < keyboard_mapping.keysyms.len() {
-                let mut variations = Vec::new();
-
-                for i in 0..syms_per_keycode {
-                    let sym_index = index * syms_per_keycode + i;
-                    if sym_index < keyboard_mapping.keysyms.len() {
-                        let keysym = keyboard_mapping.keysyms[sym_index];
-                        if let Some(char) = keysym_to_char(keysym) {
-                            variations.push(char);
-                        }
-                    }
-                }
-
-                if !variations.is_empty() {
-                    map.insert(keycode, variations);
+            let start = (keycode - min_keycode) as usize * syms_per_keycode;
+            if let Some(syms) = keyboard_mapping
+                .keysyms
+                .get(start..start + syms_per_keycode)
+            {
+                if syms.iter().any(|&sym| sym != 0) {
+                    map.insert(keycode, syms.to_vec());
                 }
             }
         }
@@ -214,28 +426,24 @@ pub fn setup_keyboard_map(
 
     // Fallback mapping if X11 lookup fails
     if map.is_empty() {
+        let sym = |c: u8| c as u32;
         // Basic ASCII mapping
         for i in 0..26 {
-            let keycode = KEYCODE_A + i;
-            let lower = ((b'a' + i) as char).to_string();
-            let upper = ((b'A' + i) as char).to_string();
-            map.insert(keycode, vec![lower, upper]);
+            map.insert(KEYCODE_A + i, vec![sym(b'a' + i), sym(b'A' + i)]);
         }
 
         // Numbers
         for i in 0..10 {
-            let keycode = KEYCODE_0 + i;
-            let num = ((b'0' + i) as char).to_string();
-            map.insert(keycode, vec![num.clone(), num]);
+            map.insert(KEYCODE_0 + i, vec![sym(b'0' + i), sym(b'0' + i)]);
         }
 
         // Common symbols
-        map.insert(KEYCODE_SPACE, vec![" ".to_string()]); // Space
-        map.insert(KEYCODE_MINUS, vec!["-".to_string(), "_".to_string()]);
-        map.insert(KEYCODE_EQUAL, vec!["=".to_string(), "+".to_string()]);
-        map.insert(KEYCODE_COMMA, vec![",".to_string(), "<".to_string()]);
-        map.insert(KEYCODE_DOT, vec![".".to_string(), ">".to_string()]);
-        map.insert(KEYCODE_SLASH, vec!["/".to_string(), "?".to_string()]);
+        map.insert(KEYCODE_SPACE, vec![sym(b' ')]);
+        map.insert(KEYCODE_MINUS, vec![sym(b'-'), sym(b'_')]);
+        map.insert(KEYCODE_EQUAL, vec![sym(b'='), sym(b'+')]);
+        map.insert(KEYCODE_COMMA, vec![sym(b','), sym(b'<')]);
+        map.insert(KEYCODE_DOT, vec![sym(b'.'), sym(b'>')]);
+        map.insert(KEYCODE_SLASH, vec![sym(b'/'), sym(b'?')]);
     }
 
     Ok(map)
@@ -243,417 +451,3355 @@ pub fn setup_keyboard_map(
 
 const KEYSYM_ASCII_START: u32 = 0x0020;
 const KEYSYM_ASCII_END: u32 = 0x007E;
-const KEYSYM_BACKSPACE: u32 = 0xFF08;
+const KEYSYM_LATIN1_START: u32 = 0x00A0;
+const KEYSYM_LATIN1_END: u32 = 0x00FF;
+const KEYSYM_EURO: u32 = 0x20AC;
+/// Keysyms for any Unicode character are the code point plus this offset.
+const KEYSYM_UNICODE_OFFSET: u32 = 0x0100_0000;
 const KEYSYM_TAB: u32 = 0xFF09;
-const KEYSYM_ENTER: u32 = 0xFF0D;
-const KEYSYM_ESCAPE: u32 = 0xFF1B;
-const KEYSYM_ARROW_START: u32 = 0xFF51;
-const KEYSYM_ARROW_END: u32 = 0xFF58;
+const KEYSYM_C: u32 = 0x0063;
+/// AltGr on most layouts, or Mode_switch on older ones.
+const KEYSYM_ISO_LEVEL3_SHIFT: u32 = 0xFE03;
+const KEYSYM_MODE_SWITCH: u32 = 0xFF7E;
+const KEYSYM_KP_START: u32 = 0xFF80;
+const KEYSYM_KP_END: u32 = 0xFFBD;
+const KEYSYM_KP_0: u32 = 0xFFB0;
+const KEYSYM_KP_9: u32 = 0xFFB9;
+
+/// Whether the keysym belongs to the numeric keypad.
+fn is_keypad(keysym: u32) -> bool {
+    (KEYSYM_KP_START..=KEYSYM_KP_END).contains(&keysym)
+}
 
-fn keysym_to_char(keysym: u32) -> Option<String> {
+/// Text typed by a keypad keysym; navigation ones like KP_Home type nothing.
+fn keypad_to_char(keysym: u32) -> Option<char> {
     match keysym {
-        KEYSYM_ASCII_START..=KEYSYM_ASCII_END => Some((keysym as u8 as char).to_string()), // ASCII printable
-        KEYSYM_BACKSPACE => None,                      // Backspace
-        KEYSYM_TAB => Some("\t".to_string()),          // Tab
-        KEYSYM_ENTER => None,                          // Enter
-        KEYSYM_ESCAPE => None,                         // Escape
-        KEYSYM_ARROW_START..=KEYSYM_ARROW_END => None, // Arrow keys, etc.
+        KEYSYM_KP_0..=KEYSYM_KP_9 => char::from_digit(keysym - KEYSYM_KP_0, 10),
+        0xFF80 => Some(' '), // KP_Space
+        0xFFAA => Some('*'), // KP_Multiply
+        0xFFAB => Some('+'), // KP_Add
+        0xFFAC => Some(','), // KP_Separator
+        0xFFAD => Some('-'), // KP_Subtract
+        0xFFAE => Some('.'), // KP_Decimal
+        0xFFAF => Some('/'), // KP_Divide
+        0xFFBD => Some('='), // KP_Equal
         _ => None,
     }
 }
 
-pub fn run_ui(cfg: Config, conn: RustConnection, screen_num: usize) -> Result<(), LauncherError> {
-    let screen = &conn.setup().roots[screen_num];
-    let win = conn.generate_id()?;
+fn keysym_to_char(keysym: u32) -> Option<char> {
+    match keysym {
+        KEYSYM_ASCII_START..=KEYSYM_ASCII_END | KEYSYM_LATIN1_START..=KEYSYM_LATIN1_END => {
+            char::from_u32(keysym)
+        }
+        KEYSYM_EURO => Some('\u{20ac}'),
+        KEYSYM_TAB => Some('\t'),
+        _ if is_keypad(keysym) => keypad_to_char(keysym),
+        _ if keysym > KEYSYM_UNICODE_OFFSET => char::from_u32(keysym - KEYSYM_UNICODE_OFFSET),
+        // Backspace, Enter, Escape, arrows and other function keys
+        _ => None,
+    }
+}
 
-    // Center window on screen
-    let x = (screen.width_in_pixels.saturating_sub(cfg.width)) / 2;
-    let y = (screen.height_in_pixels.saturating_sub(cfg.height)) / 3;
+/// Whether the keysym switches to the third shift level, like AltGr.
+fn is_level3_shift(keysym: u32) -> bool {
+    matches!(keysym, KEYSYM_ISO_LEVEL3_SHIFT | KEYSYM_MODE_SWITCH)
+}
 
-    conn.create_window(
-        COPY_FROM_PARENT as u8,
-        win,
-        screen.root,
-        x as i16,
-        y as i16,
-        cfg.width,
-        cfg.height,
-        cfg.border_width,
-        WindowClass::INPUT_OUTPUT,
-        COPY_FROM_PARENT,
-        &CreateWindowAux::new()
-            .background_pixel(cfg.theme.bg_color)
-            .border_pixel(cfg.theme.border_color)
-            .event_mask(
-                EventMask::EXPOSURE
-                    | EventMask::KEY_PRESS
-                    | EventMask::KEY_RELEASE
-                    | EventMask::BUTTON_PRESS
-                    | EventMask::STRUCTURE_NOTIFY
-                    | EventMask::FOCUS_CHANGE,
-            ),
-    )?;
+/// The keysym `syms` produce with Shift and AltGr held as given. The core
+/// mapping of an XKB keyboard has group 1 in the first two columns, group 2
+/// in the next two and the third and fourth levels of group 1 after those.
+/// Keypad keys follow NumLock instead, which Shift inverts.
+fn keysym_for_level(syms: &[u32], shift: bool, level3: bool, num_lock: bool) -> Option<u32> {
+    let column = |i: usize| syms.get(i).copied().filter(|&sym| sym != 0);
+    if column(1).is_some_and(is_keypad) {
+        return if num_lock != shift {
+            column(1)
+        } else {
+            column(0)
+        };
+    }
+    let (lower, upper) = if level3 && (column(4).is_some() || column(5).is_some()) {
+        (column(4), column(5))
+    } else {
+        (column(0), column(1))
+    };
+    if !shift {
+        return lower;
+    }
+    // A letter with a single keysym gets its case from Shift
+    upper.or_else(|| {
+        let lower = lower?;
+        let upper = keysym_to_char(lower)?.to_uppercase().next()?;
+        Some(match u32::from(upper) {
+            code @ KEYSYM_ASCII_START..=KEYSYM_LATIN1_END => code,
+            code => code + KEYSYM_UNICODE_OFFSET,
+        })
+    })
+}
+
+/// Dead keys with the accent they type on their own, followed by the
+/// letters they compose with as base/result pairs.
+const DEAD_KEYS: [(u32, char, &str); 6] = [
+    (0xFE50, '`', "aàeèiìoòuùAÀEÈIÌOÒUÙ"),     // dead_grave
+    (0xFE51, '´', "aáeéiíoóuúyýAÁEÉIÍOÓUÚYÝ"), // dead_acute
+    (0xFE52, '^', "aâeêiîoôuûAÂEÊIÎOÔUÛ"),     // dead_circumflex
+    (0xFE53, '~', "aãnñoõAÃNÑOÕ"),             // dead_tilde
+    (0xFE57, '¨', "aäeëiïoöuüyÿAÄEËIÏOÖUÜ"),   // dead_diaeresis
+    (0xFE5B, '¸', "cçCÇ"),                     // dead_cedilla
+];
+
+fn is_dead_key(keysym: u32) -> bool {
+    DEAD_KEYS.iter().any(|(dead, _, _)| *dead == keysym)
+}
 
-    conn.change_window_attributes(win, &ChangeWindowAttributesAux::new().override_redirect(1))?;
+/// Text typed by `ch` after the dead key `dead`: the composed letter, the
+/// accent alone after a space, or both when they don't combine.
+fn compose_dead_key(dead: u32, ch: char) -> String {
+    let Some((_, accent, pairs)) = DEAD_KEYS.iter().find(|(sym, _, _)| *sym == dead) else {
+        return ch.to_string();
+    };
+    if ch == ' ' {
+        return accent.to_string();
+    }
+    let pairs: Vec<char> = pairs.chars().collect();
+    match pairs.chunks(2).find(|pair| pair[0] == ch) {
+        Some(pair) => pair[1].to_string(),
+        None => format!("{}{}", accent, ch),
+    }
+}
 
-    conn.map_window(win)?;
-    conn.flush()?;
+/// Removes the word before the end of `query` along with any whitespace
+/// after it.
+pub fn delete_word_backward(query: &mut String) {
+    let trimmed = query.trim_end_matches(char::is_whitespace);
+    let word_start = trimmed
+        .rfind(char::is_whitespace)
+        .map(|i| i + trimmed[i..].chars().next().map_or(1, char::len_utf8))
+        .unwrap_or(0);
+    query.truncate(word_start);
+}
 
-    std::thread::sleep(std::time::Duration::from_millis(100));
+x11rb::atom_manager! {
+    /// Atoms for the window properties, interned together at startup.
+    Atoms: AtomsCookie {
+        UTF8_STRING,
+        WM_PROTOCOLS,
+        WM_DELETE_WINDOW,
+        _NET_WM_NAME,
+        _NET_WM_PID,
+        _NET_WM_WINDOW_TYPE,
+        _NET_WM_WINDOW_TYPE_DIALOG,
+        _NET_WM_STATE,
+        _NET_WM_STATE_ABOVE,
+        _NET_WM_WINDOW_OPACITY,
+    }
+}
 
-    let grab_cookie = conn.grab_keyboard(
-        true, // owner_events
+/// Names `win` and types it as a dialog of class `rufi` so WM and compositor
+/// rules can match it. A managed window also asks to stay on top and to be
+/// given focus.
+fn set_window_properties(
+    conn: &RustConnection,
+    win: Window,
+    atoms: &Atoms,
+    managed: bool,
+) -> Result<(), LauncherError> {
+    conn.change_property8(
+        PropMode::REPLACE,
+        win,
+        AtomEnum::WM_NAME,
+        AtomEnum::STRING,
+        b"rufi",
+    )?;
+    conn.change_property8(
+        PropMode::REPLACE,
+        win,
+        atoms._NET_WM_NAME,
+        atoms.UTF8_STRING,
+        b"rufi",
+    )?;
+    // Instance and class name, each NUL-terminated
+    conn.change_property8(
+        PropMode::REPLACE,
+        win,
+        AtomEnum::WM_CLASS,
+        AtomEnum::STRING,
+        b"rufi\0rufi\0",
+    )?;
+    conn.change_property32(
+        PropMode::REPLACE,
         win,
-        x11rb::CURRENT_TIME,
-        GrabMode::ASYNC,
-        GrabMode::ASYNC,
+        atoms._NET_WM_WINDOW_TYPE,
+        AtomEnum::ATOM,
+        &[atoms._NET_WM_WINDOW_TYPE_DIALOG],
     )?;
-    if grab_cookie.reply()?.status != GrabStatus::SUCCESS {
-        return Err(LauncherError::Other("Could not grab keyboard".into()));
+    conn.change_property32(
+        PropMode::REPLACE,
+        win,
+        atoms._NET_WM_PID,
+        AtomEnum::CARDINAL,
+        &[std::process::id()],
+    )?;
+    conn.change_property32(
+        PropMode::REPLACE,
+        win,
+        atoms.WM_PROTOCOLS,
+        AtomEnum::ATOM,
+        &[atoms.WM_DELETE_WINDOW],
+    )?;
+
+    if managed {
+        conn.change_property32(
+            PropMode::REPLACE,
+            win,
+            atoms._NET_WM_STATE,
+            AtomEnum::ATOM,
+            &[atoms._NET_WM_STATE_ABOVE],
+        )?;
+
+        // WM_HINTS with only the InputHint flag set and input = True
+        const INPUT_HINT: u32 = 1;
+        let mut hints = [0u32; 9];
+        hints[0] = INPUT_HINT;
+        hints[1] = 1;
+        conn.change_property32(
+            PropMode::REPLACE,
+            win,
+            AtomEnum::WM_HINTS,
+            AtomEnum::WM_HINTS,
+            &hints,
+        )?;
+    }
+    Ok(())
+}
+
+/// Ungrabs the keyboard (if grabbed) and destroys the window when dropped,
+/// so errors propagated with `?` don't leave the session's keyboard stuck.
+struct Cleanup<'a> {
+    conn: &'a RustConnection,
+    win: Window,
+    grabbed: bool,
+}
+
+impl Drop for Cleanup<'_> {
+    fn drop(&mut self) {
+        if self.grabbed {
+            let _ = self.conn.ungrab_keyboard(x11rb::CURRENT_TIME);
+        }
+        let _ = self.conn.destroy_window(self.win);
+        let _ = self.conn.flush();
     }
+}
 
-    conn.set_input_focus(InputFocus::POINTER_ROOT, win, 0u32)?;
+/// Maps the window and, unless the WM manages it, takes the keyboard.
+fn show_window(cleanup: &mut Cleanup, cfg: &Config) -> Result<(), LauncherError> {
+    let conn = cleanup.conn;
+    conn.map_window(cleanup.win)?;
     conn.flush()?;
 
-    let cache = Arc::new(Mutex::new(ItemCache::new(cfg.cache_timeout)));
-    let mut loading = true;
+    // A managed window gets focus from the WM through WM_HINTS
+    if !cfg.managed_window {
+        grab_keyboard(conn, cleanup.win, cfg.grab_attempts)?;
+        cleanup.grabbed = true;
 
-    // Start initial load asynchronously to prevent blocking
-    let initial_cache = cache.clone();
-    thread::spawn(move || {
-        let mut all_items = Vec::new();
-        all_items.extend(collect_commands());
-        all_items.extend(collect_applications());
-        if let Ok(mut cache_guard) = initial_cache.lock() {
-            cache_guard.update(all_items);
-        }
-    });
+        conn.set_input_focus(InputFocus::POINTER_ROOT, cleanup.win, 0u32)?;
+        conn.flush()?;
+    }
+    Ok(())
+}
 
-    let mut query = String::new();
-    let mut sel = 0usize;
-    let mut start_index = 0usize; // New: start_index
-    let mut shift_down = false;
-    let keymap = setup_keyboard_map(&conn)?;
+/// Releases the keyboard and unmaps the window, keeping it for the next
+/// `--show` of a daemon.
+fn hide_window(cleanup: &mut Cleanup) -> Result<(), LauncherError> {
+    let conn = cleanup.conn;
+    if cleanup.grabbed {
+        conn.ungrab_keyboard(x11rb::CURRENT_TIME)?;
+        cleanup.grabbed = false;
+    }
+    conn.unmap_window(cleanup.win)?;
+    conn.flush()?;
+    Ok(())
+}
 
-    println!("rufi launcher started");
+/// How the window appears when it opens with `animations` on.
+enum OpenAnimation {
+    /// Opacity from 0 up, which only a compositor shows.
+    Fade,
+    /// Vertical distance the window travels to its place.
+    Slide(i16),
+}
 
-    loop {
-        let cache_guard = cache.lock().unwrap();
-        let items = cache_guard.get();
-
-        // Update loading state based on whether we have items
-        if loading && !items.is_empty() {
-            loading = false;
-        }
-
-        if cache_guard.is_expired() {
-            let reloader_cache = cache.clone();
-            thread::spawn(move || {
-                let mut new_items = Vec::new();
-                new_items.extend(collect_commands());
-                new_items.extend(collect_applications());
-                if let Ok(mut guard) = reloader_cache.lock() {
-                    guard.update(new_items);
-                }
-            });
-        }
+/// Pause between the steps of the opening animation.
+const ANIMATION_FRAME: Duration = Duration::from_millis(16);
 
-        let filtered = fuzzy::fuzzy_search(&query, items, cfg.max_results);
+/// The opening animation to play, if any: a fade when a compositor owns
+/// `_NET_WM_CM_S<screen>`, otherwise a slide away from the anchored edge.
+/// A managed window is placed by the WM, so it doesn't slide.
+fn open_animation(
+    conn: &RustConnection,
+    screen_num: usize,
+    cfg: &Config,
+) -> Result<Option<OpenAnimation>, LauncherError> {
+    if !cfg.animations || cfg.animation_ms == 0 {
+        return Ok(None);
+    }
+    let selection = format!("_NET_WM_CM_S{}", screen_num);
+    let cm = conn.intern_atom(false, selection.as_bytes())?.reply()?.atom;
+    if conn.get_selection_owner(cm)?.reply()?.owner != x11rb::NONE {
+        return Ok(Some(OpenAnimation::Fade));
+    }
+    if cfg.managed_window {
+        return Ok(None);
+    }
+    let distance = cfg.item_height as i16;
+    let from_below = matches!(
+        cfg.anchor,
+        Anchor::Bottom | Anchor::BottomLeft | Anchor::BottomRight
+    );
+    Ok(Some(OpenAnimation::Slide(if from_below {
+        distance
+    } else {
+        -distance
+    })))
+}
 
-        // Show loading message if still loading and no items
-        if loading && items.is_empty() {
-            draw_rect(&conn, win, 0, 0, cfg.width, cfg.height, cfg.theme.bg_color)?;
-            draw_text(
-                &conn,
+/// Puts the window `progress` (0 to 1) of the way through `animation`,
+/// with `origin` its final position.
+fn set_open_progress(
+    conn: &RustConnection,
+    win: Window,
+    atoms: &Atoms,
+    animation: &OpenAnimation,
+    origin: (i16, i16),
+    progress: f32,
+) -> Result<(), LauncherError> {
+    match animation {
+        // Fully opaque is the same as no opacity at all
+        OpenAnimation::Fade if progress >= 1.0 => {
+            conn.delete_property(win, atoms._NET_WM_WINDOW_OPACITY)?;
+        }
+        OpenAnimation::Fade => {
+            let opacity = (progress.clamp(0.0, 1.0) * u32::MAX as f32) as u32;
+            conn.change_property32(
+                PropMode::REPLACE,
                 win,
-                (cfg.width / 2 - 80) as i16,
-                (cfg.height / 2) as i16,
-                "Loading applications...",
-                cfg.theme.fg_color,
-                cfg.theme.bg_color,
+                atoms._NET_WM_WINDOW_OPACITY,
+                AtomEnum::CARDINAL,
+                &[opacity],
+            )?;
+        }
+        OpenAnimation::Slide(distance) => {
+            let offset = (*distance as f32 * (1.0 - progress.clamp(0.0, 1.0))).round() as i32;
+            conn.configure_window(
+                win,
+                &ConfigureWindowAux::new()
+                    .x(origin.0 as i32)
+                    .y(origin.1 as i32 + offset),
             )?;
-            conn.flush()?;
-            drop(cache_guard);
-            std::thread::sleep(std::time::Duration::from_millis(50));
-            continue;
         }
+    }
+    Ok(())
+}
 
-        // Calculate item_heights for all filtered items
-        let item_heights: Vec<u16> = filtered
-            .iter()
-            .map(|(item, _score)| {
-                let has_desc =
-                    cfg.show_descriptions && item.description.is_some() && cfg.item_height > 24;
-                if has_desc {
-                    cfg.item_height + cfg.font_size + cfg.padding / 2
-                } else {
-                    cfg.item_height
-                }
-            })
-            .collect();
-
-        sel = sel.min(filtered.len().saturating_sub(1));
-
-        // Determine max_visible dynamically based on available height
-        let mut current_display_height = 0;
-        let mut dynamic_max_visible = 0;
-        let query_h = cfg.item_height + cfg.padding;
-        let available_display_height = cfg.height.saturating_sub(query_h + cfg.padding * 2);
-
-        for i in start_index..filtered.len() {
-            if let Some(item_h) = item_heights.get(i) {
-                if current_display_height + *item_h <= available_display_height {
-                    current_display_height += *item_h;
-                    dynamic_max_visible += 1;
-                } else {
-                    break;
-                }
-            }
+/// Plays `animation` to the end over `duration_ms`, easing out. Blocks the
+/// event loop meanwhile; keys pressed during it are handled right after.
+fn animate_open(
+    conn: &RustConnection,
+    win: Window,
+    atoms: &Atoms,
+    animation: &OpenAnimation,
+    origin: (i16, i16),
+    duration_ms: u32,
+) -> Result<(), LauncherError> {
+    let duration = Duration::from_millis(duration_ms as u64);
+    let start = Instant::now();
+    loop {
+        let t = (start.elapsed().as_secs_f32() / duration.as_secs_f32()).min(1.0);
+        let eased = 1.0 - (1.0 - t).powi(3);
+        set_open_progress(conn, win, atoms, animation, origin, eased)?;
+        conn.flush()?;
+        if t >= 1.0 {
+            return Ok(());
         }
-        // A LOT to fix here
-        let max_visible = dynamic_max_visible.max(1); // Ensure at least one item is visible
+        thread::sleep(ANIMATION_FRAME);
+    }
+}
 
-        // Adjust start_index to keep sel in view
-        if sel >= start_index + max_visible {
-            // If sel is below the current visible window, scroll down
-            start_index = sel - max_visible + 1;
-        } else if sel < start_index {
-            // If sel is above the current visible window, scroll up
-            start_index = sel;
+/// First pause between keyboard grab attempts; each retry waits a little
+/// longer, up to `GRAB_RETRY_MAX`. With the default 20 attempts the whole
+/// budget is about a second.
+const GRAB_RETRY_START: Duration = Duration::from_millis(10);
+const GRAB_RETRY_STEP: Duration = Duration::from_millis(5);
+const GRAB_RETRY_MAX: Duration = Duration::from_millis(100);
+
+/// Grabs the keyboard for `win`, retrying with a growing delay while another
+/// client (the WM, the hotkey daemon that launched us) still holds it.
+fn grab_keyboard(conn: &RustConnection, win: Window, attempts: u32) -> Result<(), LauncherError> {
+    let attempts = attempts.max(1);
+    let mut delay = GRAB_RETRY_START;
+    for attempt in 1..=attempts {
+        let reply = conn
+            .grab_keyboard(
+                true, // owner_events
+                win,
+                x11rb::CURRENT_TIME,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+            )?
+            .reply()?;
+        if reply.status == GrabStatus::SUCCESS {
+            return Ok(());
+        }
+        if attempt < attempts {
+            thread::sleep(delay);
+            delay = (delay + GRAB_RETRY_STEP).min(GRAB_RETRY_MAX);
         }
-        // Clamp start_index to valid range
-        start_index = start_index.min(filtered.len().saturating_sub(max_visible).max(0));
+    }
+    Err(LauncherError::Other("Could not grab keyboard".into()))
+}
 
-        // Clear background
-        draw_rect(&conn, win, 0, 0, cfg.width, cfg.height, cfg.theme.bg_color)?;
+/// Computes the window origin on `monitor` from the configured anchor and
+/// margin, then applies the offsets and clamps the result so the window stays visible.
+pub fn window_position(cfg: &Config, monitor: &Monitor, width: u16, height: u16) -> (i16, i16) {
+    let outer_width = width + cfg.border_width * 2;
+    let outer_height = height + cfg.border_width * 2;
+    let free_x = monitor.width.saturating_sub(outer_width) as i32;
+    let free_y = monitor.height.saturating_sub(outer_height) as i32;
+
+    // Anchor within the monitor inset by the margin on every side
+    let margin = (cfg.margin as i32).min(free_x / 2).min(free_y / 2);
+    let (inset_x, inset_y) = (free_x - margin * 2, free_y - margin * 2);
+    let (x, y) = match cfg.anchor {
+        Anchor::Center => (inset_x / 2, inset_y / 3),
+        Anchor::Top => (inset_x / 2, 0),
+        Anchor::TopLeft => (0, 0),
+        Anchor::TopRight => (inset_x, 0),
+        Anchor::Bottom => (inset_x / 2, inset_y),
+        Anchor::BottomLeft => (0, inset_y),
+        Anchor::BottomRight => (inset_x, inset_y),
+        Anchor::Left => (0, inset_y / 2),
+        Anchor::Right => (inset_x, inset_y / 2),
+    };
+    let (x, y) = (x + margin, y + margin);
+
+    let x = (x + cfg.x_offset as i32).clamp(0, free_x);
+    let y = (y + cfg.y_offset as i32).clamp(0, free_y);
+    (monitor.x + x as i16, monitor.y + y as i16)
+}
 
-        draw_rect(
-            &conn,
-            win,
-            cfg.padding as i16,
-            cfg.padding as i16,
-            cfg.width - cfg.padding * 2,
-            query_h,
-            cfg.theme.query_bg,
-        )?;
+/// The configured `scale`, or `Xft.dpi` relative to the standard 96 dpi.
+fn scale_factor(conn: &RustConnection, screen: &Screen, cfg: &Config) -> f32 {
+    cfg.scale
+        .or_else(|| xft_dpi(conn, screen.root).map(|dpi| dpi / 96.0))
+        .unwrap_or(1.0)
+}
 
-        let prompt = if query.is_empty() {
-            "Search applications and commands..."
-        } else {
-            &format!("❯ {}", query)
-        };
+/// Wakes the event loop blocked in `wait_for_event` by sending a
+/// ClientMessage to the window over a second connection, so background
+/// threads can request a redraw.
+#[derive(Clone)]
+struct Waker {
+    conn: Arc<RustConnection>,
+    window: Window,
+}
 
-        let prompt_color = if query.is_empty() {
-            let r = ((cfg.theme.fg_color >> 16) & 0xFF) / 2;
-            let g = ((cfg.theme.fg_color >> 8) & 0xFF) / 2;
-            let b = (cfg.theme.fg_color & 0xFF) / 2;
-            (r << 16) | (g << 8) | b
-        } else {
-            cfg.theme.accent_color
-        };
+impl Waker {
+    fn connect(window: Window) -> Result<Self, LauncherError> {
+        let (conn, _) = RustConnection::connect(None)?;
+        Ok(Self {
+            conn: Arc::new(conn),
+            window,
+        })
+    }
 
-        draw_text(
-            &conn,
-            win,
-            (cfg.padding + 12) as i16,
-            (cfg.padding + cfg.font_size + 6) as i16,
-            prompt,
-            prompt_color,
-            cfg.theme.query_bg,
-        )?;
+    fn wake(&self) {
+        let event = ClientMessageEvent::new(32, self.window, AtomEnum::NONE, [0u32; 5]);
+        let _ = self
+            .conn
+            .send_event(false, self.window, EventMask::NO_EVENT, event);
+        let _ = self.conn.flush();
+    }
 
-        if !query.is_empty() {
-            let counter = format!("{} results", filtered.len());
-            draw_text(
-                &conn,
-                win,
-                (cfg.width - cfg.padding - 100) as i16,
-                (cfg.padding + cfg.font_size + 6) as i16,
-                &counter,
-                cfg.theme.fg_color,
-                cfg.theme.query_bg,
-            )?;
+    fn wake_after(&self, delay: Duration) {
+        let waker = self.clone();
+        thread::spawn(move || {
+            thread::sleep(delay);
+            waker.wake();
+        });
+    }
+}
+
+/// Sets `flag` whenever SIGHUP arrives and wakes the event loop.
+fn spawn_reload_watcher(waker: Waker, flag: Arc<AtomicBool>) -> Result<(), LauncherError> {
+    let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGHUP])?;
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            flag.store(true, Ordering::SeqCst);
+            waker.wake();
         }
+    });
+    Ok(())
+}
 
-        let list_start_y = query_h + cfg.padding * 2;
-        let mut current_y = list_start_y;
-        for (idx, (item, _score)) in filtered
-            .iter()
-            .enumerate()
-            .skip(start_index)
-            .take(max_visible)
-        // Use the dynamically calculated max_visible
-        {
-            let has_desc =
-                cfg.show_descriptions && item.description.is_some() && cfg.item_height > 24;
-            let current_item_height = if has_desc {
-                cfg.item_height + cfg.font_size + cfg.padding / 2 
-            } else {
-                cfg.item_height
-            };
+/// How often the config file is checked for changes.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_millis(400);
+
+/// The config file and the user theme it selects, if any.
+fn config_files(cfg_path: Option<&Path>, cfg: &Config) -> Vec<PathBuf> {
+    let theme_file = cfg.theme_name.as_deref().and_then(theme::user_theme_path);
+    cfg_path
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(theme_file)
+        .collect()
+}
 
-            let y = current_y;
-            let is_selected = idx == sel;
+/// Polls the modification times of `paths`, which may be swapped while
+/// running, and sets `flag` and wakes the event loop when one changes.
+fn spawn_config_watcher(waker: Waker, flag: Arc<AtomicBool>, paths: Arc<Mutex<Vec<PathBuf>>>) {
+    thread::spawn(move || {
+        let mut seen: HashMap<PathBuf, Option<SystemTime>> = HashMap::new();
+        loop {
+            let current = paths.lock().map(|paths| paths.clone()).unwrap_or_default();
+            let mut changed = false;
+            for path in current {
+                let mtime = fs::metadata(&path).and_then(|m| m.modified()).ok();
+                // Paths seen for the first time only set the baseline
+                if seen
+                    .insert(path, mtime)
+                    .is_some_and(|previous| previous != mtime)
+                {
+                    changed = true;
+                }
+            }
+            if changed {
+                flag.store(true, Ordering::SeqCst);
+                waker.wake();
+            }
+            thread::sleep(CONFIG_POLL_INTERVAL);
+        }
+    });
+}
 
-            let (item_bg_color, item_fg_color) = if is_selected {
-                (cfg.theme.selected_bg, cfg.theme.selected_fg)
-            } else {
-                (cfg.theme.bg_color, cfg.theme.fg_color)
+/// Squeezes a multi-line parse error into the first line, which has the
+/// location, and the last one, which says what is wrong.
+fn single_line_error(error: &str) -> String {
+    let mut lines = error.lines().map(str::trim).filter(|line| !line.is_empty());
+    let first = lines.next().unwrap_or_default();
+    match lines.next_back() {
+        Some(last) => format!("{}: {}", first, last),
+        None => first.to_string(),
+    }
+}
+
+/// Sets `toggle` or `show` when another invocation sends that request and
+/// wakes the event loop.
+fn spawn_toggle_watcher(
+    instance: &InstanceLock,
+    waker: Waker,
+    toggle: Arc<AtomicBool>,
+    show: Arc<AtomicBool>,
+) -> Result<(), LauncherError> {
+    instance.listen(move |request| {
+        match request {
+            Request::Toggle => toggle.store(true, Ordering::SeqCst),
+            Request::Show => show.store(true, Ordering::SeqCst),
+        }
+        waker.wake();
+    })
+}
+
+/// Reloads the items in the background and wakes the event loop once the
+/// cache holds them. Does nothing while `reloading` says an earlier reload
+/// is still running, so an expired cache doesn't start one per iteration.
+fn spawn_item_reload(
+    cache: &Arc<Mutex<ItemCache>>,
+    reloading: &Arc<AtomicBool>,
+    mode: &Mode,
+    desktops: &[String],
+    cfg: &Config,
+    waker: &Waker,
+) {
+    if reloading.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let cache = cache.clone();
+    let reloading = reloading.clone();
+    let mode = mode.clone();
+    let desktops = desktops.to_vec();
+    let cfg = cfg.clone();
+    let waker = waker.clone();
+    thread::spawn(move || {
+        let (new_items, failures) = mode.collect(&desktops, &cfg);
+        if let Ok(mut guard) = cache.lock() {
+            guard.update(new_items, failures);
+        }
+        reloading.store(false, Ordering::SeqCst);
+        waker.wake();
+    });
+}
+
+/// Reads the process list again in the background and swaps it into the
+/// cache, without running the other sources.
+fn spawn_process_reload(cache: &Arc<Mutex<ItemCache>>, reloading: &Arc<AtomicBool>, waker: &Waker) {
+    if reloading.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let cache = cache.clone();
+    let reloading = reloading.clone();
+    let waker = waker.clone();
+    thread::spawn(move || {
+        let processes = process::items();
+        if let Ok(mut guard) = cache.lock() {
+            guard.replace_processes(processes);
+        }
+        reloading.store(false, Ordering::SeqCst);
+        waker.wake();
+    });
+}
+
+/// Everything a layout renderer needs to paint one frame.
+struct Frame<'a> {
+    conn: &'a RustConnection,
+    win: Window,
+    gc: Gcontext,
+    /// Draws in the bold face, when the server has one.
+    bold_gc: Option<Gcontext>,
+    cfg: &'a Config,
+    font: FontMetrics,
+    width: u16,
+    height: u16,
+    query: &'a str,
+    filtered: &'a [(LaunchItem, i32)],
+    sel: usize,
+    caret_visible: bool,
+    /// Shown next to the result counter while the launcher lists only
+    /// applications or only commands.
+    filter_label: Option<&'a str>,
+}
+
+impl Frame<'_> {
+    /// The GC for an item's name: bold for the selection with
+    /// `bold_selection`.
+    fn name_gc(&self, selected: bool) -> Gcontext {
+        match self.bold_gc {
+            Some(bold_gc) if selected && self.cfg.bold_selection => bold_gc,
+            _ => self.gc,
+        }
+    }
+}
+
+/// Whether `item` is listed while the launcher is in `mode`.
+pub fn mode_matches(mode: &LauncherMode, item: &LaunchItem) -> bool {
+    match mode {
+        LauncherMode::Combi => matches!(item.item_type, ItemType::Application | ItemType::Command),
+        LauncherMode::Drun => item.item_type == ItemType::Application,
+        LauncherMode::Run => item.item_type == ItemType::Command,
+        LauncherMode::Window => matches!(item.item_type, ItemType::Window(_)),
+        LauncherMode::Ssh => item.item_type == ItemType::Host,
+        LauncherMode::Files => item.item_type == ItemType::File,
+        LauncherMode::Emoji => item.item_type == ItemType::Character,
+        LauncherMode::Kill => matches!(item.item_type, ItemType::Process(_)),
+        LauncherMode::Power => item.item_type == ItemType::Action,
+        LauncherMode::Script(name) => {
+            matches!(&item.item_type, ItemType::Script(mode) if mode == name)
+        }
+    }
+}
+
+/// Name shown next to the result counter for `mode`; none for `combi`,
+/// which lists everything.
+pub fn mode_label(mode: &LauncherMode) -> Option<&str> {
+    match mode {
+        LauncherMode::Combi => None,
+        mode => Some(mode.name()),
+    }
+}
+
+/// WM_CLASS names an application's windows may have: its `StartupWMClass`,
+/// or else the desktop file name, which many applications use as class.
+fn window_classes(item: &LaunchItem) -> Vec<&str> {
+    let desktop_id = item
+        .source
+        .as_deref()
+        .and_then(|path| path.file_stem())
+        .and_then(|stem| stem.to_str());
+    match item.wm_class.as_deref() {
+        Some(class) => vec![class],
+        None => desktop_id.into_iter().collect(),
+    }
+}
+
+/// The script mode whose `prefix` starts `query`, and the rest of the
+/// query to search its entries with.
+fn prefixed_script<'a>(cfg: &'a Config, query: &'a str) -> Option<(&'a str, &'a str)> {
+    cfg.script_modes.iter().find_map(|script| {
+        let prefix = script
+            .prefix
+            .as_deref()
+            .filter(|prefix| !prefix.is_empty())?;
+        let rest = query.strip_prefix(prefix)?;
+        Some((script.name.as_str(), rest.trim_start()))
+    })
+}
+
+/// The text Ctrl+C copies from `item`. Items without a command, like
+/// `--run` entries, copy their name either way.
+fn copied_text(field: CopyField, item: &LaunchItem) -> &str {
+    match field {
+        CopyField::Command if !item.command.is_empty() => &item.command,
+        _ => &item.display_name,
+    }
+}
+
+/// The row shown above the matches when the query is arithmetic.
+pub fn answer_item(query: &str, result: String) -> LaunchItem {
+    LaunchItem {
+        name: result.clone(),
+        display_name: format!("= {}", result),
+        command: result,
+        description: Some(query.trim().trim_start_matches('=').trim().to_string()),
+        generic_name: None,
+        keywords: Vec::new(),
+        icon: None,
+        working_dir: None,
+        source: None,
+        wm_class: None,
+        needs_terminal: false,
+        item_type: ItemType::Answer,
+    }
+}
+
+/// Caret blink half-period, and how long it stays solid after a keystroke.
+const CARET_BLINK: Duration = Duration::from_millis(500);
+const CARET_HOLD: Duration = Duration::from_secs(1);
+
+/// Whether the caret is shown `since_input` after the last keystroke, and
+/// how long until that changes.
+fn caret_phase(since_input: Duration) -> (bool, Duration) {
+    if since_input < CARET_HOLD {
+        return (true, CARET_HOLD - since_input);
+    }
+    let blink = CARET_BLINK.as_millis();
+    let elapsed = (since_input - CARET_HOLD).as_millis();
+    let visible = (elapsed / blink) % 2 == 1;
+    let remaining = blink - elapsed % blink;
+    (visible, Duration::from_millis(remaining as u64))
+}
+
+/// Draws the text caret as a bar with its left edge at `x`.
+fn draw_caret(frame: &Frame, x: u16, baseline: i16) -> Result<(), LauncherError> {
+    if !frame.caret_visible {
+        return Ok(());
+    }
+    let font = frame.font;
+    draw_rect(
+        frame.conn,
+        frame.win,
+        frame.gc,
+        x as i16,
+        baseline - font.ascent as i16,
+        2,
+        font.ascent + font.descent,
+        frame.cfg.theme.accent_color,
+    )
+}
+
+/// Like `wait_for_event`, but gives up after `timeout` and returns `None` so
+/// the caller can redraw without input.
+fn wait_for_event_timeout(
+    conn: &RustConnection,
+    timeout: Duration,
+) -> Result<Option<Event>, LauncherError> {
+    if let Some(event) = conn.poll_for_event()? {
+        return Ok(Some(event));
+    }
+
+    let mut fds = [libc::pollfd {
+        fd: conn.stream().as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    }];
+    let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+    // SAFETY: `fds` is a valid array of one pollfd that outlives the call.
+    let ready = unsafe { libc::poll(fds.as_mut_ptr(), 1, timeout_ms) };
+    if ready < 0 {
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err.into());
+        }
+    }
+
+    Ok(conn.poll_for_event()?)
+}
+
+/// Shortens `text` to at most `max_chars` characters, ending in "..." when
+/// anything was cut.
+fn ellipsize(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(max_chars.saturating_sub(3)).collect();
+    format!("{}...", kept)
+}
+
+/// The prompt followed by the query, e.g. `❯ fire`.
+pub fn prompt_line(prompt: &str, query: &str) -> String {
+    if prompt.is_empty() {
+        query.to_string()
+    } else {
+        format!("{} {}", prompt, query)
+    }
+}
+
+/// Draws only the query bar, with the prompt and counter, over what is
+/// there; callers clear the window first when the rows change too.
+fn draw_query_bar(frame: &Frame) -> Result<(), LauncherError> {
+    let Frame {
+        conn,
+        win,
+        gc,
+        cfg,
+        font,
+        width,
+        query,
+        filtered,
+        ..
+    } = *frame;
+    let query_h = cfg.item_height + cfg.padding;
+
+    // Covers the whole bar, so it can be redrawn alone when the caret blinks
+    draw_rect(
+        conn,
+        win,
+        gc,
+        cfg.padding as i16,
+        cfg.padding as i16,
+        width - cfg.padding * 2,
+        query_h,
+        cfg.theme.query_bg,
+    )?;
+    draw_outline(
+        conn,
+        win,
+        gc,
+        cfg.padding as i16,
+        cfg.padding as i16,
+        width - cfg.padding * 2,
+        query_h,
+        cfg.query_outline_width,
+        cfg.theme.accent_color,
+    )?;
+
+    // Leave room for the result counter on the right
+    let text_x = cfg.padding + 12;
+    let counter = match (query.is_empty(), frame.filter_label) {
+        (true, None) => None,
+        (true, Some(label)) => Some(label.to_string()),
+        (false, None) => Some(format!("{} results", filtered.len())),
+        (false, Some(label)) => Some(format!("{} results ({})", filtered.len(), label)),
+    };
+    let counter_w = counter
+        .as_ref()
+        .map_or(0, |counter| font.text_width(counter).max(88) + 12);
+    let text_w = width.saturating_sub(text_x + cfg.padding + counter_w);
+    let baseline = (cfg.padding + cfg.font_size + 6) as i16;
+
+    if query.is_empty() {
+        let prompt = ellipsize(&cfg.prompt, (text_w / font.char_width) as usize);
+        let prompt_w = if prompt.is_empty() {
+            0
+        } else {
+            font.text_width(&prompt) + font.char_width
+        };
+        draw_text(
+            conn,
+            win,
+            gc,
+            text_x as i16,
+            baseline,
+            &prompt,
+            cfg.theme.accent_color,
+            cfg.theme.query_bg,
+        )?;
+        let placeholder = ellipsize(
+            &cfg.placeholder,
+            (text_w.saturating_sub(prompt_w) / font.char_width) as usize,
+        );
+        draw_text(
+            conn,
+            win,
+            gc,
+            (text_x + prompt_w) as i16,
+            baseline,
+            &placeholder,
+            dim_color(cfg.theme.fg_color, 50),
+            cfg.theme.query_bg,
+        )?;
+    } else {
+        let line = ellipsize(
+            &prompt_line(&cfg.prompt, query),
+            (text_w / font.char_width) as usize,
+        );
+        draw_text(
+            conn,
+            win,
+            gc,
+            text_x as i16,
+            baseline,
+            &line,
+            cfg.theme.accent_color,
+            cfg.theme.query_bg,
+        )?;
+    }
+
+    let caret_x = text_x
+        + font
+            .text_width(&prompt_line(&cfg.prompt, query))
+            .min(text_w);
+    draw_caret(frame, caret_x, baseline)?;
+
+    if let Some(counter) = counter {
+        draw_text(
+            conn,
+            win,
+            gc,
+            width.saturating_sub(cfg.padding + counter_w) as i16,
+            (cfg.padding + cfg.font_size + 6) as i16,
+            &counter,
+            cfg.theme.fg_color,
+            cfg.theme.query_bg,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Whether `query` is typed but still shorter than `min_query_length`.
+pub fn query_too_short(cfg: &Config, query: &str) -> bool {
+    !query.is_empty() && query.chars().count() < cfg.min_query_length
+}
+
+/// The configured empty-state message with `{query}` filled in, or a hint
+/// to keep typing while the query is too short to search.
+pub fn empty_message(cfg: &Config, query: &str) -> String {
+    if query_too_short(cfg, query) {
+        return "Keep typing...".to_string();
+    }
+    cfg.empty_message.replace("{query}", query)
+}
+
+/// Draws the dimmed "no results" message centered in the list area that
+/// starts at `top`.
+fn draw_empty_state(frame: &Frame, top: u16) -> Result<(), LauncherError> {
+    let Frame {
+        conn,
+        win,
+        gc,
+        cfg,
+        font,
+        width,
+        height,
+        query,
+        ..
+    } = *frame;
+
+    // A dynamic-height window shrinks to a single row
+    let area_h = if cfg.dynamic_height {
+        cfg.item_height
+    } else {
+        height.saturating_sub(top + cfg.padding)
+    };
+    let max_chars = (width.saturating_sub(cfg.padding * 2) / font.char_width) as usize;
+    let message = ellipsize(&empty_message(cfg, query), max_chars);
+    let x = width.saturating_sub(font.text_width(&message)) / 2;
+    let y = top + (area_h + font.ascent).saturating_sub(font.descent) / 2;
+    draw_text(
+        conn,
+        win,
+        gc,
+        x as i16,
+        y as i16,
+        &message,
+        dim_color(cfg.theme.fg_color, 75),
+        cfg.theme.bg_color,
+    )
+}
+
+/// Returns the icon's pixels when they're ready, otherwise queues it in
+/// `wanted` for the loader.
+fn lookup_icon(
+    icon_loader: &IconLoader,
+    icon_name: &str,
+    size: u16,
+    wanted: &mut Vec<(String, u16)>,
+) -> Option<Arc<Vec<u8>>> {
+    match icon_loader.lookup(icon_name, size) {
+        IconState::Ready(pixels) => Some(pixels),
+        IconState::Pending => {
+            wanted.push((icon_name.to_string(), size));
+            None
+        }
+        IconState::Missing => None,
+    }
+}
+
+/// Configured stand-in for items without a usable icon of their own.
+fn fallback_icon<'a>(item: &LaunchItem, cfg: &'a Config) -> Option<&'a str> {
+    let name = match item.item_type {
+        ItemType::Application | ItemType::Action | ItemType::Window(_) | ItemType::Web => {
+            &cfg.fallback_icon
+        }
+        ItemType::Command | ItemType::Host => &cfg.command_fallback_icon,
+        ItemType::Character
+        | ItemType::Text
+        | ItemType::Answer
+        | ItemType::File
+        | ItemType::Script(_)
+        | ItemType::Process(_) => return None,
+    };
+    Some(name.as_str()).filter(|name| !name.is_empty())
+}
+
+/// Whether rows reserve space for `item`'s icon.
+fn has_icon(item: &LaunchItem, cfg: &Config) -> bool {
+    cfg.show_icons && (item.icon.is_some() || fallback_icon(item, cfg).is_some())
+}
+
+/// Like `lookup_icon` for an item's own icon, using the fallback icon once
+/// the own one turns out to be missing.
+fn lookup_item_icon(
+    icon_loader: &IconLoader,
+    item: &LaunchItem,
+    cfg: &Config,
+    size: u16,
+    wanted: &mut Vec<(String, u16)>,
+) -> Option<Arc<Vec<u8>>> {
+    if let Some(icon_name) = item.icon.as_deref().filter(|name| !name.is_empty()) {
+        match icon_loader.lookup(icon_name, size) {
+            IconState::Ready(pixels) => return Some(pixels),
+            IconState::Pending => {
+                wanted.push((icon_name.to_string(), size));
+                return None;
+            }
+            IconState::Missing => {}
+        }
+    }
+    lookup_icon(icon_loader, fallback_icon(item, cfg)?, size, wanted)
+}
+
+/// Label and chip color of the badge marking an item's type.
+fn type_badge(item_type: &ItemType, theme: &ConfigTheme) -> (&'static str, u32) {
+    match item_type {
+        ItemType::Application => ("APP", theme.badge_app_color.unwrap_or(theme.accent_color)),
+        ItemType::Command => ("CMD", theme.badge_cmd_color.unwrap_or(theme.border_color)),
+        ItemType::Action => ("ACT", theme.selected_bg),
+        ItemType::Character => ("CHR", theme.badge_cmd_color.unwrap_or(theme.border_color)),
+        ItemType::Window(_) => ("WIN", theme.badge_app_color.unwrap_or(theme.accent_color)),
+        ItemType::Host => ("SSH", theme.badge_cmd_color.unwrap_or(theme.border_color)),
+        ItemType::Text => ("TXT", theme.border_color),
+        ItemType::Answer => ("ANS", theme.badge_app_color.unwrap_or(theme.accent_color)),
+        ItemType::File => ("FIL", theme.badge_cmd_color.unwrap_or(theme.border_color)),
+        ItemType::Script(_) => ("SCR", theme.badge_cmd_color.unwrap_or(theme.border_color)),
+        ItemType::Process(_) => ("PID", theme.badge_cmd_color.unwrap_or(theme.border_color)),
+        ItemType::Web => ("WEB", theme.badge_app_color.unwrap_or(theme.accent_color)),
+    }
+}
+
+/// Draws the type badge with its left edge at `x`, its label on `baseline`.
+/// Returns the badge width.
+fn draw_badge(
+    frame: &Frame,
+    x: i16,
+    baseline: i16,
+    item_type: &ItemType,
+) -> Result<u16, LauncherError> {
+    let Frame {
+        conn,
+        win,
+        gc,
+        cfg,
+        font,
+        ..
+    } = *frame;
+    let (label, color) = type_badge(item_type, &cfg.theme);
+    let badge_w = font.text_width(label) + 8;
+    let badge_h = font.ascent + font.descent + 2;
+    draw_rect(
+        conn,
+        win,
+        gc,
+        x,
+        baseline - font.ascent as i16 - 1,
+        badge_w,
+        badge_h,
+        color,
+    )?;
+    draw_text(
+        conn,
+        win,
+        gc,
+        x + 4,
+        baseline,
+        label,
+        cfg.theme.bg_color,
+        color,
+    )?;
+    Ok(badge_w)
+}
+
+/// Where the name and description of a list row start, after the icon slot.
+fn row_text_x(item: &LaunchItem, cfg: &Config) -> u16 {
+    if has_icon(item, cfg) {
+        // 4px inset, an icon a bit smaller than the row, then an 8px gap
+        cfg.padding + 4 + (cfg.item_height - 8) + 8
+    } else {
+        cfg.padding + 12
+    }
+}
+
+/// The description of a list row as drawn: wrapped to `max_lines` lines of
+/// `max_chars`, with "..." on the last one when the rest doesn't fit.
+fn description_lines(desc: &str, max_chars: usize, max_lines: usize) -> Vec<String> {
+    let max_lines = max_lines.max(1);
+    let mut lines = wrap_text(desc, max_chars);
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        let last = lines.last_mut().unwrap();
+        let keep = last.chars().count().min(max_chars.saturating_sub(3));
+        *last = format!("{}...", last.chars().take(keep).collect::<String>());
+    }
+    lines
+}
+
+/// Draws the query bar and the one-item-per-row list. Returns the height of
+/// the visible rows, so the caller can fit the window to them, and how many
+/// rows are visible.
+fn draw_vertical(
+    frame: &Frame,
+    start_index: &mut usize,
+    icon_loader: &IconLoader,
+    wanted_icons: &mut Vec<(String, u16)>,
+) -> Result<(u16, usize), LauncherError> {
+    let Frame {
+        conn,
+        win,
+        gc,
+        cfg,
+        font,
+        width,
+        height,
+        filtered,
+        sel,
+        ..
+    } = *frame;
+
+    // Wrapped description lines of every row; rows grow by one line each
+    let descriptions: Vec<Vec<String>> = filtered
+        .iter()
+        .enumerate()
+        .map(|(idx, (item, _score))| {
+            let Some(desc) = &item.description else {
+                return Vec::new();
+            };
+            if !cfg.show_descriptions || cfg.item_height <= 24 {
+                return Vec::new();
+            }
+            let max_lines = if cfg.desc_wrap == DescriptionWrap::All || idx == sel {
+                cfg.desc_max_lines as usize
+            } else {
+                1
+            };
+            let text_w = width.saturating_sub(row_text_x(item, cfg) + cfg.padding * 2);
+            let max_chars = (text_w / font.char_width.max(1)) as usize;
+            description_lines(desc, max_chars, max_lines)
+        })
+        .collect();
+    let item_heights: Vec<u16> = descriptions
+        .iter()
+        .map(|lines| {
+            if lines.is_empty() {
+                cfg.item_height
+            } else {
+                cfg.item_height + cfg.font_size * lines.len() as u16 + cfg.row_text_padding() / 2
+            }
+        })
+        .collect();
+
+    // Determine max_visible dynamically based on available height
+    let mut current_display_height = 0;
+    let mut dynamic_max_visible = 0;
+    let query_h = cfg.item_height + cfg.padding;
+    let available_display_height = height.saturating_sub(query_h + cfg.padding * 2);
+
+    for i in *start_index..filtered.len() {
+        if let Some(item_h) = item_heights.get(i) {
+            let spacing = if dynamic_max_visible > 0 {
+                cfg.row_spacing
+            } else {
+                0
+            };
+            if current_display_height + spacing + *item_h <= available_display_height {
+                current_display_height += spacing + *item_h;
+                dynamic_max_visible += 1;
+            } else {
+                break;
+            }
+        }
+    }
+    // A LOT to fix here
+    let max_visible = dynamic_max_visible.max(1); // Ensure at least one item is visible
+
+    // Adjust start_index to keep sel in view
+    if sel >= *start_index + max_visible {
+        // If sel is below the current visible window, scroll down
+        *start_index = sel - max_visible + 1;
+    } else if sel < *start_index {
+        // If sel is above the current visible window, scroll up
+        *start_index = sel;
+    }
+    // Clamp start_index to valid range
+    *start_index = (*start_index).min(filtered.len().saturating_sub(max_visible).max(0));
+
+    // Clear background
+    draw_rect(conn, win, gc, 0, 0, width, height, cfg.theme.bg_color)?;
+    draw_query_bar(frame)?;
+
+    let list_start_y = query_h + cfg.padding * 2;
+    if filtered.is_empty() {
+        draw_empty_state(frame, list_start_y)?;
+    }
+    let mut current_y = list_start_y;
+    for (idx, (item, _score)) in filtered
+        .iter()
+        .enumerate()
+        .skip(*start_index)
+        .take(max_visible)
+    // Use the dynamically calculated max_visible
+    {
+        let current_item_height = item_heights[idx];
+
+        let y = current_y;
+        let is_selected = idx == sel;
+
+        let is_alt = cfg.alternate_rows && idx % 2 == 1;
+        let (item_bg_color, item_fg_color) = if is_selected {
+            (cfg.theme.selected_bg, cfg.theme.selected_fg)
+        } else if is_alt {
+            let alt_bg = cfg
+                .theme
+                .row_alt_bg
+                .unwrap_or_else(|| blend_color(cfg.theme.bg_color, cfg.theme.fg_color, 6));
+            (alt_bg, cfg.theme.fg_color)
+        } else {
+            (cfg.theme.bg_color, cfg.theme.fg_color)
+        };
+
+        if is_selected || is_alt {
+            draw_rect(
+                conn,
+                win,
+                gc,
+                cfg.padding as i16,
+                y as i16,
+                width - cfg.padding * 2,
+                current_item_height,
+                item_bg_color,
+            )?;
+        }
+
+        if has_icon(item, cfg) {
+            let icon_size = cfg.item_height - 8; // A bit smaller than item_height
+            let icon_x = cfg.padding as i16 + 4;
+            let icon_y = y as i16 + 4;
+            if let Some(pixels) = lookup_item_icon(icon_loader, item, cfg, icon_size, wanted_icons)
+            {
+                if let Err(e) = draw_icon(frame, icon_x, icon_y, icon_size, &pixels, item_bg_color)
+                {
+                    eprintln!("Failed to draw icon for {}: {}", item.display_name, e);
+                }
+            }
+        }
+        let text_start_x = row_text_x(item, cfg) as i16;
+
+        let text_padding = cfg.row_text_padding();
+        let display_text_y = (y + text_padding) as i16; // Position name with padding from top of current_item_height
+
+        // Every line is text in dmenu mode, so a badge says nothing there
+        let name_x = if cfg.show_type_badges && item.item_type != ItemType::Text {
+            let badge_w = draw_badge(frame, text_start_x, display_text_y, &item.item_type)?;
+            text_start_x + badge_w as i16 + 6
+        } else {
+            text_start_x
+        };
+
+        draw_text(
+            conn,
+            win,
+            frame.name_gc(is_selected),
+            name_x,
+            display_text_y,
+            &item.display_name,
+            item_fg_color,
+            item_bg_color,
+        )?;
+
+        if !descriptions[idx].is_empty() {
+            let desc_color = if is_selected {
+                item_fg_color
+            } else {
+                // Dimmed description color
+                let r = ((cfg.theme.fg_color >> 16) & 0xFF) * 3 / 4;
+                let g = ((cfg.theme.fg_color >> 8) & 0xFF) * 3 / 4;
+                let b = (cfg.theme.fg_color & 0xFF) * 3 / 4;
+                (r << 16) | (g << 8) | b
             };
 
-            if is_selected {
-                draw_rect(
-                    &conn,
-                    win,
-                    cfg.padding as i16,
-                    y as i16,
-                    cfg.width - cfg.padding * 2,
-                    current_item_height,
-                    item_bg_color,
-                )?;
+            let mut desc_y = (y + text_padding + cfg.font_size + text_padding / 4) as i16; // Position description below name
+            for line in &descriptions[idx] {
+                draw_text(
+                    conn,
+                    win,
+                    gc,
+                    text_start_x,
+                    desc_y,
+                    line,
+                    desc_color,
+                    item_bg_color,
+                )?;
+                desc_y += cfg.font_size as i16;
+            }
+        }
+        // The selected row's highlight covers its separator
+        let is_last = idx + 1 == (*start_index + max_visible).min(filtered.len());
+        if cfg.show_separators && !is_selected && !is_last {
+            draw_rect(
+                conn,
+                win,
+                gc,
+                cfg.padding as i16,
+                (y + current_item_height - 1) as i16,
+                width - cfg.padding * 2,
+                1,
+                cfg.theme.separator_color.unwrap_or(cfg.theme.border_color),
+            )?;
+        }
+        current_y += current_item_height + cfg.row_spacing;
+    }
+
+    let visible = max_visible.min(filtered.len() - *start_index);
+    let list_h = item_heights
+        .iter()
+        .skip(*start_index)
+        .take(max_visible)
+        .sum::<u16>()
+        + cfg.row_spacing * visible.saturating_sub(1) as u16;
+    Ok((list_h, visible))
+}
+
+/// Draws results as a grid of `cfg.columns` cells, each a large icon above a
+/// centered label. Scrolls by whole rows. Returns how many cells are visible.
+fn draw_grid(
+    frame: &Frame,
+    start_index: &mut usize,
+    icon_loader: &IconLoader,
+    wanted_icons: &mut Vec<(String, u16)>,
+) -> Result<usize, LauncherError> {
+    let Frame {
+        conn,
+        win,
+        gc,
+        cfg,
+        font,
+        width,
+        height,
+        filtered,
+        sel,
+        ..
+    } = *frame;
+    let columns = cfg.columns.max(1) as usize;
+
+    let query_h = cfg.item_height + cfg.padding;
+    let list_start_y = query_h + cfg.padding * 2;
+    let cell_w = width.saturating_sub(cfg.padding * 2) / columns as u16;
+    let icon_size = cell_w
+        .saturating_sub(cfg.padding * 2)
+        .min(cfg.item_height * 2)
+        .max(1);
+    let label_h = font.ascent + font.descent;
+    let cell_h = icon_size + label_h + cfg.padding * 2;
+    let visible_rows = (height.saturating_sub(list_start_y) / cell_h).max(1) as usize;
+
+    // Keep the selected row in view, scrolling a row at a time
+    let sel_row = sel / columns;
+    let mut start_row = *start_index / columns;
+    if sel_row >= start_row + visible_rows {
+        start_row = sel_row + 1 - visible_rows;
+    } else if sel_row < start_row {
+        start_row = sel_row;
+    }
+    *start_index = start_row * columns;
+
+    // Clear background
+    draw_rect(conn, win, gc, 0, 0, width, height, cfg.theme.bg_color)?;
+    draw_query_bar(frame)?;
+    if filtered.is_empty() {
+        draw_empty_state(frame, list_start_y)?;
+    }
+
+    let max_chars = (cell_w.saturating_sub(cfg.padding) / font.char_width.max(1)) as usize;
+    for (idx, (item, _score)) in filtered
+        .iter()
+        .enumerate()
+        .skip(*start_index)
+        .take(visible_rows * columns)
+    {
+        let offset = idx - *start_index;
+        let cell_x = cfg.padding + (offset % columns) as u16 * cell_w;
+        let cell_y = list_start_y + (offset / columns) as u16 * cell_h;
+
+        let (bg, fg) = if idx == sel {
+            (cfg.theme.selected_bg, cfg.theme.selected_fg)
+        } else {
+            (cfg.theme.bg_color, cfg.theme.fg_color)
+        };
+        if idx == sel {
+            draw_rect(
+                conn,
+                win,
+                gc,
+                cell_x as i16,
+                cell_y as i16,
+                cell_w,
+                cell_h,
+                bg,
+            )?;
+        }
+
+        if has_icon(item, cfg) {
+            if let Some(pixels) = lookup_item_icon(icon_loader, item, cfg, icon_size, wanted_icons)
+            {
+                let icon_x = cell_x + cell_w.saturating_sub(icon_size) / 2;
+                let icon_y = cell_y + cfg.padding;
+                if let Err(e) =
+                    draw_icon(frame, icon_x as i16, icon_y as i16, icon_size, &pixels, bg)
+                {
+                    eprintln!("Failed to draw icon for {}: {}", item.display_name, e);
+                }
+            }
+        }
+
+        let label = ellipsize(&item.display_name, max_chars);
+        let label_x = cell_x + cell_w.saturating_sub(font.text_width(&label)) / 2;
+        let label_y = cell_y + cfg.padding + icon_size + font.ascent;
+        draw_text(
+            conn,
+            win,
+            frame.name_gc(idx == sel),
+            label_x as i16,
+            label_y as i16,
+            &label,
+            fg,
+            bg,
+        )?;
+    }
+
+    Ok((visible_rows * columns).min(filtered.len() - *start_index))
+}
+
+/// How long a footer status message stays up without further input.
+const STATUS_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// Scales each channel of `color` to `percent` of its value.
+pub fn dim_color(color: u32, percent: u32) -> u32 {
+    let r = ((color >> 16) & 0xFF) * percent / 100;
+    let g = ((color >> 8) & 0xFF) * percent / 100;
+    let b = (color & 0xFF) * percent / 100;
+    (r << 16) | (g << 8) | b
+}
+
+/// Mixes `percent` of `to` into `from`, channel by channel.
+fn blend_color(from: u32, to: u32, percent: u32) -> u32 {
+    let channel = |shift: u32| {
+        let a = (from >> shift) & 0xFF;
+        let b = (to >> shift) & 0xFF;
+        (a * (100 - percent) + b * percent) / 100
+    };
+    (channel(16) << 16) | (channel(8) << 8) | channel(0)
+}
+
+/// Draws the footer strip at `y`: the status message on the left and the
+/// selected item's command on the right, both dimmed.
+fn draw_footer(frame: &Frame, y: u16, status: Option<&str>) -> Result<(), LauncherError> {
+    let Frame {
+        conn,
+        win,
+        gc,
+        cfg,
+        font,
+        width,
+        filtered,
+        sel,
+        ..
+    } = *frame;
+
+    let footer_h = font.ascent + font.descent + cfg.padding;
+    draw_rect(
+        conn,
+        win,
+        gc,
+        0,
+        y as i16,
+        width,
+        footer_h,
+        cfg.theme.bg_color,
+    )?;
+
+    let color = dim_color(cfg.theme.fg_color, 60);
+    let baseline = (y + cfg.padding / 2 + font.ascent) as i16;
+    let inner_w = width.saturating_sub(cfg.padding * 2);
+
+    let mut status_w = 0;
+    if let Some(message) = status {
+        status_w = font.text_width(message).min(inner_w);
+        draw_text(
+            conn,
+            win,
+            gc,
+            cfg.padding as i16,
+            baseline,
+            message,
+            color,
+            cfg.theme.bg_color,
+        )?;
+    }
+
+    if let Some((item, _)) = filtered.get(sel) {
+        // Right-aligned, and only if it fits beside the status message
+        let command_w = font.text_width(&item.command);
+        if status_w + command_w + cfg.padding <= inner_w {
+            draw_text(
+                conn,
+                win,
+                gc,
+                (width - cfg.padding - command_w) as i16,
+                baseline,
+                &item.command,
+                color,
+                cfg.theme.bg_color,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Key hints for the bottom row: the keys that do something in `mode` with
+/// the current layout, and what Enter does there. `visible_count` is the
+/// number of results on screen, which the Alt+digit shortcuts can reach.
+fn key_hints(mode: &Mode, cfg: &Config, visible_count: usize) -> String {
+    let moves = match cfg.layout {
+        Layout::Horizontal => "Left/Right move",
+        Layout::Vertical if cfg.grid_columns() > 1 => "Arrows move",
+        Layout::Vertical => "Up/Down move",
+    };
+    let enter = match mode {
+        Mode::Launcher | Mode::Run(_) => "launch",
+        Mode::Power(_) => "run",
+        Mode::Emoji => "copy",
+        Mode::Dmenu(_) => "select",
+    };
+    let escape = if cfg.escape_clears_first {
+        "clear/close"
+    } else {
+        "close"
+    };
+    let mut hints = vec![moves.to_string(), format!("Enter {}", enter)];
+    match visible_count.min(9) {
+        0 | 1 => {}
+        last => hints.push(format!("Alt+1..{} pick", last)),
+    }
+    // Only the launcher has modes to cycle through
+    if matches!(mode, Mode::Launcher) && cfg.launcher_modes().len() > 1 {
+        hints.push("Ctrl+Tab mode".to_string());
+    }
+    hints.push(format!("Esc {}", escape));
+    hints.join("   ")
+}
+
+/// Draws the key hints row with its top edge at `y`, dimmed like the footer.
+fn draw_hints(frame: &Frame, y: u16, hints: &str) -> Result<(), LauncherError> {
+    let Frame {
+        conn,
+        win,
+        gc,
+        cfg,
+        font,
+        width,
+        ..
+    } = *frame;
+
+    let hints_h = font.ascent + font.descent + cfg.padding;
+    draw_rect(
+        conn,
+        win,
+        gc,
+        0,
+        y as i16,
+        width,
+        hints_h,
+        cfg.theme.bg_color,
+    )?;
+
+    let inner_w = width.saturating_sub(cfg.padding * 2);
+    let hints = ellipsize(hints, (inner_w / font.char_width.max(1)) as usize);
+    draw_text(
+        conn,
+        win,
+        gc,
+        cfg.padding as i16,
+        (y + cfg.padding / 2 + font.ascent) as i16,
+        &hints,
+        dim_color(cfg.theme.fg_color, 60),
+        cfg.theme.bg_color,
+    )
+}
+
+/// The preview panel is dropped below this window width so the list keeps
+/// enough room.
+const PREVIEW_MIN_WIDTH: u16 = 600;
+
+/// Man page summaries of commands shown in the preview panel, looked up on
+/// a background thread the first time each command is selected.
+struct CommandSummaries {
+    known: Arc<Mutex<HashMap<String, Option<String>>>>,
+    waker: Waker,
+}
+
+impl CommandSummaries {
+    fn new(waker: Waker) -> Self {
+        Self {
+            known: Arc::new(Mutex::new(HashMap::new())),
+            waker,
+        }
+    }
+
+    /// Returns the summary of `command` if it is known, starting the lookup
+    /// otherwise. The window is woken once it finishes.
+    fn get(&self, command: &str) -> Option<String> {
+        let mut known = self.known.lock().ok()?;
+        if let Some(summary) = known.get(command) {
+            return summary.clone();
+        }
+        // Marks the lookup as running so it only starts once
+        known.insert(command.to_string(), None);
+
+        let command = command.to_string();
+        let known = self.known.clone();
+        let waker = self.waker.clone();
+        thread::spawn(move || {
+            let summary = command_summary(&command);
+            if summary.is_some() {
+                if let Ok(mut known) = known.lock() {
+                    known.insert(command, summary);
+                }
+                waker.wake();
+            }
+        });
+        None
+    }
+}
+
+/// Splits `text` into lines of at most `max_chars` characters, breaking at
+/// whitespace where possible.
+fn wrap_text(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        // Hard-break words that don't fit on a line of their own
+        while word.len() > max_chars {
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            lines.push(word.drain(..max_chars).collect());
+        }
+        let word: String = word.into_iter().collect();
+        if word.is_empty() {
+            continue;
+        }
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Draws the details of the selected item in a `panel_w` wide panel to the
+/// right of the frame: a large icon, the name, the full description, the
+/// command and where the item came from. Commands also show their man page
+/// summary.
+fn draw_preview(
+    frame: &Frame,
+    panel_w: u16,
+    icon_loader: &IconLoader,
+    summaries: &CommandSummaries,
+    wanted_icons: &mut Vec<(String, u16)>,
+) -> Result<(), LauncherError> {
+    let Frame {
+        conn,
+        win,
+        gc,
+        cfg,
+        font,
+        width,
+        height,
+        filtered,
+        sel,
+        ..
+    } = *frame;
+
+    let x = width;
+    draw_rect(
+        conn,
+        win,
+        gc,
+        x as i16,
+        0,
+        panel_w,
+        height,
+        cfg.theme.bg_color,
+    )?;
+    draw_rect(
+        conn,
+        win,
+        gc,
+        x as i16,
+        0,
+        1,
+        height,
+        cfg.theme.border_color,
+    )?;
+
+    let Some((item, _)) = filtered.get(sel) else {
+        return Ok(());
+    };
+
+    let inner_x = x + cfg.padding * 2;
+    let inner_w = panel_w.saturating_sub(cfg.padding * 4);
+    let mut y = cfg.padding * 2;
+
+    if has_icon(item, cfg) {
+        let icon_size = inner_w.min(cfg.item_height * 3).max(1);
+        if let Some(pixels) = lookup_item_icon(icon_loader, item, cfg, icon_size, wanted_icons) {
+            let icon_x = inner_x + inner_w.saturating_sub(icon_size) / 2;
+            let bg = cfg.theme.bg_color;
+            if let Err(e) = draw_icon(frame, icon_x as i16, y as i16, icon_size, &pixels, bg) {
+                eprintln!("Failed to draw icon for {}: {}", item.display_name, e);
+            }
+        }
+        y += icon_size + cfg.padding;
+    }
+
+    let max_chars = (inner_w / font.char_width.max(1)) as usize;
+    let line_h = font.ascent + font.descent + 2;
+    let detail_color = dim_color(cfg.theme.fg_color, 75);
+
+    let mut sections: Vec<(String, u32)> =
+        vec![(item.display_name.clone(), cfg.theme.accent_color)];
+    if let Some(desc) = &item.description {
+        sections.push((desc.clone(), cfg.theme.fg_color));
+    }
+    if item.item_type == ItemType::Command {
+        if let Some(summary) = summaries.get(&item.name) {
+            sections.push((summary, cfg.theme.fg_color));
+        }
+    }
+    sections.push((format!("Exec: {}", item.command), detail_color));
+    if let Some(dir) = &item.working_dir {
+        sections.push((format!("Path: {}", dir), detail_color));
+    }
+    if let Some(source) = &item.source {
+        sections.push((format!("Source: {}", source.display()), detail_color));
+    }
+
+    for (text, color) in sections {
+        for line in wrap_text(&text, max_chars) {
+            if y + line_h > height.saturating_sub(cfg.padding) {
+                return Ok(());
+            }
+            draw_text(
+                conn,
+                win,
+                gc,
+                inner_x as i16,
+                (y + font.ascent) as i16,
+                &line,
+                color,
+                cfg.theme.bg_color,
+            )?;
+            y += line_h;
+        }
+        y += line_h / 2;
+    }
+
+    Ok(())
+}
+
+/// Draws a single dmenu-style row: the query on the left, then as many
+/// matches as fit, scrolled so the selection stays visible. Returns how many
+/// matches fit.
+fn draw_horizontal(frame: &Frame, start_index: &mut usize) -> Result<usize, LauncherError> {
+    let Frame {
+        conn,
+        win,
+        gc,
+        cfg,
+        font,
+        width,
+        height,
+        query,
+        filtered,
+        sel,
+        ..
+    } = *frame;
+
+    draw_rect(conn, win, gc, 0, 0, width, height, cfg.theme.bg_color)?;
+
+    let baseline = ((height + font.ascent).saturating_sub(font.descent) / 2) as i16;
+    let prompt_w = width / 4;
+    draw_rect(conn, win, gc, 0, 0, prompt_w, height, cfg.theme.query_bg)?;
+    draw_outline(
+        conn,
+        win,
+        gc,
+        0,
+        0,
+        prompt_w,
+        height,
+        cfg.query_outline_width,
+        cfg.theme.accent_color,
+    )?;
+
+    let max_chars = (prompt_w.saturating_sub(cfg.padding * 2) / font.char_width) as usize;
+    let (prompt, prompt_color) = if query.is_empty() {
+        (
+            prompt_line(&cfg.prompt, &cfg.placeholder),
+            cfg.theme.fg_color,
+        )
+    } else {
+        (prompt_line(&cfg.prompt, query), cfg.theme.accent_color)
+    };
+    let prompt = ellipsize(&prompt, max_chars);
+    draw_text(
+        conn,
+        win,
+        gc,
+        cfg.padding as i16,
+        baseline,
+        &prompt,
+        prompt_color,
+        cfg.theme.query_bg,
+    )?;
+    let caret_x = cfg.padding
+        + font
+            .text_width(&prompt_line(&cfg.prompt, query))
+            .min(prompt_w.saturating_sub(cfg.padding * 2));
+    draw_caret(frame, caret_x, baseline)?;
+
+    let items_x = (prompt_w + cfg.padding) as u32;
+    if filtered.is_empty() {
+        draw_text(
+            conn,
+            win,
+            gc,
+            items_x as i16,
+            baseline,
+            &empty_message(cfg, query),
+            dim_color(cfg.theme.fg_color, 75),
+            cfg.theme.bg_color,
+        )?;
+        return Ok(0);
+    }
+
+    let available = (width as u32).saturating_sub(items_x + cfg.padding as u32);
+    let cell_width =
+        |item: &LaunchItem| font.text_width(&item.display_name) as u32 + cfg.padding as u32 * 2;
+
+    // Scroll so that everything from start_index through sel fits
+    if sel < *start_index {
+        *start_index = sel;
+    }
+    while *start_index < sel
+        && filtered[*start_index..=sel]
+            .iter()
+            .map(|(item, _)| cell_width(item))
+            .sum::<u32>()
+            > available
+    {
+        *start_index += 1;
+    }
+
+    let mut x = items_x;
+    let mut visible = 0;
+    for (idx, (item, _score)) in filtered.iter().enumerate().skip(*start_index) {
+        let w = cell_width(item);
+        if x + w > items_x + available && idx != *start_index {
+            break;
+        }
+
+        let (bg, fg) = if idx == sel {
+            (cfg.theme.selected_bg, cfg.theme.selected_fg)
+        } else {
+            (cfg.theme.bg_color, cfg.theme.fg_color)
+        };
+        if idx == sel {
+            draw_rect(conn, win, gc, x as i16, 0, w as u16, height, bg)?;
+        }
+        draw_text(
+            conn,
+            win,
+            frame.name_gc(idx == sel),
+            (x + cfg.padding as u32) as i16,
+            baseline,
+            &item.display_name,
+            fg,
+            bg,
+        )?;
+        x += w;
+        visible += 1;
+    }
+
+    Ok(visible)
+}
+
+/// Where a launcher mode was left when Ctrl+Tab switched away from it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModeSnapshot {
+    pub query: String,
+    /// Name of the selected item, found again by name as the list may
+    /// have changed in the meantime.
+    pub selected: Option<String>,
+    pub start_index: usize,
+}
+
+/// Keeps where the `leaving` mode was and takes out the snapshot of the
+/// `entering` one, if it was visited before.
+pub fn switch_mode_snapshot(
+    snapshots: &mut HashMap<LauncherMode, ModeSnapshot>,
+    leaving: LauncherMode,
+    left: ModeSnapshot,
+    entering: &LauncherMode,
+) -> Option<ModeSnapshot> {
+    snapshots.insert(leaving, left);
+    snapshots.remove(entering)
+}
+
+/// The selection and scroll offset that bring back `snapshot` in
+/// `filtered`: on the same item when it is still listed, otherwise the top.
+pub fn restored_position(
+    snapshot: &ModeSnapshot,
+    filtered: &[(LaunchItem, i32)],
+) -> (usize, usize) {
+    let found = snapshot
+        .selected
+        .as_ref()
+        .and_then(|name| filtered.iter().position(|(item, _)| &item.name == name));
+    match found {
+        // Drawing scrolls further when the item moved below the old view
+        Some(sel) => (sel, snapshot.start_index.min(sel)),
+        None => (0, 0),
+    }
+}
+
+/// Where the config was read from, and the command-line settings laid over
+/// it again after every reload.
+pub struct ConfigSource {
+    pub path: Option<PathBuf>,
+    pub overrides: Box<dyn Fn(&mut Config)>,
+}
+
+/// Runs the launcher window until it closes. Returns whether a line was
+/// accepted, which only happens in dmenu mode.
+pub fn run_ui(
+    mut cfg: Config,
+    source: ConfigSource,
+    mut mode: Mode,
+    instance: Option<InstanceLock>,
+    daemon: bool,
+    conn: RustConnection,
+    screen_num: usize,
+) -> Result<bool, LauncherError> {
+    // Shared with the panic hook so it can release the grab
+    let conn = Arc::new(conn);
+    let screen = &conn.setup().roots[screen_num];
+    let win = conn.generate_id()?;
+    init_pixel_format(conn.setup(), screen_num)?;
+
+    cfg.apply_scale(scale_factor(&conn, screen, &cfg));
+    let mut monitor = select_monitor(&conn, screen, cfg.monitor.as_deref());
+    let (mut width, mut height) = cfg.window_size(monitor.width, monitor.height);
+    // Actual window height; differs from `height` only with dynamic_height
+    let mut window_height = height;
+    let (x, y) = window_position(&cfg, &monitor, width, height);
+
+    conn.create_window(
+        COPY_FROM_PARENT as u8,
+        win,
+        screen.root,
+        x,
+        y,
+        width,
+        height,
+        cfg.border_width,
+        WindowClass::INPUT_OUTPUT,
+        COPY_FROM_PARENT,
+        &CreateWindowAux::new()
+            .background_pixel(pixel(cfg.theme.bg_color))
+            .border_pixel(pixel(cfg.theme.border_color))
+            .event_mask(
+                EventMask::EXPOSURE
+                    | EventMask::KEY_PRESS
+                    | EventMask::KEY_RELEASE
+                    | EventMask::BUTTON_PRESS
+                    | EventMask::STRUCTURE_NOTIFY
+                    | EventMask::FOCUS_CHANGE,
+            ),
+    )?;
+
+    // From here on every exit path releases the grab and removes the window
+    let mut cleanup = Cleanup {
+        conn: &conn,
+        win,
+        grabbed: false,
+    };
+    let hook_conn = conn.clone();
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = hook_conn.ungrab_keyboard(x11rb::CURRENT_TIME);
+        let _ = hook_conn.destroy_window(win);
+        let _ = hook_conn.flush();
+        default_hook(info);
+    }));
+
+    let atoms = Atoms::new(&conn)?.reply()?;
+    set_window_properties(&conn, win, &atoms, cfg.managed_window)?;
+    if !cfg.managed_window {
+        conn.change_window_attributes(win, &ChangeWindowAttributesAux::new().override_redirect(1))?;
+    }
+
+    // A daemon stays hidden until the first `rufi --show`
+    let mut hidden = daemon;
+    // Played once the opened window has been painted
+    let mut opening = None;
+    if !hidden {
+        opening = open_animation(&conn, screen_num, &cfg)?;
+        if let Some(animation) = &opening {
+            set_open_progress(&conn, win, &atoms, animation, (x, y), 0.0)?;
+        }
+        show_window(&mut cleanup, &cfg)?;
+    }
+
+    let cache = Arc::new(Mutex::new(ItemCache::new(cfg.cache_timeout)));
+    // Set while a background reload of the items is running
+    let reloading = Arc::new(AtomicBool::new(false));
+    let mut loading = true;
+
+    // Start initial load asynchronously to prevent blocking
+    let desktops = current_desktops(cfg.current_desktop.as_deref());
+    let initial_cache = cache.clone();
+    let initial_desktops = desktops.clone();
+    let initial_mode = mode.clone();
+    let initial_cfg = cfg.clone();
+    // Counts as a running reload, so the still expired cache doesn't start another
+    reloading.store(true, Ordering::SeqCst);
+    let initial_reloading = reloading.clone();
+    thread::spawn(move || {
+        let (all_items, failures) = initial_mode.collect(&initial_desktops, &initial_cfg);
+        if let Ok(mut cache_guard) = initial_cache.lock() {
+            cache_guard.update(all_items, failures);
+        }
+        initial_reloading.store(false, Ordering::SeqCst);
+    });
+
+    let mut query = String::new();
+    let mut sel = 0usize;
+    let mut start_index = 0usize; // New: start_index
+    // A dead key waiting for the letter it accents
+    let mut dead_key: Option<u32> = None;
+    // Position in `cfg.modes` of the active launcher mode; kept until rufi closes
+    let mut mode_index = 0usize;
+    // Where the modes Ctrl+Tab left were, and the one to bring back once
+    // its items are filtered
+    let mut mode_snapshots: HashMap<LauncherMode, ModeSnapshot> = HashMap::new();
+    let mut restoring: Option<ModeSnapshot> = None;
+    // Results shown in the last repaint, for the Alt+digit shortcuts
+    let mut visible_count = 0usize;
+    let keymap = setup_keyboard_map(&conn)?;
+    let font = query_font_metrics(&conn, win)?;
+    // Shared by every draw call; each one sets the colors it needs
+    let gc = conn.generate_id()?;
+    conn.create_gc(gc, win, &CreateGCAux::new())?;
+    // Opened regardless of bold_selection so a config reload can turn it on
+    let bold_gc = match open_bold_font(&conn, win, font)? {
+        Some(bold_font) => {
+            let bold_gc = conn.generate_id()?;
+            conn.create_gc(bold_gc, win, &CreateGCAux::new().font(bold_font))?;
+            conn.close_font(bold_font)?;
+            Some(bold_gc)
+        }
+        None => {
+            if cfg.bold_selection {
+                eprintln!("No bold variant of the font, drawing the selection in regular weight");
+            }
+            None
+        }
+    };
+    let waker = Waker::connect(win)?;
+    let icon_waker = waker.clone();
+    let icon_loader = IconLoader::spawn(cfg.icon_cache_size, move || icon_waker.wake());
+    let summaries = CommandSummaries::new(waker.clone());
+
+    let reload_requested = Arc::new(AtomicBool::new(false));
+    spawn_reload_watcher(waker.clone(), reload_requested.clone())?;
+    let watched_files = Arc::new(Mutex::new(config_files(source.path.as_deref(), &cfg)));
+    spawn_config_watcher(
+        waker.clone(),
+        reload_requested.clone(),
+        watched_files.clone(),
+    );
+
+    let close_requested = Arc::new(AtomicBool::new(false));
+    let show_requested = Arc::new(AtomicBool::new(false));
+    if let Some(instance) = &instance {
+        spawn_toggle_watcher(
+            instance,
+            waker.clone(),
+            close_requested.clone(),
+            show_requested.clone(),
+        )?;
+    }
+
+    let mut history = History::load();
+    // Past queries, and which one Ctrl+Up/Down last put in the query bar
+    let mut query_history = History::load_queries();
+    let mut query_index: Option<usize> = None;
+    // Whether the window needs a repaint, and the item cache generation and
+    // results it was last painted from
+    let mut dirty = true;
+    // Set when only the caret blinked, which redraws just the query bar
+    let mut caret_dirty = false;
+    let mut seen_generation = 0;
+    let mut filtered = Vec::new();
+
+    // Keeps the caret solid while typing
+    let mut last_input = Instant::now();
+
+    // Transient footer message and when it stops being shown
+    let mut status: Option<(String, Instant)> = None;
+    // Destructive power action waiting for a Yes, listed instead of the items
+    let mut pending_confirm: Option<LaunchItem> = None;
+    // Entries a script printed when one of its entries was picked
+    let mut script_reply: Option<Vec<LaunchItem>> = None;
+    // Whether a line was printed in dmenu mode, for the exit status
+    let mut accepted = false;
+
+    eprintln!("rufi launcher started");
+
+    loop {
+        if hidden {
+            // Hidden daemon: sleep until asked to show, keeping the items
+            // fresh on the usual cache timeout
+            loop {
+                let toggled = close_requested.swap(false, Ordering::SeqCst);
+                if show_requested.swap(false, Ordering::SeqCst) || toggled {
+                    break;
+                }
+                if cache.lock().unwrap().is_expired() {
+                    spawn_item_reload(&cache, &reloading, &mode, &desktops, &cfg, &waker);
+                }
+                // Anything arriving while unmapped is stale, e.g. our own UnmapNotify
+                let timeout = Duration::from_secs(cfg.cache_timeout.max(1));
+                wait_for_event_timeout(&conn, timeout)?;
+            }
+
+            query.clear();
+            sel = 0;
+            start_index = 0;
+            mode_index = 0;
+            mode_snapshots.clear();
+            restoring = None;
+            dead_key = None;
+            status = None;
+            pending_confirm = None;
+            script_reply = None;
+            query_index = None;
+            last_input = Instant::now();
+            dirty = true;
+
+            // The pointer may be on another monitor than last time
+            monitor = select_monitor(&conn, screen, cfg.monitor.as_deref());
+            (width, height) = cfg.window_size(monitor.width, monitor.height);
+            window_height = height;
+            let (x, y) = window_position(&cfg, &monitor, width, height);
+            conn.configure_window(
+                win,
+                &ConfigureWindowAux::new()
+                    .x(x as i32)
+                    .y(y as i32)
+                    .width(width as u32)
+                    .height(height as u32),
+            )?;
+            opening = open_animation(&conn, screen_num, &cfg)?;
+            if let Some(animation) = &opening {
+                set_open_progress(&conn, win, &atoms, animation, (x, y), 0.0)?;
             }
+            show_window(&mut cleanup, &cfg)?;
+        }
 
-            let text_start_x = if cfg.show_icons && item.icon.is_some() {
-                let icon_size = cfg.item_height - 8; // A bit smaller than item_height
-                let icon_x = cfg.padding as i16 + 4;
-                let icon_y = y as i16 + 4;
-                if let Some(icon_path) = &item.icon {
-                    if let Err(e) = draw_icon(&conn, win, icon_x, icon_y, icon_size, icon_path) {
-                        eprintln!("Failed to draw icon for {}: {}", item.display_name, e);
+        loop {
+            if close_requested.swap(false, Ordering::SeqCst) {
+                break;
+            }
+            // Already showing; the window is left as it is
+            show_requested.store(false, Ordering::SeqCst);
+
+            if reload_requested.swap(false, Ordering::SeqCst) {
+                let reloaded = source
+                    .path
+                    .as_deref()
+                    .and_then(|path| match Config::load(path) {
+                        Ok(new_cfg) => Some((path, new_cfg)),
+                        Err(e) => {
+                            eprintln!(
+                                "Keeping the current configuration, {} is invalid: {}",
+                                path.display(),
+                                e
+                            );
+                            let message = format!(
+                                "Config not reloaded: {}",
+                                single_line_error(&e.to_string())
+                            );
+                            status = Some((message, Instant::now() + STATUS_TIMEOUT));
+                            waker.wake_after(STATUS_TIMEOUT);
+                            dirty = true;
+                            None
+                        }
+                    });
+                if let Some((path, new_cfg)) = reloaded {
+                    cfg = new_cfg;
+                    // The file doesn't know about the command line, so its
+                    // flags still win over the reloaded values
+                    mode.apply_prompt(&mut cfg);
+                    (source.overrides)(&mut cfg);
+                    if let Ok(mut files) = watched_files.lock() {
+                        *files = config_files(source.path.as_deref(), &cfg);
+                    }
+                    cfg.apply_scale(scale_factor(&conn, screen, &cfg));
+                    if let Mode::Power(power) = &mut mode {
+                        *power = cfg.power.clone();
                     }
+                    monitor = select_monitor(&conn, screen, cfg.monitor.as_deref());
+                    (width, height) = cfg.window_size(monitor.width, monitor.height);
+                    window_height = height;
+                    let (x, y) = window_position(&cfg, &monitor, width, height);
+                    conn.configure_window(
+                        win,
+                        &ConfigureWindowAux::new()
+                            .x(x as i32)
+                            .y(y as i32)
+                            .width(width as u32)
+                            .height(height as u32)
+                            .border_width(cfg.border_width as u32),
+                    )?;
+                    conn.change_window_attributes(
+                        win,
+                        &ChangeWindowAttributesAux::new()
+                            .background_pixel(pixel(cfg.theme.bg_color))
+                            .border_pixel(pixel(cfg.theme.border_color)),
+                    )?;
+                    eprintln!("Configuration reloaded from {}", path.display());
+                    dirty = true;
                 }
-                (icon_x + icon_size as i16 + 8) as i16 // 8px gap after icon
-            } else {
-                (cfg.padding + 12) as i16 // Default text start
-            };
+            }
 
-            let type_indicator = match item.item_type {
-                crate::commands::ItemType::Application => "App:",
-                crate::commands::ItemType::Command => "Cmd:",
-            };
+            let cache_guard = cache.lock().unwrap();
+            let items = cache_guard.get();
 
-            let display_text = format!("{} {}", type_indicator, item.display_name);
+            // Loaded once the first scan is in, even if it found nothing
+            // (dmenu with empty input still takes free text)
+            if loading && (!items.is_empty() || cache_guard.generation() > 0) {
+                loading = false;
+            }
 
-            let display_text_y = (y + cfg.padding) as i16; // Position name with padding from top of current_item_height
+            // The other modes list their items unfiltered. The modulo keeps
+            // the index valid after a reload shortened `modes`.
+            let launcher_mode = match mode {
+                Mode::Launcher => {
+                    let modes = cfg.launcher_modes();
+                    Some(modes[mode_index % modes.len()].clone())
+                }
+                _ => None,
+            };
+            // Processes come and go faster than the cache times out
+            let listing_processes = launcher_mode == Some(LauncherMode::Kill);
+
+            if cache_guard.generation() != seen_generation {
+                seen_generation = cache_guard.generation();
+                dirty = true;
+                if listing_processes {
+                    waker.wake_after(process::REFRESH_INTERVAL);
+                }
+                if !cache_guard.failures.is_empty() {
+                    let message = cache_guard.failures.join("; ");
+                    eprintln!("Failed to list items: {}", message);
+                    status = Some((message, Instant::now() + STATUS_TIMEOUT));
+                    waker.wake_after(STATUS_TIMEOUT);
+                }
+            }
 
-            draw_text(
-                &conn,
-                win,
-                text_start_x,
-                display_text_y,
-                &display_text,
-                item_fg_color,
-                item_bg_color,
-            )?;
+            if cache_guard.is_expired() {
+                spawn_item_reload(&cache, &reloading, &mode, &desktops, &cfg, &waker);
+            } else if listing_processes
+                && cache_guard.processes_older_than(process::REFRESH_INTERVAL)
+            {
+                spawn_process_reload(&cache, &reloading, &waker);
+            }
 
-            if has_desc {
-                let desc = item.description.as_ref().unwrap();
-                let desc = if desc.len() > 60 {
-                    format!("{}...", &desc[..57])
+            if dirty {
+                // History is only kept for the launcher
+                let recent: &[String] = if cfg.recent_on_empty && matches!(mode, Mode::Launcher) {
+                    history.recent()
                 } else {
-                    desc.clone()
+                    &[]
                 };
-
-                let desc_color = if is_selected {
-                    item_fg_color
+                let browsed = (launcher_mode == Some(LauncherMode::Files))
+                    .then(|| files::browse(&query, &cfg))
+                    .flatten();
+                filtered = if let Some(action) = &pending_confirm {
+                    let choices = power::confirmation_items(action);
+                    fuzzy::fuzzy_search(&query, &choices, cfg.max_results, &[])
+                } else if let Some(entries) = &script_reply {
+                    fuzzy::fuzzy_search(&query, entries, cfg.max_results, &[])
+                } else if query_too_short(&cfg, &query) {
+                    Vec::new()
+                } else if let Some((entries, leaf)) = browsed {
+                    // A path in the files mode lists that directory
+                    fuzzy::fuzzy_search(leaf, &entries, cfg.max_results, &[])
+                } else if let Some((name, rest)) =
+                    prefixed_script(&cfg, &query).filter(|_| launcher_mode.is_some())
+                {
+                    let mode = LauncherMode::Script(name.to_string());
+                    let subset: Vec<LaunchItem> = items
+                        .iter()
+                        .filter(|item| mode_matches(&mode, item))
+                        .cloned()
+                        .collect();
+                    fuzzy::fuzzy_search(rest, &subset, cfg.max_results, &[])
+                } else if let Some(launcher_mode) = &launcher_mode {
+                    let subset: Vec<LaunchItem> = items
+                        .iter()
+                        .filter(|item| mode_matches(launcher_mode, item))
+                        .cloned()
+                        .collect();
+                    fuzzy::fuzzy_search(&query, &subset, cfg.max_results, recent)
                 } else {
-                    // Dimmed description color
-                    let r = ((cfg.theme.fg_color >> 16) & 0xFF) * 3 / 4;
-                    let g = ((cfg.theme.fg_color >> 8) & 0xFF) * 3 / 4;
-                    let b = (cfg.theme.fg_color & 0xFF) * 3 / 4;
-                    (r << 16) | (g << 8) | b
+                    fuzzy::fuzzy_search(&query, items, cfg.max_results, recent)
                 };
+                let listing_items = pending_confirm.is_none() && script_reply.is_none();
+                if matches!(mode, Mode::Launcher) && listing_items && !query_too_short(&cfg, &query)
+                {
+                    if let Some(result) = calc::answer(&query) {
+                        filtered.insert(0, (answer_item(&query, result), 0));
+                    }
+                    // A bang searches even when items match; otherwise the
+                    // web is only offered when no application or command did
+                    let searchable = matches!(
+                        launcher_mode,
+                        Some(LauncherMode::Combi | LauncherMode::Drun | LauncherMode::Run)
+                    );
+                    if let Some(search) = web_search::bang_item(&cfg.web_search, &query) {
+                        filtered.insert(0, (search, 0));
+                    } else if filtered.is_empty() && searchable {
+                        filtered.extend(
+                            web_search::fallback_item(&cfg.web_search, &query)
+                                .map(|item| (item, 0)),
+                        );
+                    }
+                }
+                if let Some(snapshot) = restoring.take() {
+                    (sel, start_index) = restored_position(&snapshot, &filtered);
+                }
+            }
 
-                let desc_y = (y + cfg.padding + cfg.font_size + cfg.padding / 4) as i16; // Position description below name
+            // Show loading message if still loading and no items
+            if loading && items.is_empty() {
+                draw_rect(&conn, win, gc, 0, 0, width, height, cfg.theme.bg_color)?;
                 draw_text(
                     &conn,
                     win,
-                    text_start_x,
-                    desc_y,
-                    &desc,
-                    desc_color,
-                    item_bg_color,
+                    gc,
+                    (width / 2 - 80) as i16,
+                    (height / 2) as i16,
+                    "Loading applications...",
+                    cfg.theme.fg_color,
+                    cfg.theme.bg_color,
                 )?;
+                conn.flush()?;
+                // Open on the loading screen rather than leave the window
+                // transparent or off its place until the items arrive
+                if let Some(animation) = opening.take() {
+                    let origin = window_position(&cfg, &monitor, width, window_height);
+                    animate_open(&conn, win, &atoms, &animation, origin, cfg.animation_ms)?;
+                }
+                drop(cache_guard);
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                continue;
             }
-            current_y += current_item_height;
-        }
 
-        conn.flush()?;
+            sel = sel.min(filtered.len().saturating_sub(1));
+            let grid_columns = cfg.grid_columns();
 
-        let ev = conn.wait_for_event()?;
-        match ev {
-            Event::FocusOut(_) => {
-                // Attempt to regain focus once
-                conn.set_input_focus(InputFocus::POINTER_ROOT, win, x11rb::CURRENT_TIME)?;
-                conn.flush()?;
-            }
-            Event::ButtonPress(_) => {
-                // Close on any mouse click
-                break;
+            if status
+                .as_ref()
+                .is_some_and(|(_, until)| Instant::now() >= *until)
+            {
+                status = None;
             }
-            Event::UnmapNotify(_) => {
-                // Window was unmapped, exit gracefully
-                break;
-            }
-            Event::KeyPress(k) => {
-                let code = k.detail;
-                match code {
-                    9 => break, // ESC
-                    36 => {
-                        // Enter
-                        if let Some((item, _)) = filtered.get(sel) {
-                            println!("Launching: {} ({})", item.display_name, item.command);
-                            if let Err(e) = launch_item(item) {
-                                eprintln!("Failed to launch {}: {}", item.display_name, e);
-                            }
+            let (caret_visible, caret_timeout) = caret_phase(last_input.elapsed());
+
+            // Only repaint when something visible changed
+            if dirty || caret_dirty {
+                let footer_h = if (cfg.show_footer || pending_confirm.is_some() || status.is_some())
+                    && cfg.layout != Layout::Horizontal
+                {
+                    font.ascent + font.descent + cfg.padding
+                } else {
+                    0
+                };
+                let hints_h = if cfg.show_hints && cfg.layout != Layout::Horizontal {
+                    font.ascent + font.descent + cfg.padding
+                } else {
+                    0
+                };
+
+                let preview_w = if cfg.show_preview
+                    && cfg.layout == Layout::Vertical
+                    && width >= PREVIEW_MIN_WIDTH
+                {
+                    width * 2 / 5
+                } else {
+                    0
+                };
+
+                let frame = Frame {
+                    conn: &conn,
+                    win,
+                    gc,
+                    bold_gc,
+                    cfg: &cfg,
+                    font,
+                    width: width - preview_w,
+                    height: height.saturating_sub(footer_h + hints_h),
+                    query: &query,
+                    filtered: &filtered,
+                    sel,
+                    caret_visible,
+                    filter_label: launcher_mode.as_ref().and_then(mode_label),
+                };
+                caret_dirty = false;
+                if !dirty {
+                    // Nothing but the caret changed, so leave the rows be
+                    match cfg.layout {
+                        Layout::Vertical => draw_query_bar(&frame)?,
+                        Layout::Horizontal => {
+                            visible_count = draw_horizontal(&frame, &mut start_index)?
                         }
-                        break;
                     }
-                    111 => {
-                        // Up
-                        if sel > 0 {
-                            sel -= 1;
+                    conn.flush()?;
+                } else {
+                    dirty = false;
+                    let mut wanted_icons = Vec::new();
+                    match cfg.layout {
+                        Layout::Vertical if grid_columns > 1 => {
+                            visible_count = draw_grid(
+                                &frame,
+                                &mut start_index,
+                                &icon_loader,
+                                &mut wanted_icons,
+                            )?
                         }
-                    }
-                    116 => {
-                        // Down
-                        if !filtered.is_empty() && sel + 1 < filtered.len() {
-                            sel += 1;
+                        Layout::Vertical => {
+                            let list_h;
+                            (list_h, visible_count) = draw_vertical(
+                                &frame,
+                                &mut start_index,
+                                &icon_loader,
+                                &mut wanted_icons,
+                            )?;
+                            if cfg.dynamic_height {
+                                let query_h = cfg.item_height + cfg.padding;
+                                let needed = (query_h
+                                    + cfg.padding * 3
+                                    + list_h.max(cfg.item_height)
+                                    + footer_h
+                                    + hints_h)
+                                    .min(height);
+                                // Grow right away, but only shrink once more than a row would be
+                                // left empty so the window doesn't bounce while typing
+                                let slack = cfg.item_height
+                                    + cfg.font_size
+                                    + cfg.row_text_padding() / 2
+                                    + cfg.row_spacing;
+                                if needed > window_height || needed + slack < window_height {
+                                    window_height = needed;
+                                    let (x, y) =
+                                        window_position(&cfg, &monitor, width, window_height);
+                                    conn.configure_window(
+                                        win,
+                                        &ConfigureWindowAux::new()
+                                            .x(x as i32)
+                                            .y(y as i32)
+                                            .height(window_height as u32),
+                                    )?;
+                                }
+                            }
                         }
+                        Layout::Horizontal => {
+                            visible_count = draw_horizontal(&frame, &mut start_index)?
+                        }
+                    }
+                    if preview_w > 0 {
+                        draw_preview(
+                            &frame,
+                            preview_w,
+                            &icon_loader,
+                            &summaries,
+                            &mut wanted_icons,
+                        )?;
+                    }
+                    icon_loader.request(wanted_icons);
+
+                    let bottom = if cfg.dynamic_height {
+                        window_height
+                    } else {
+                        height
+                    };
+                    if hints_h > 0 {
+                        draw_hints(
+                            &frame,
+                            bottom.saturating_sub(hints_h),
+                            &key_hints(&mode, &cfg, visible_count),
+                        )?;
+                    }
+                    if footer_h > 0 {
+                        let footer_y = bottom.saturating_sub(hints_h + footer_h);
+                        let confirm = pending_confirm
+                            .as_ref()
+                            .map(|item| format!("{}? Escape goes back", item.display_name));
+                        let message = confirm
+                            .as_deref()
+                            .or(status.as_ref().map(|(msg, _)| msg.as_str()));
+                        draw_footer(&frame, footer_y, message)?;
+                    }
+
+                    // Drawn last so nothing paints over it
+                    draw_outline(
+                        &conn,
+                        win,
+                        gc,
+                        0,
+                        0,
+                        width,
+                        window_height,
+                        cfg.inner_border_width,
+                        cfg.theme.border_color,
+                    )?;
+
+                    conn.flush()?;
+
+                    // The keyboard is already grabbed, so typing isn't lost
+                    if let Some(animation) = opening.take() {
+                        let origin = window_position(&cfg, &monitor, width, window_height);
+                        animate_open(&conn, win, &atoms, &animation, origin, cfg.animation_ms)?;
                     }
-                    22 => {
-                        // Backspace
-                        query.pop();
-                        sel = 0;
-                        start_index = 0; // Reset start_index on query change
+                }
+            }
+
+            let Some(ev) = wait_for_event_timeout(&conn, caret_timeout)? else {
+                // Timed out: redraw to blink the caret
+                caret_dirty = true;
+                continue;
+            };
+            match ev {
+                Event::Expose(e) if e.count == 0 => dirty = true,
+                Event::ConfigureNotify(e)
+                    if e.window == win
+                        && (e.width != width || (!cfg.dynamic_height && e.height != height)) =>
+                {
+                    // Resized by someone else, e.g. a WM that ignores
+                    // override_redirect: lay out for the new size from now on
+                    width = e.width;
+                    // With dynamic_height the height is ours to manage, and a
+                    // notify for one of our own earlier resizes may still arrive
+                    if !cfg.dynamic_height {
+                        height = e.height;
+                        window_height = e.height;
+                    }
+                    dirty = true;
+                }
+                Event::ClientMessage(e)
+                    if e.type_ == atoms.WM_PROTOCOLS
+                        && e.data.as_data32()[0] == atoms.WM_DELETE_WINDOW =>
+                {
+                    // Closed by the window manager
+                    break;
+                }
+                Event::ClientMessage(_) => {
+                    // Sent by the waker when icons, items or the status changed
+                    dirty = true;
+                }
+                Event::FocusOut(e) => {
+                    if !cfg.managed_window {
+                        // Attempt to regain focus once
+                        conn.set_input_focus(InputFocus::POINTER_ROOT, win, x11rb::CURRENT_TIME)?;
+                        conn.flush()?;
+                    } else if e.mode == NotifyMode::NORMAL && e.detail != NotifyDetail::INFERIOR {
+                        // The WM moved focus elsewhere; focus shuffles caused by
+                        // grabs or within our own window don't count
+                        break;
                     }
-                    50 | 62 => {
-                        // Shift (left/right)
-                        shift_down = true;
+                }
+                Event::ButtonPress(_) => {
+                    // Close on any mouse click
+                    break;
+                }
+                Event::UnmapNotify(_) => {
+                    // Window was unmapped, exit gracefully
+                    break;
+                }
+                Event::KeyPress(k) => {
+                    let code = k.detail;
+                    // Read from the event rather than tracked, so modifiers
+                    // already held when rufi opened count too
+                    let shift_down = k.state.contains(KeyButMask::SHIFT);
+                    let alt_down = k.state.contains(KeyButMask::MOD1);
+                    // AltGr is bound to Mod5 on practically every layout
+                    let level3_down = k.state.contains(KeyButMask::MOD5);
+                    let base_sym = keymap.get(&code).and_then(|syms| syms.first()).copied();
+                    if base_sym.is_some_and(is_level3_shift) {
+                        continue;
                     }
-                    _ => {
-                        if let Some(variations) = keymap.get(&code) {
-                            let variation_index = if shift_down && variations.len() > 1 {
-                                1
+                    let mut launch = None;
+                    // Any key but Ctrl+Up/Down ends browsing past queries
+                    let recalled = query_index.take();
+                    dirty = true;
+                    status = None;
+                    last_input = Instant::now();
+                    match code {
+                        9 if pending_confirm.is_some() => {
+                            // ESC backs out of a confirmation to the list
+                            pending_confirm = None;
+                            query.clear();
+                            sel = 0;
+                            start_index = 0;
+                        }
+                        9 if cfg.escape_clears_first && !query.is_empty() => {
+                            // ESC clears the query first, and closes on the next press
+                            query.clear();
+                            sel = 0;
+                            start_index = 0;
+                        }
+                        9 => break,                     // ESC
+                        36 | 104 => launch = Some(sel), // Enter, KP_Enter
+                        10..=18 if alt_down => {
+                            // Alt+1..9 launches the nth visible result
+                            let offset = (code - 10) as usize;
+                            if offset < visible_count {
+                                launch = Some(start_index + offset);
+                            }
+                        }
+                        111 if k.state.contains(KeyButMask::CONTROL) => {
+                            // Ctrl+Up recalls the previous query
+                            let older = recalled.map_or(0, |i| i + 1);
+                            query_index = match query_history.recent().get(older) {
+                                Some(past) => {
+                                    query = past.clone();
+                                    Some(older)
+                                }
+                                None => recalled,
+                            };
+                            sel = 0;
+                            start_index = 0;
+                        }
+                        116 if k.state.contains(KeyButMask::CONTROL) => {
+                            // Ctrl+Down steps back towards the newest, then an empty query
+                            match recalled {
+                                Some(0) => query.clear(),
+                                Some(i) => {
+                                    query = query_history.recent()[i - 1].clone();
+                                    query_index = Some(i - 1);
+                                }
+                                None => {}
+                            }
+                            sel = 0;
+                            start_index = 0;
+                        }
+                        111 | 116 if cfg.layout == Layout::Horizontal => {}
+                        113 if cfg.layout == Layout::Horizontal => {
+                            // Left
+                            sel = sel.saturating_sub(1);
+                        }
+                        114 if cfg.layout == Layout::Horizontal => {
+                            // Right
+                            if sel + 1 < filtered.len() {
+                                sel += 1;
+                            }
+                        }
+                        111 if grid_columns > 1 => {
+                            // Up a row
+                            if sel >= grid_columns {
+                                sel -= grid_columns;
+                            }
+                        }
+                        116 if grid_columns > 1 => {
+                            // Down a row, or to the last item from a partial row above it
+                            if sel + grid_columns < filtered.len() {
+                                sel += grid_columns;
+                            } else if sel / grid_columns
+                                < filtered.len().saturating_sub(1) / grid_columns
+                            {
+                                sel = filtered.len() - 1;
+                            }
+                        }
+                        113 if grid_columns > 1 => {
+                            // Left
+                            sel = sel.saturating_sub(1);
+                        }
+                        114 if grid_columns > 1 => {
+                            // Right
+                            if sel + 1 < filtered.len() {
+                                sel += 1;
+                            }
+                        }
+                        111 => {
+                            // Up
+                            if sel > 0 {
+                                sel -= 1;
+                            }
+                        }
+                        116 => {
+                            // Down
+                            if !filtered.is_empty() && sel + 1 < filtered.len() {
+                                sel += 1;
+                            }
+                        }
+                        23 if k.state.contains(KeyButMask::CONTROL) => {
+                            // Ctrl+Tab cycles through the launcher modes
+                            let modes = cfg.launcher_modes();
+                            let leaving = modes[mode_index % modes.len()].clone();
+                            mode_index = (mode_index + 1) % modes.len();
+                            let entering = &modes[mode_index];
+                            script_reply = None;
+                            if !cfg.reset_on_mode_switch {
+                                let left = ModeSnapshot {
+                                    query: query.clone(),
+                                    selected: filtered.get(sel).map(|(item, _)| item.name.clone()),
+                                    start_index,
+                                };
+                                // A mode not visited yet starts with the query as typed
+                                restoring = switch_mode_snapshot(
+                                    &mut mode_snapshots,
+                                    leaving,
+                                    left,
+                                    entering,
+                                );
+                                if let Some(snapshot) = &restoring {
+                                    query = snapshot.query.clone();
+                                }
+                            }
+                            sel = 0;
+                            start_index = 0;
+                        }
+                        23 if !filtered.is_empty() => {
+                            // Tab / Shift+Tab cycle through the results, wrapping
+                            sel = if shift_down {
+                                sel.checked_sub(1).unwrap_or(filtered.len() - 1)
                             } else {
-                                0
+                                (sel + 1) % filtered.len()
                             };
-                            if let Some(ch) = variations.get(variation_index) {
-                                query.push_str(ch);
+                        }
+                        23 => {}
+                        _ if k.state.contains(KeyButMask::CONTROL)
+                            && base_sym == Some(KEYSYM_C) =>
+                        {
+                            // Ctrl+C copies the selection instead of launching it.
+                            // Matched by keysym, as the C key moves between layouts
+                            if let Some((item, _)) = filtered.get(sel) {
+                                let text = copied_text(cfg.copy_field, item);
+                                match copy_to_clipboard(text, &cfg.clipboard_command) {
+                                    Ok(()) => break,
+                                    Err(e) => {
+                                        eprintln!("Failed to copy {}: {}", text, e);
+                                        if cfg.show_footer {
+                                            let message = format!("Failed to copy: {}", e);
+                                            status =
+                                                Some((message, Instant::now() + STATUS_TIMEOUT));
+                                            waker.wake_after(STATUS_TIMEOUT);
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        22 if k.state.contains(KeyButMask::CONTROL) => {
+                            // Ctrl-Backspace
+                            delete_word_backward(&mut query);
+                            sel = 0;
+                            start_index = 0;
+                        }
+                        22 => {
+                            // Backspace
+                            query.pop();
+                            sel = 0;
+                            start_index = 0; // Reset start_index on query change
+                        }
+                        _ => {
+                            let keysym = keymap.get(&code).and_then(|syms| {
+                                // NumLock is Mod2 on practically every layout
+                                let num_lock = k.state.contains(KeyButMask::MOD2);
+                                keysym_for_level(syms, shift_down, level3_down, num_lock)
+                            });
+                            if let Some(keysym) = keysym {
+                                if is_dead_key(keysym) {
+                                    // The same dead key twice types its accent
+                                    if dead_key.replace(keysym) == Some(keysym) {
+                                        dead_key = None;
+                                        query.push_str(&compose_dead_key(keysym, ' '));
+                                        sel = 0;
+                                    }
+                                } else if let Some(ch) = keysym_to_char(keysym) {
+                                    match dead_key.take() {
+                                        Some(dead) => query.push_str(&compose_dead_key(dead, ch)),
+                                        None => query.push(ch),
+                                    }
+                                    sel = 0;
+                                }
+                            }
+                        }
+                    }
+
+                    if let Some(index) = launch {
+                        if let Mode::Dmenu(dmenu) = &mode {
+                            // Like dmenu, text matching no line is printed as typed
+                            let picked = filtered.get(index).map(|(item, _)| item);
+                            println!("{}", dmenu.output(picked, &query));
+                            accepted = true;
+                            break;
+                        }
+                        if let Some((item, _)) = filtered.get(index) {
+                            if power::is_cancel(item) {
+                                pending_confirm = None;
+                                query.clear();
+                                sel = 0;
+                                start_index = 0;
+                                continue;
+                            }
+                            if power::needs_confirmation(item) && pending_confirm.is_none() {
+                                // Ask Yes/No with the list instead of running it
+                                pending_confirm = Some(item.clone());
+                                query.clear();
                                 sel = 0;
+                                start_index = 0;
+                                continue;
+                            }
+                            if files::is_dir(item) {
+                                // Directories open in place instead of in a file manager
+                                if let Some(path) = files::descend_query(item) {
+                                    query = path;
+                                    sel = 0;
+                                    start_index = 0;
+                                }
+                                continue;
+                            }
+                            let launched = match item.item_type {
+                                ItemType::Command if item.command.is_empty() => {
+                                    // Entries without an exec from `--run` are printed
+                                    println!("{}", item.display_name);
+                                    break;
+                                }
+                                ItemType::Character => {
+                                    println!("{}", item.command);
+                                    copy_to_clipboard(&item.command, &cfg.clipboard_command)
+                                }
+                                ItemType::Window(id) => window::activate(id),
+                                ItemType::Process(pid) => {
+                                    // Shift+Enter kills what ignores a polite SIGTERM
+                                    if let Err(e) = process::kill(pid, shift_down) {
+                                        // Likely exited since it was listed
+                                        let message = format!(
+                                            "Failed to signal {}: {}",
+                                            item.display_name, e
+                                        );
+                                        eprintln!("{}", message);
+                                        status = Some((message, Instant::now() + STATUS_TIMEOUT));
+                                        waker.wake_after(STATUS_TIMEOUT);
+                                        continue;
+                                    }
+                                    Ok(())
+                                }
+                                ItemType::Application if cfg.focus_running => {
+                                    match window::find_by_class(&window_classes(item)) {
+                                        Ok(Some(id)) => window::activate(id),
+                                        found => {
+                                            if let Err(e) = found {
+                                                eprintln!("Failed to look for open windows: {}", e);
+                                            }
+                                            println!(
+                                                "Launching: {} ({})",
+                                                item.display_name, item.command
+                                            );
+                                            launch_item(item, &cfg, false)
+                                        }
+                                    }
+                                }
+                                ItemType::Script(ref name) => match cfg.script_mode(name) {
+                                    Some(mode) => match script::select(mode, item, &cfg) {
+                                        Ok(Some(entries)) => {
+                                            // The script answered with a new list
+                                            script_reply = Some(entries);
+                                            query.clear();
+                                            sel = 0;
+                                            start_index = 0;
+                                            continue;
+                                        }
+                                        Ok(None) => Ok(()),
+                                        Err(e) => Err(e),
+                                    },
+                                    None => {
+                                        Err(LauncherError::Other(format!("unknown mode {}", name)))
+                                    }
+                                },
+                                ItemType::Answer if shift_down => {
+                                    // Shift+Enter copies and stays open for the next sum
+                                    if let Err(e) =
+                                        copy_to_clipboard(&item.command, &cfg.clipboard_command)
+                                    {
+                                        eprintln!("Failed to copy {}: {}", item.command, e);
+                                    } else if cfg.show_footer {
+                                        let message = format!("Copied {}", item.command);
+                                        status = Some((message, Instant::now() + STATUS_TIMEOUT));
+                                        waker.wake_after(STATUS_TIMEOUT);
+                                    }
+                                    continue;
+                                }
+                                ItemType::Answer => {
+                                    println!("{}", item.command);
+                                    copy_to_clipboard(&item.command, &cfg.clipboard_command)
+                                }
+                                ItemType::Command if is_path_command(item) => {
+                                    // Shift+Enter opens a terminal for console programs
+                                    let in_terminal = shift_down || cfg.commands_in_terminal;
+                                    println!("Launching: {} ({})", item.display_name, item.command);
+                                    launch_item(item, &cfg, in_terminal)
+                                }
+                                _ => {
+                                    println!("Launching: {} ({})", item.display_name, item.command);
+                                    launch_item(item, &cfg, false)
+                                }
+                            };
+                            // Windows and processes come and go and answers are
+                            // one-offs, so none are worth remembering
+                            let remembered = !matches!(
+                                item.item_type,
+                                ItemType::Window(_)
+                                    | ItemType::Answer
+                                    | ItemType::Process(_)
+                                    | ItemType::Web
+                            );
+                            if launched.is_ok() && remembered && matches!(mode, Mode::Launcher) {
+                                if let Err(e) = history.record(&item.name) {
+                                    eprintln!("Failed to save history: {}", e);
+                                }
+                            }
+                            if launched.is_ok() && matches!(mode, Mode::Launcher) {
+                                let searched = query.trim();
+                                if !searched.is_empty() {
+                                    if let Err(e) = query_history.record(searched) {
+                                        eprintln!("Failed to save query history: {}", e);
+                                    }
+                                }
+                            }
+                            if let Err(e) = launched {
+                                eprintln!("Failed to launch {}: {}", item.display_name, e);
+                                // Keep the window open so the error can be read
+                                if cfg.show_footer {
+                                    pending_confirm = None;
+                                    let message =
+                                        format!("Failed to launch {}: {}", item.display_name, e);
+                                    status = Some((message, Instant::now() + STATUS_TIMEOUT));
+                                    waker.wake_after(STATUS_TIMEOUT);
+                                    continue;
+                                }
                             }
                         }
+                        break;
                     }
                 }
+                _ => {}
             }
-            Event::KeyRelease(k) => {
-                if k.detail == 50 || k.detail == 62 {
-                    shift_down = false;
-                }
-            }
-            _ => {}
         }
+
+        if !daemon {
+            break;
+        }
+        // Cancelling or launching only hides a daemon's window
+        hide_window(&mut cleanup)?;
+        hidden = true;
     }
 
-    Ok(())
+    conn.free_gc(gc)?;
+    if let Some(bold_gc) = bold_gc {
+        conn.free_gc(bold_gc)?;
+    }
+    drop(cleanup);
+    // Restore the default hook, which also releases our connection handle
+    drop(std::panic::take_hook());
+    Ok(accepted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str) -> LaunchItem {
+        LaunchItem {
+            name: name.to_string(),
+            display_name: name.to_string(),
+            command: name.to_string(),
+            description: None,
+            generic_name: None,
+            keywords: Vec::new(),
+            icon: None,
+            working_dir: None,
+            source: None,
+            wm_class: None,
+            needs_terminal: false,
+            item_type: ItemType::Command,
+        }
+    }
+
+    fn listed(names: &[&str]) -> Vec<(LaunchItem, i32)> {
+        names.iter().map(|name| (item(name), 0)).collect()
+    }
+
+    fn snapshot(selected: Option<&str>, start_index: usize) -> ModeSnapshot {
+        ModeSnapshot {
+            query: "fi".to_string(),
+            selected: selected.map(String::from),
+            start_index,
+        }
+    }
+
+    #[test]
+    fn restores_the_same_selection_and_scroll() {
+        let filtered = listed(&["a", "b", "c", "d", "e"]);
+        assert_eq!(
+            restored_position(&snapshot(Some("d"), 2), &filtered),
+            (3, 2)
+        );
+    }
+
+    #[test]
+    fn follows_the_selected_item_when_it_moved() {
+        let filtered = listed(&["d", "a", "b"]);
+        // The old scroll offset would hide it, so scroll back up to it
+        assert_eq!(
+            restored_position(&snapshot(Some("d"), 2), &filtered),
+            (0, 0)
+        );
+        let filtered = listed(&["x", "y", "a", "b", "c", "d"]);
+        assert_eq!(
+            restored_position(&snapshot(Some("d"), 2), &filtered),
+            (5, 2)
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_top_when_the_item_is_gone() {
+        let filtered = listed(&["a", "b", "c"]);
+        assert_eq!(
+            restored_position(&snapshot(Some("gone"), 2), &filtered),
+            (0, 0)
+        );
+        assert_eq!(restored_position(&snapshot(None, 1), &filtered), (0, 0));
+        assert_eq!(restored_position(&snapshot(Some("a"), 0), &[]), (0, 0));
+    }
+
+    #[test]
+    fn switching_back_restores_the_mode_left() {
+        let mut snapshots = HashMap::new();
+        let apps = snapshot(Some("firefox"), 3);
+        let entered = switch_mode_snapshot(
+            &mut snapshots,
+            LauncherMode::Drun,
+            apps.clone(),
+            &LauncherMode::Window,
+        );
+        // First visit to the window mode
+        assert_eq!(entered, None);
+
+        let windows = snapshot(Some("term"), 0);
+        let entered = switch_mode_snapshot(
+            &mut snapshots,
+            LauncherMode::Window,
+            windows,
+            &LauncherMode::Drun,
+        );
+        assert_eq!(entered, Some(apps));
+        assert!(snapshots.contains_key(&LauncherMode::Window));
+        assert!(!snapshots.contains_key(&LauncherMode::Drun));
+    }
+
+    fn true_color(depth: u8, bits_per_pixel: u8, big_endian: bool) -> PixelFormat {
+        let (red_mask, green_mask, blue_mask) = if depth == 16 {
+            (0xF800, 0x07E0, 0x001F)
+        } else {
+            (0xFF0000, 0x00FF00, 0x0000FF)
+        };
+        PixelFormat {
+            red_mask,
+            green_mask,
+            blue_mask,
+            depth,
+            bits_per_pixel,
+            scanline_pad: 32,
+            big_endian,
+        }
+    }
+
+    const OPAQUE: [u8; 8] = [0x11, 0x22, 0x33, 0xFF, 0xAA, 0xBB, 0xCC, 0xFF];
+
+    #[test]
+    fn lsb_first_32_bit_pixels_are_bgrx() {
+        let format = true_color(24, 32, false);
+        let (data, bytes_per_line) = rgba_to_zpixmap(&OPAQUE, 2, 0, &format);
+        assert_eq!(bytes_per_line, 8);
+        assert_eq!(data, [0x33, 0x22, 0x11, 0, 0xCC, 0xBB, 0xAA, 0]);
+    }
+
+    #[test]
+    fn msb_first_32_bit_pixels_are_xrgb() {
+        let format = true_color(24, 32, true);
+        let (data, bytes_per_line) = rgba_to_zpixmap(&OPAQUE, 2, 0, &format);
+        assert_eq!(bytes_per_line, 8);
+        assert_eq!(data, [0, 0x11, 0x22, 0x33, 0, 0xAA, 0xBB, 0xCC]);
+    }
+
+    #[test]
+    fn packed_24_bit_rows_are_padded_to_the_scanline_unit() {
+        let lsb = rgba_to_zpixmap(&OPAQUE, 2, 0, &true_color(24, 24, false));
+        assert_eq!(lsb, (vec![0x33, 0x22, 0x11, 0xCC, 0xBB, 0xAA, 0, 0], 8));
+        let msb = rgba_to_zpixmap(&OPAQUE, 2, 0, &true_color(24, 24, true));
+        assert_eq!(msb, (vec![0x11, 0x22, 0x33, 0xAA, 0xBB, 0xCC, 0, 0], 8));
+    }
+
+    #[test]
+    fn rows_are_laid_out_one_after_another() {
+        let format = true_color(24, 24, false);
+        let (data, bytes_per_line) = rgba_to_zpixmap(&OPAQUE, 1, 0, &format);
+        assert_eq!(bytes_per_line, 4);
+        assert_eq!(data, [0x33, 0x22, 0x11, 0, 0xCC, 0xBB, 0xAA, 0]);
+    }
+
+    #[test]
+    fn sixteen_bit_pixels_keep_the_high_bits() {
+        let white_red = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0xFF];
+        let (data, _) = rgba_to_zpixmap(&white_red, 2, 0, &true_color(16, 16, false));
+        assert_eq!(data, [0xFF, 0xFF, 0x00, 0xF8]);
+        let (data, _) = rgba_to_zpixmap(&white_red, 2, 0, &true_color(16, 16, true));
+        assert_eq!(data, [0xFF, 0xFF, 0xF8, 0x00]);
+    }
+
+    #[test]
+    fn translucent_pixels_are_blended_over_the_background() {
+        let format = true_color(24, 32, false);
+        let pixels = [0xFF, 0, 0, 0x80, 0xFF, 0xFF, 0xFF, 0];
+        let (data, _) = rgba_to_zpixmap(&pixels, 2, 0x0000FF, &format);
+        // Half red over blue, then a fully transparent pixel showing blue
+        assert_eq!(data, [0x7F, 0, 0x80, 0, 0xFF, 0, 0, 0]);
+    }
+
+    #[test]
+    fn empty_width_gives_no_rows() {
+        let format = true_color(24, 32, false);
+        assert_eq!(rgba_to_zpixmap(&[], 0, 0, &format), (Vec::new(), 0));
+    }
+
+    #[test]
+    fn strips_hold_at_least_one_row() {
+        assert_eq!(strip_rows(100, 4096), 1);
+        assert_eq!(strip_rows(0, 4096), 1);
+        assert_eq!(strip_rows(100, 0), 76);
+    }
+
+    #[test]
+    fn strips_fill_the_request_exactly() {
+        // 24 bytes of PutImage header plus 4 rows of 256 bytes
+        assert_eq!(strip_rows(24 + 4 * 256, 256), 4);
+    }
+
+    #[test]
+    fn strips_leave_out_a_partial_row() {
+        assert_eq!(strip_rows(24 + 4 * 256 + 255, 256), 4);
+        let rows = strip_rows(24 + 3 * 100, 100);
+        let image = vec![0u8; 10 * 100];
+        let strips: Vec<usize> = image.chunks(rows * 100).map(|s| s.len() / 100).collect();
+        assert_eq!(strips, [3, 3, 3, 1]);
+    }
+
+    const XK_KP_END: u32 = 0xFF9C;
+    const XK_KP_1: u32 = 0xFFB1;
+
+    #[test]
+    fn keypad_keys_type_their_characters() {
+        assert_eq!(keypad_to_char(KEYSYM_KP_0), Some('0'));
+        assert_eq!(keypad_to_char(KEYSYM_KP_9), Some('9'));
+        assert_eq!(keypad_to_char(0xFFAB), Some('+'));
+        assert_eq!(keypad_to_char(0xFFAE), Some('.'));
+        assert_eq!(keysym_to_char(XK_KP_1), Some('1'));
+    }
+
+    #[test]
+    fn keypad_navigation_keys_type_nothing() {
+        assert_eq!(keysym_to_char(XK_KP_END), None);
+        // KP_Tab and KP_Enter must not put control characters in the query
+        assert_eq!(keysym_to_char(0xFF89), None);
+        assert_eq!(keysym_to_char(0xFF8D), None);
+    }
+
+    #[test]
+    fn num_lock_picks_the_keypad_digit() {
+        let syms = [XK_KP_END, XK_KP_1];
+        assert_eq!(keysym_for_level(&syms, false, false, true), Some(XK_KP_1));
+        assert_eq!(
+            keysym_for_level(&syms, false, false, false),
+            Some(XK_KP_END)
+        );
+        // Shift inverts NumLock
+        assert_eq!(keysym_for_level(&syms, true, false, true), Some(XK_KP_END));
+        assert_eq!(keysym_for_level(&syms, true, false, false), Some(XK_KP_1));
+    }
+
+    #[test]
+    fn shift_picks_the_second_column() {
+        let syms = [u32::from('1'), u32::from('!')];
+        assert_eq!(
+            keysym_for_level(&syms, false, false, false),
+            Some(u32::from('1'))
+        );
+        assert_eq!(
+            keysym_for_level(&syms, true, false, false),
+            Some(u32::from('!'))
+        );
+    }
+
+    #[test]
+    fn shift_uppercases_a_single_keysym_letter() {
+        assert_eq!(
+            keysym_for_level(&[u32::from('a')], true, false, false),
+            Some(u32::from('A'))
+        );
+        // Outside Latin-1 the uppercase letter is a Unicode keysym
+        let syms = [KEYSYM_UNICODE_OFFSET + 0x3B1]; // α
+        assert_eq!(
+            keysym_for_level(&syms, true, false, false),
+            Some(KEYSYM_UNICODE_OFFSET + 0x391)
+        );
+    }
+
+    #[test]
+    fn level3_picks_the_fifth_and_sixth_columns() {
+        // German `q`: q Q, group 2 unused, @ at level 3
+        let syms = [u32::from('q'), u32::from('Q'), 0, 0, u32::from('@'), 0];
+        assert_eq!(
+            keysym_for_level(&syms, false, true, false),
+            Some(u32::from('@'))
+        );
+        let syms = [u32::from('e'), u32::from('E'), 0, 0, KEYSYM_EURO, 0];
+        assert_eq!(
+            keysym_for_level(&syms, false, true, false),
+            Some(KEYSYM_EURO)
+        );
+    }
+
+    #[test]
+    fn level3_without_symbols_falls_back_to_the_base_level() {
+        let syms = [u32::from('x'), u32::from('X')];
+        assert_eq!(
+            keysym_for_level(&syms, false, true, false),
+            Some(u32::from('x'))
+        );
+        assert_eq!(
+            keysym_for_level(&syms, true, true, false),
+            Some(u32::from('X'))
+        );
+    }
+
+    #[test]
+    fn hints_offer_ctrl_tab_only_with_several_launcher_modes() {
+        let single = Config {
+            modes: vec![LauncherMode::Drun],
+            ..Config::default()
+        };
+        let several = Config {
+            modes: vec![LauncherMode::Drun, LauncherMode::Run],
+            ..Config::default()
+        };
+        assert!(!key_hints(&Mode::Launcher, &single, 5).contains("Ctrl+Tab"));
+        assert!(key_hints(&Mode::Launcher, &several, 5).contains("Ctrl+Tab"));
+        assert!(!key_hints(&Mode::Emoji, &several, 5).contains("Ctrl+Tab"));
+    }
+
+    #[test]
+    fn hints_name_the_reachable_alt_shortcuts() {
+        let cfg = Config {
+            modes: vec![LauncherMode::Drun],
+            ..Config::default()
+        };
+        assert_eq!(
+            key_hints(&Mode::Launcher, &cfg, 4),
+            "Up/Down move   Enter launch   Alt+1..4 pick   Esc close"
+        );
+        assert!(key_hints(&Mode::Launcher, &cfg, 20).contains("Alt+1..9 pick"));
+        assert!(!key_hints(&Mode::Launcher, &cfg, 1).contains("Alt+"));
+    }
+
+    #[test]
+    fn hints_follow_the_layout() {
+        let horizontal = Config {
+            layout: Layout::Horizontal,
+            ..Config::default()
+        };
+        let grid = Config {
+            columns: 4,
+            ..Config::default()
+        };
+        assert!(key_hints(&Mode::Emoji, &horizontal, 0).starts_with("Left/Right move"));
+        assert!(key_hints(&Mode::Emoji, &grid, 0).starts_with("Arrows move"));
+    }
+
+    #[test]
+    fn text_beyond_latin1_falls_back_for_core_fonts() {
+        let bytes: Vec<u8> = "Café — “New” ✓".chars().map(latin1_byte).collect();
+        assert_eq!(bytes, b"Caf\xe9 - \"New\" ?");
+    }
 }
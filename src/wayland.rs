@@ -0,0 +1,1227 @@
+//! A native Wayland front end on the wlr layer-shell protocol, built with the
+//! `wayland` feature. It is a first cut next to the X11 UI: it lists and
+//! searches the items, cycles the launcher modes with Ctrl+Tab, browses
+//! directories and offers the web search row, but draws no icons and has
+//! no daemon mode or power menu yet.
+
+use crate::commands::{
+    ItemType, LaunchItem, copy_to_clipboard, current_desktops, is_path_command, launch_item,
+};
+use crate::config::{Config, LauncherMode};
+use crate::error::LauncherError;
+use crate::fuzzy;
+use crate::history::History;
+use crate::modes::{Mode, files};
+use crate::monitor::Monitor;
+use crate::{calc, ui, web_search};
+use fontdue::{Font, FontSettings, Metrics};
+use smithay_client_toolkit::reexports::client::globals::registry_queue_init;
+use smithay_client_toolkit::reexports::client::protocol::{
+    wl_keyboard, wl_output, wl_seat, wl_shm, wl_surface,
+};
+use smithay_client_toolkit::reexports::client::{Connection, QueueHandle};
+use smithay_client_toolkit::{
+    compositor::{CompositorHandler, CompositorState},
+    delegate_compositor, delegate_keyboard, delegate_layer, delegate_output, delegate_registry,
+    delegate_seat, delegate_shm,
+    output::{OutputHandler, OutputState},
+    registry::{ProvidesRegistryState, RegistryState},
+    registry_handlers,
+    seat::{
+        Capability, SeatHandler, SeatState,
+        keyboard::{KeyEvent, KeyboardHandler, Keysym, Modifiers, RawModifiers},
+    },
+    shell::{
+        WaylandSurface,
+        wlr_layer::{
+            Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
+            LayerSurfaceConfigure,
+        },
+    },
+    shm::{Shm, ShmHandler, slot::SlotPool},
+};
+use std::collections::HashMap;
+
+/// Output size assumed when the compositor reports none.
+const FALLBACK_OUTPUT: (u16, u16) = (1920, 1080);
+
+/// Shows the launcher as a layer-shell surface and runs the pick. Returns
+/// whether something was picked, which dmenu mode reports as the exit status.
+pub fn run_ui(mut cfg: Config, mode: Mode) -> Result<bool, LauncherError> {
+    if matches!(mode, Mode::Power(_)) {
+        return Err(LauncherError::Other(
+            "the power menu is not available on Wayland yet; run rufi under XWayland".to_string(),
+        ));
+    }
+    if matches!(mode, Mode::Launcher) {
+        // Only the modes listed here are collected, so nothing is asked of X11
+        let (kept, dropped): (Vec<LauncherMode>, Vec<LauncherMode>) = cfg
+            .launcher_modes()
+            .iter()
+            .cloned()
+            .partition(available_on_wayland);
+        if !dropped.is_empty() {
+            let names: Vec<&str> = dropped.iter().map(LauncherMode::name).collect();
+            eprintln!("Not available on Wayland yet: {}", names.join(", "));
+        }
+        if kept.is_empty() {
+            return Err(LauncherError::Other(
+                "none of the configured modes is available on Wayland yet".to_string(),
+            ));
+        }
+        cfg.modes = kept;
+    }
+    let text = TextRenderer::load(&cfg)?;
+
+    let desktops = current_desktops(cfg.current_desktop.as_deref());
+    let (items, failures) = mode.collect(&desktops, &cfg);
+    if !failures.is_empty() {
+        eprintln!("Failed to list items: {}", failures.join("; "));
+    }
+    let modes = match mode {
+        Mode::Launcher => cfg.launcher_modes().to_vec(),
+        _ => Vec::new(),
+    };
+    let history = History::load();
+    let recent = if cfg.recent_on_empty && matches!(mode, Mode::Launcher) {
+        history.recent().to_vec()
+    } else {
+        Vec::new()
+    };
+    let mut menu = Menu::new(items, modes, recent);
+    menu.refilter(&cfg);
+
+    let conn = Connection::connect_to_env()?;
+    let (globals, mut queue) = registry_queue_init(&conn)
+        .map_err(|e| LauncherError::Other(format!("Wayland registry error: {}", e)))?;
+    let qh = queue.handle();
+    let missing =
+        |global: &str| LauncherError::Other(format!("the compositor does not offer {}", global));
+    let compositor = CompositorState::bind(&globals, &qh).map_err(|_| missing("wl_compositor"))?;
+    let layer_shell =
+        LayerShell::bind(&globals, &qh).map_err(|_| missing("zwlr_layer_shell_v1"))?;
+    let shm = Shm::bind(&globals, &qh).map_err(|_| missing("wl_shm"))?;
+    let pool = SlotPool::new(1, &shm)
+        .map_err(|e| LauncherError::Other(format!("Failed to create a shm pool: {}", e)))?;
+
+    let mut app = App {
+        registry_state: RegistryState::new(&globals),
+        seat_state: SeatState::new(&globals, &qh),
+        output_state: OutputState::new(&globals, &qh),
+        shm,
+        pool,
+        layer: None,
+        keyboard: None,
+        width: 0,
+        height: 0,
+        modifiers: Modifiers::default(),
+        cfg,
+        mode,
+        menu,
+        text,
+        history,
+        query_history: History::load_queries(),
+        accepted: false,
+        exit: false,
+    };
+    // The outputs arrive with the first roundtrip
+    queue.roundtrip(&mut app)?;
+
+    let (output, output_size) = pick_output(&app.output_state, app.cfg.monitor.as_deref());
+    let monitor = Monitor {
+        name: String::new(),
+        x: 0,
+        y: 0,
+        width: output_size.0,
+        height: output_size.1,
+        primary: true,
+    };
+    let (width, height) = app.cfg.window_size(monitor.width, monitor.height);
+    let (x, y) = ui::window_position(&app.cfg, &monitor, width, height);
+    // X11 draws the border outside the window; here it is part of the surface
+    app.width = (width + app.cfg.border_width * 2) as u32;
+    app.height = (height + app.cfg.border_width * 2) as u32;
+
+    let surface = compositor.create_surface(&qh);
+    let layer = layer_shell.create_layer_surface(
+        &qh,
+        surface,
+        Layer::Overlay,
+        Some("rufi"),
+        output.as_ref(),
+    );
+    layer.set_anchor(Anchor::TOP | Anchor::LEFT);
+    layer.set_margin(y as i32, 0, 0, x as i32);
+    layer.set_size(app.width, app.height);
+    layer.set_keyboard_interactivity(KeyboardInteractivity::Exclusive);
+    // The first commit has no buffer; drawing starts at the configure
+    layer.commit();
+    app.layer = Some(layer);
+
+    while !app.exit {
+        queue.blocking_dispatch(&mut app)?;
+    }
+    Ok(app.accepted)
+}
+
+/// Whether the Wayland front end can list and run the items of `mode`.
+/// Windows and processes still go through X11, and the power menu and
+/// scripts need the X11 UI's confirmation and reply handling.
+fn available_on_wayland(mode: &LauncherMode) -> bool {
+    !matches!(
+        mode,
+        LauncherMode::Window | LauncherMode::Kill | LauncherMode::Power | LauncherMode::Script(_)
+    )
+}
+
+/// The output named by the `monitor` setting, or else the first one, with
+/// its logical size.
+fn pick_output(
+    outputs: &OutputState,
+    name: Option<&str>,
+) -> (Option<wl_output::WlOutput>, (u16, u16)) {
+    let with_info: Vec<_> = outputs
+        .outputs()
+        .filter_map(|output| outputs.info(&output).map(|info| (output, info)))
+        .collect();
+    let picked = name
+        .and_then(|name| {
+            with_info
+                .iter()
+                .find(|(_, info)| info.name.as_deref() == Some(name))
+        })
+        .or(with_info.first());
+    match picked {
+        Some((output, info)) => {
+            let size = info
+                .logical_size
+                .map(|(w, h)| {
+                    (
+                        w.clamp(1, u16::MAX as i32) as u16,
+                        h.clamp(1, u16::MAX as i32) as u16,
+                    )
+                })
+                .unwrap_or(FALLBACK_OUTPUT);
+            (Some(output.clone()), size)
+        }
+        None => (None, FALLBACK_OUTPUT),
+    }
+}
+
+/// A key the menu reacts to, already translated from the keymap.
+#[derive(Debug, Clone, PartialEq)]
+enum Key {
+    Escape,
+    Enter,
+    /// Ctrl+Tab, to the next launcher mode.
+    NextMode,
+    Up,
+    Down,
+    Backspace,
+    DeleteWord,
+    Text(String),
+}
+
+impl Key {
+    fn from_event(event: &KeyEvent, modifiers: &Modifiers) -> Option<Key> {
+        let key = match event.keysym {
+            Keysym::Escape => Key::Escape,
+            Keysym::Return | Keysym::KP_Enter => Key::Enter,
+            Keysym::Tab | Keysym::ISO_Left_Tab if modifiers.ctrl => Key::NextMode,
+            Keysym::Up | Keysym::KP_Up | Keysym::ISO_Left_Tab => Key::Up,
+            Keysym::Tab if modifiers.shift => Key::Up,
+            Keysym::Down | Keysym::KP_Down | Keysym::Tab => Key::Down,
+            Keysym::BackSpace if modifiers.ctrl => Key::DeleteWord,
+            Keysym::BackSpace => Key::Backspace,
+            _ if modifiers.ctrl || modifiers.alt => return None,
+            _ => {
+                let text = event.utf8.as_ref()?;
+                if text.is_empty() || text.chars().any(char::is_control) {
+                    return None;
+                }
+                Key::Text(text.clone())
+            }
+        };
+        Some(key)
+    }
+}
+
+/// What a key asks of the surface holding the menu.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Redraw,
+    Close,
+    /// Enter on the row at this index of `filtered`, which may be past the
+    /// end when nothing matched.
+    Accept(usize),
+}
+
+/// The query, results and selection, kept apart from the surface so the
+/// key handling doesn't need a compositor.
+struct Menu {
+    items: Vec<LaunchItem>,
+    /// The launcher modes Ctrl+Tab cycles through; empty for the other modes.
+    modes: Vec<LauncherMode>,
+    mode_index: usize,
+    /// Where each launcher mode was left, to come back to it.
+    snapshots: HashMap<LauncherMode, ui::ModeSnapshot>,
+    recent: Vec<String>,
+    query: String,
+    filtered: Vec<(LaunchItem, i32)>,
+    sel: usize,
+    start: usize,
+}
+
+impl Menu {
+    fn new(items: Vec<LaunchItem>, modes: Vec<LauncherMode>, recent: Vec<String>) -> Self {
+        Menu {
+            items,
+            modes,
+            mode_index: 0,
+            snapshots: HashMap::new(),
+            recent,
+            query: String::new(),
+            filtered: Vec::new(),
+            sel: 0,
+            start: 0,
+        }
+    }
+
+    fn launcher_mode(&self) -> Option<&LauncherMode> {
+        self.modes.get(self.mode_index)
+    }
+
+    fn refilter(&mut self, cfg: &Config) {
+        let launcher_mode = self.launcher_mode().cloned();
+        let too_short = ui::query_too_short(cfg, &self.query);
+        let browsed = (launcher_mode == Some(LauncherMode::Files))
+            .then(|| files::browse(&self.query, cfg))
+            .flatten();
+        self.filtered = if too_short {
+            Vec::new()
+        } else if let Some((entries, leaf)) = browsed {
+            // A path in the files mode lists that directory
+            fuzzy::fuzzy_search(leaf, &entries, cfg.max_results, &[])
+        } else if let Some(launcher_mode) = &launcher_mode {
+            let subset: Vec<LaunchItem> = self
+                .items
+                .iter()
+                .filter(|item| ui::mode_matches(launcher_mode, item))
+                .cloned()
+                .collect();
+            fuzzy::fuzzy_search(&self.query, &subset, cfg.max_results, &self.recent)
+        } else {
+            fuzzy::fuzzy_search(&self.query, &self.items, cfg.max_results, &self.recent)
+        };
+        if launcher_mode.is_some() && !too_short {
+            if let Some(result) = calc::answer(&self.query) {
+                self.filtered
+                    .insert(0, (ui::answer_item(&self.query, result), 0));
+            }
+            // As on X11, the web is offered when no application or command
+            // matched, and a bang searches right away
+            let searchable = matches!(
+                launcher_mode,
+                Some(LauncherMode::Combi | LauncherMode::Drun | LauncherMode::Run)
+            );
+            if let Some(search) = web_search::bang_item(&cfg.web_search, &self.query) {
+                self.filtered.insert(0, (search, 0));
+            } else if self.filtered.is_empty() && searchable {
+                self.filtered.extend(
+                    web_search::fallback_item(&cfg.web_search, &self.query).map(|item| (item, 0)),
+                );
+            }
+        }
+        self.sel = 0;
+        self.start = 0;
+    }
+
+    /// Moves to the next launcher mode, back where it was left unless
+    /// `reset_on_mode_switch` is set.
+    fn next_mode(&mut self, cfg: &Config) {
+        if self.modes.len() < 2 {
+            return;
+        }
+        let leaving = self.modes[self.mode_index].clone();
+        self.mode_index = (self.mode_index + 1) % self.modes.len();
+        let restoring = if cfg.reset_on_mode_switch {
+            None
+        } else {
+            let left = ui::ModeSnapshot {
+                query: self.query.clone(),
+                selected: self
+                    .filtered
+                    .get(self.sel)
+                    .map(|(item, _)| item.name.clone()),
+                start_index: self.start,
+            };
+            // A mode not visited yet starts with the query as typed
+            let entering = &self.modes[self.mode_index];
+            ui::switch_mode_snapshot(&mut self.snapshots, leaving, left, entering)
+        };
+        if let Some(snapshot) = &restoring {
+            self.query = snapshot.query.clone();
+        }
+        self.refilter(cfg);
+        if let Some(snapshot) = restoring {
+            (self.sel, self.start) = ui::restored_position(&snapshot, &self.filtered);
+        }
+    }
+
+    fn key(&mut self, key: Key, cfg: &Config) -> Outcome {
+        match key {
+            Key::Escape => return Outcome::Close,
+            Key::Enter => {
+                let descended = self
+                    .filtered
+                    .get(self.sel)
+                    .filter(|(item, _)| files::is_dir(item))
+                    .and_then(|(item, _)| files::descend_query(item));
+                match descended {
+                    // Directories open in place instead of in a file manager
+                    Some(path) => {
+                        self.query = path;
+                        self.refilter(cfg);
+                    }
+                    None => return Outcome::Accept(self.sel),
+                }
+            }
+            Key::NextMode => self.next_mode(cfg),
+            Key::Up => self.sel = self.sel.saturating_sub(1),
+            Key::Down => {
+                if self.sel + 1 < self.filtered.len() {
+                    self.sel += 1;
+                }
+            }
+            Key::Backspace => {
+                self.query.pop();
+                self.refilter(cfg);
+            }
+            Key::DeleteWord => {
+                ui::delete_word_backward(&mut self.query);
+                self.refilter(cfg);
+            }
+            Key::Text(text) => {
+                self.query.push_str(&text);
+                self.refilter(cfg);
+            }
+        }
+        Outcome::Redraw
+    }
+
+    /// Scrolls so the selection is within the `rows` shown.
+    fn scroll_into_view(&mut self, rows: usize) {
+        if self.sel < self.start {
+            self.start = self.sel;
+        } else if self.sel >= self.start + rows {
+            self.start = self.sel + 1 - rows;
+        }
+    }
+}
+
+/// An ARGB8888 buffer the way wl_shm shares it, one little-endian word per
+/// pixel.
+struct Canvas<'a> {
+    data: &'a mut [u8],
+    width: i32,
+    height: i32,
+}
+
+impl Canvas<'_> {
+    fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: u32) {
+        let pixel = (0xFF00_0000 | color).to_le_bytes();
+        for row in y.max(0)..(y + height).min(self.height) {
+            for col in x.max(0)..(x + width).min(self.width) {
+                let at = ((row * self.width + col) * 4) as usize;
+                self.data[at..at + 4].copy_from_slice(&pixel);
+            }
+        }
+    }
+
+    /// Mixes `color` into the pixel by `coverage` out of 255, as glyph
+    /// bitmaps give it.
+    fn blend(&mut self, x: i32, y: i32, color: u32, coverage: u8) {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height || coverage == 0 {
+            return;
+        }
+        let at = ((y * self.width + x) * 4) as usize;
+        let below = u32::from_le_bytes(self.data[at..at + 4].try_into().unwrap());
+        let alpha = coverage as u32;
+        let mix = |shift: u32| {
+            let src = (color >> shift) & 0xFF;
+            let dst = (below >> shift) & 0xFF;
+            ((src * alpha + dst * (255 - alpha)) / 255) << shift
+        };
+        let mixed = 0xFF00_0000 | mix(16) | mix(8) | mix(0);
+        self.data[at..at + 4].copy_from_slice(&mixed.to_le_bytes());
+    }
+}
+
+/// The configured font rasterized with fontdue, each glyph kept after its
+/// first use.
+struct TextRenderer {
+    font: Font,
+    px: f32,
+    ascent: i32,
+    descent: i32,
+    glyphs: HashMap<char, (Metrics, Vec<u8>)>,
+}
+
+impl TextRenderer {
+    /// Finds `font` among the system fonts, falling back to any monospace
+    /// or sans-serif face.
+    fn load(cfg: &Config) -> Result<Self, LauncherError> {
+        use usvg::fontdb::{Database, Family, Query};
+
+        let mut db = Database::new();
+        db.load_system_fonts();
+        let families = [
+            Family::Name(&cfg.font),
+            Family::Monospace,
+            Family::SansSerif,
+        ];
+        let query = Query {
+            families: &families,
+            ..Query::default()
+        };
+        let id = db
+            .query(&query)
+            .ok_or_else(|| LauncherError::Other(format!("No font found for {}", cfg.font)))?;
+        let px = cfg.font_size.max(1) as f32;
+        let font = db
+            .with_face_data(id, |data, index| {
+                let settings = FontSettings {
+                    collection_index: index,
+                    scale: px,
+                    ..FontSettings::default()
+                };
+                Font::from_bytes(data, settings)
+            })
+            .ok_or_else(|| LauncherError::Other(format!("Failed to read {}", cfg.font)))?
+            .map_err(|e| LauncherError::Other(format!("Failed to load {}: {}", cfg.font, e)))?;
+        let (ascent, descent) = match font.horizontal_line_metrics(px) {
+            Some(line) => (line.ascent.round() as i32, line.descent.round() as i32),
+            None => (px.round() as i32, 0),
+        };
+        Ok(TextRenderer {
+            font,
+            px,
+            ascent,
+            descent,
+            glyphs: HashMap::new(),
+        })
+    }
+
+    /// The baseline that centers a line of text in a row.
+    fn baseline(&self, top: i32, height: i32) -> i32 {
+        // fontdue's descent is negative
+        top + (height + self.ascent + self.descent) / 2
+    }
+
+    /// How far `text` advances the pen.
+    fn width(&mut self, text: &str) -> i32 {
+        let (font, px) = (&self.font, self.px);
+        text.chars()
+            .map(|ch| {
+                let (metrics, _) = self
+                    .glyphs
+                    .entry(ch)
+                    .or_insert_with(|| font.rasterize(ch, px));
+                metrics.advance_width.round() as i32
+            })
+            .sum()
+    }
+
+    /// Draws `text` from `x` and returns where it ends, stopping at
+    /// `max_x` rather than drawing past it.
+    fn draw(
+        &mut self,
+        canvas: &mut Canvas,
+        x: i32,
+        baseline: i32,
+        text: &str,
+        color: u32,
+        max_x: i32,
+    ) -> i32 {
+        let mut pen = x;
+        for ch in text.chars() {
+            let (font, px) = (&self.font, self.px);
+            let (metrics, bitmap) = self
+                .glyphs
+                .entry(ch)
+                .or_insert_with(|| font.rasterize(ch, px));
+            let advance = metrics.advance_width.round() as i32;
+            if pen + advance > max_x {
+                break;
+            }
+            let top = baseline - metrics.height as i32 - metrics.ymin;
+            for row in 0..metrics.height {
+                for col in 0..metrics.width {
+                    let coverage = bitmap[row * metrics.width + col];
+                    let px_x = pen + metrics.xmin + col as i32;
+                    canvas.blend(px_x, top + row as i32, color, coverage);
+                }
+            }
+            pen += advance;
+        }
+        pen
+    }
+}
+
+/// Paints the query bar and the visible rows of `menu`.
+fn paint(canvas: &mut Canvas, cfg: &Config, menu: &mut Menu, text: &mut TextRenderer) {
+    let theme = &cfg.theme;
+    let (width, height) = (canvas.width, canvas.height);
+    let border = cfg.border_width as i32;
+    canvas.fill_rect(0, 0, width, height, theme.border_color);
+    canvas.fill_rect(
+        border,
+        border,
+        width - border * 2,
+        height - border * 2,
+        theme.bg_color,
+    );
+
+    let padding = cfg.padding as i32;
+    let inner_x = border + padding;
+    let inner_width = width - inner_x * 2;
+    let text_x = inner_x + cfg.row_text_padding() as i32;
+    let max_x = inner_x + inner_width - cfg.row_text_padding() as i32;
+    let item_height = cfg.item_height as i32;
+
+    let query_y = border + padding;
+    canvas.fill_rect(inner_x, query_y, inner_width, item_height, theme.query_bg);
+    let baseline = text.baseline(query_y, item_height);
+    // The active launcher mode is named at the end of the bar
+    let mode_label = menu.launcher_mode().and_then(ui::mode_label);
+    let query_max_x = match mode_label {
+        Some(label) => {
+            let label_x = max_x - text.width(label);
+            let color = ui::dim_color(theme.fg_color, 60);
+            text.draw(canvas, label_x, baseline, label, color, max_x);
+            label_x - cfg.row_text_padding() as i32
+        }
+        None => max_x,
+    };
+    let caret_x = if menu.query.is_empty() {
+        let prompt = ui::prompt_line(&cfg.prompt, "");
+        let end = text.draw(
+            canvas,
+            text_x,
+            baseline,
+            &prompt,
+            theme.accent_color,
+            query_max_x,
+        );
+        let placeholder = ui::dim_color(theme.fg_color, 50);
+        text.draw(
+            canvas,
+            end,
+            baseline,
+            &cfg.placeholder,
+            placeholder,
+            query_max_x,
+        );
+        end
+    } else {
+        let line = ui::prompt_line(&cfg.prompt, &menu.query);
+        text.draw(canvas, text_x, baseline, &line, theme.fg_color, query_max_x)
+    };
+    let caret_height = text.ascent - text.descent;
+    canvas.fill_rect(
+        caret_x + 1,
+        baseline - text.ascent,
+        2,
+        caret_height,
+        theme.accent_color,
+    );
+
+    let list_y = query_y + item_height + padding;
+    let row_height = (cfg.item_height + cfg.row_spacing).max(1) as i32;
+    let rows = ((height - border - padding - list_y) / row_height).max(1) as usize;
+    menu.scroll_into_view(rows);
+
+    if menu.filtered.is_empty() {
+        let message = ui::empty_message(cfg, &menu.query);
+        let baseline = text.baseline(list_y, item_height);
+        let color = ui::dim_color(theme.fg_color, 60);
+        text.draw(canvas, text_x, baseline, &message, color, max_x);
+        return;
+    }
+    let visible = menu.filtered.iter().enumerate().skip(menu.start).take(rows);
+    for (index, (item, _)) in visible {
+        let y = list_y + (index - menu.start) as i32 * row_height;
+        let selected = index == menu.sel;
+        if selected {
+            canvas.fill_rect(inner_x, y, inner_width, item_height, theme.selected_bg);
+        }
+        let color = if selected {
+            theme.selected_fg
+        } else {
+            theme.fg_color
+        };
+        let baseline = text.baseline(y, item_height);
+        let end = text.draw(canvas, text_x, baseline, &item.display_name, color, max_x);
+        if let Some(description) = item
+            .description
+            .as_deref()
+            .filter(|_| cfg.show_descriptions)
+        {
+            let gap = cfg.row_text_padding() as i32;
+            let dimmed = ui::dim_color(color, 60);
+            text.draw(canvas, end + gap, baseline, description, dimmed, max_x);
+        }
+    }
+}
+
+struct App {
+    registry_state: RegistryState,
+    seat_state: SeatState,
+    output_state: OutputState,
+    shm: Shm,
+    pool: SlotPool,
+    layer: Option<LayerSurface>,
+    keyboard: Option<wl_keyboard::WlKeyboard>,
+    width: u32,
+    height: u32,
+    modifiers: Modifiers,
+    cfg: Config,
+    mode: Mode,
+    menu: Menu,
+    text: TextRenderer,
+    history: History,
+    query_history: History,
+    accepted: bool,
+    exit: bool,
+}
+
+impl App {
+    fn draw(&mut self) {
+        let Some(layer) = &self.layer else {
+            return;
+        };
+        let (width, height) = (self.width as i32, self.height as i32);
+        let (buffer, data) =
+            match self
+                .pool
+                .create_buffer(width, height, width * 4, wl_shm::Format::Argb8888)
+            {
+                Ok(created) => created,
+                Err(e) => {
+                    eprintln!("Failed to create a buffer: {}", e);
+                    self.exit = true;
+                    return;
+                }
+            };
+        let mut canvas = Canvas {
+            data,
+            width,
+            height,
+        };
+        paint(&mut canvas, &self.cfg, &mut self.menu, &mut self.text);
+        layer.wl_surface().damage_buffer(0, 0, width, height);
+        if let Err(e) = buffer.attach_to(layer.wl_surface()) {
+            eprintln!("Failed to attach the buffer: {}", e);
+        }
+        layer.commit();
+    }
+
+    fn handle_key(&mut self, event: KeyEvent) {
+        let Some(key) = Key::from_event(&event, &self.modifiers) else {
+            return;
+        };
+        match self.menu.key(key, &self.cfg) {
+            Outcome::Redraw => self.draw(),
+            Outcome::Close => self.exit = true,
+            Outcome::Accept(index) => self.accept(index),
+        }
+    }
+
+    fn accept(&mut self, index: usize) {
+        let cfg = &self.cfg;
+        if let Mode::Dmenu(dmenu) = &self.mode {
+            // Like dmenu, text matching no line is printed as typed
+            let picked = self.menu.filtered.get(index).map(|(item, _)| item);
+            println!("{}", dmenu.output(picked, &self.menu.query));
+            self.accepted = true;
+            self.exit = true;
+            return;
+        }
+        let Some((item, _)) = self.menu.filtered.get(index) else {
+            return;
+        };
+        let launched = match item.item_type {
+            ItemType::Command if item.command.is_empty() => {
+                // Entries without an exec from `--run` are printed
+                println!("{}", item.display_name);
+                Ok(())
+            }
+            ItemType::Character | ItemType::Answer => {
+                println!("{}", item.command);
+                copy_to_clipboard(&item.command, &cfg.clipboard_command)
+            }
+            ItemType::Command if is_path_command(item) => {
+                // Shift+Enter opens a terminal for console programs
+                let in_terminal = self.modifiers.shift || cfg.commands_in_terminal;
+                println!("Launching: {} ({})", item.display_name, item.command);
+                launch_item(item, cfg, in_terminal)
+            }
+            _ => {
+                println!("Launching: {} ({})", item.display_name, item.command);
+                launch_item(item, cfg, false)
+            }
+        };
+        match launched {
+            Ok(()) if matches!(self.mode, Mode::Launcher) => {
+                let remembered = !matches!(item.item_type, ItemType::Answer | ItemType::Web);
+                if remembered {
+                    if let Err(e) = self.history.record(&item.name) {
+                        eprintln!("Failed to save history: {}", e);
+                    }
+                }
+                let searched = self.menu.query.trim();
+                if !searched.is_empty() {
+                    if let Err(e) = self.query_history.record(searched) {
+                        eprintln!("Failed to save query history: {}", e);
+                    }
+                }
+            }
+            Ok(()) => {}
+            Err(e) => eprintln!("Failed to launch {}: {}", item.display_name, e),
+        }
+        self.exit = true;
+    }
+}
+
+impl CompositorHandler for App {
+    fn scale_factor_changed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _new_factor: i32,
+    ) {
+    }
+
+    fn transform_changed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _new_transform: wl_output::Transform,
+    ) {
+    }
+
+    fn frame(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _time: u32,
+    ) {
+    }
+
+    fn surface_enter(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _output: &wl_output::WlOutput,
+    ) {
+    }
+
+    fn surface_leave(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _output: &wl_output::WlOutput,
+    ) {
+    }
+}
+
+impl OutputHandler for App {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.output_state
+    }
+
+    fn new_output(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+
+    fn update_output(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+
+    fn output_destroyed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+}
+
+impl LayerShellHandler for App {
+    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _layer: &LayerSurface) {
+        self.exit = true;
+    }
+
+    fn configure(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _layer: &LayerSurface,
+        configure: LayerSurfaceConfigure,
+        _serial: u32,
+    ) {
+        // Zero leaves the size to us, which set_size already chose
+        if configure.new_size.0 > 0 {
+            self.width = configure.new_size.0;
+        }
+        if configure.new_size.1 > 0 {
+            self.height = configure.new_size.1;
+        }
+        self.draw();
+    }
+}
+
+impl SeatHandler for App {
+    fn seat_state(&mut self) -> &mut SeatState {
+        &mut self.seat_state
+    }
+
+    fn new_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
+
+    fn new_capability(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        seat: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        if capability == Capability::Keyboard && self.keyboard.is_none() {
+            match self.seat_state.get_keyboard(qh, &seat, None) {
+                Ok(keyboard) => self.keyboard = Some(keyboard),
+                Err(e) => eprintln!("Failed to get the keyboard: {}", e),
+            }
+        }
+    }
+
+    fn remove_capability(
+        &mut self,
+        _conn: &Connection,
+        _: &QueueHandle<Self>,
+        _: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        if capability == Capability::Keyboard {
+            if let Some(keyboard) = self.keyboard.take() {
+                keyboard.release();
+            }
+        }
+    }
+
+    fn remove_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
+}
+
+impl KeyboardHandler for App {
+    fn enter(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_keyboard::WlKeyboard,
+        _: &wl_surface::WlSurface,
+        _: u32,
+        _: &[u32],
+        _: &[Keysym],
+    ) {
+    }
+
+    fn leave(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_keyboard::WlKeyboard,
+        _: &wl_surface::WlSurface,
+        _: u32,
+    ) {
+    }
+
+    fn press_key(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _: &wl_keyboard::WlKeyboard,
+        _: u32,
+        event: KeyEvent,
+    ) {
+        self.handle_key(event);
+    }
+
+    fn repeat_key(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _: &wl_keyboard::WlKeyboard,
+        _: u32,
+        event: KeyEvent,
+    ) {
+        self.handle_key(event);
+    }
+
+    fn release_key(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_keyboard::WlKeyboard,
+        _: u32,
+        _: KeyEvent,
+    ) {
+    }
+
+    fn update_modifiers(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_keyboard::WlKeyboard,
+        _serial: u32,
+        modifiers: Modifiers,
+        _raw_modifiers: RawModifiers,
+        _layout: u32,
+    ) {
+        self.modifiers = modifiers;
+    }
+}
+
+impl ShmHandler for App {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
+
+impl ProvidesRegistryState for App {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry_state
+    }
+    registry_handlers![OutputState, SeatState];
+}
+
+delegate_compositor!(App);
+delegate_output!(App);
+delegate_shm!(App);
+delegate_seat!(App);
+delegate_keyboard!(App);
+delegate_layer!(App);
+delegate_registry!(App);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str, item_type: ItemType) -> LaunchItem {
+        LaunchItem {
+            name: name.to_string(),
+            display_name: name.to_string(),
+            command: name.to_string(),
+            description: None,
+            generic_name: None,
+            keywords: Vec::new(),
+            icon: None,
+            working_dir: None,
+            source: None,
+            wm_class: None,
+            needs_terminal: false,
+            item_type,
+        }
+    }
+
+    fn menu(names: &[&str]) -> Menu {
+        let items = names
+            .iter()
+            .map(|name| item(name, ItemType::Text))
+            .collect();
+        let mut menu = Menu::new(items, Vec::new(), Vec::new());
+        menu.refilter(&Config::default());
+        menu
+    }
+
+    #[test]
+    fn typing_filters_and_resets_the_selection() {
+        let cfg = Config::default();
+        let mut menu = menu(&["firefox", "files", "terminal"]);
+        assert_eq!(menu.key(Key::Down, &cfg), Outcome::Redraw);
+        assert_eq!(menu.sel, 1);
+
+        menu.key(Key::Text("term".to_string()), &cfg);
+        assert_eq!(menu.sel, 0);
+        let names: Vec<&str> = menu
+            .filtered
+            .iter()
+            .map(|(item, _)| item.name.as_str())
+            .collect();
+        assert_eq!(names, ["terminal"]);
+
+        menu.key(Key::Backspace, &cfg);
+        assert_eq!(menu.query, "ter");
+        menu.key(Key::DeleteWord, &cfg);
+        assert_eq!(menu.query, "");
+    }
+
+    #[test]
+    fn selection_stays_within_the_results() {
+        let cfg = Config::default();
+        let mut menu = menu(&["a", "b"]);
+        menu.key(Key::Up, &cfg);
+        assert_eq!(menu.sel, 0);
+        menu.key(Key::Down, &cfg);
+        menu.key(Key::Down, &cfg);
+        assert_eq!(menu.sel, 1);
+        assert_eq!(menu.key(Key::Enter, &cfg), Outcome::Accept(1));
+        assert_eq!(menu.key(Key::Escape, &cfg), Outcome::Close);
+    }
+
+    #[test]
+    fn scrolling_follows_the_selection() {
+        let mut menu = menu(&["a", "b", "c", "d", "e"]);
+        menu.sel = 4;
+        menu.scroll_into_view(2);
+        assert_eq!(menu.start, 3);
+        menu.sel = 1;
+        menu.scroll_into_view(2);
+        assert_eq!(menu.start, 1);
+    }
+
+    #[test]
+    fn only_modes_runnable_without_x11_are_listed() {
+        assert!(available_on_wayland(&LauncherMode::Combi));
+        assert!(available_on_wayland(&LauncherMode::Files));
+        assert!(available_on_wayland(&LauncherMode::Emoji));
+        assert!(!available_on_wayland(&LauncherMode::Window));
+        assert!(!available_on_wayland(&LauncherMode::Kill));
+        assert!(!available_on_wayland(&LauncherMode::Power));
+        assert!(!available_on_wayland(&LauncherMode::Script(
+            "notes".to_string()
+        )));
+    }
+
+    #[test]
+    fn canvas_clips_and_blends() {
+        let mut data = vec![0; 4 * 4 * 4];
+        let mut canvas = Canvas {
+            data: &mut data,
+            width: 4,
+            height: 4,
+        };
+        canvas.fill_rect(-2, -2, 4, 4, 0x000000);
+        canvas.fill_rect(3, 3, 10, 10, 0xFFFFFF);
+        canvas.blend(0, 0, 0xFFFFFF, 255);
+        canvas.blend(1, 1, 0xFFFFFF, 0);
+        canvas.blend(9, 9, 0xFFFFFF, 255);
+
+        let pixel = |x: usize, y: usize| {
+            let at = (y * 4 + x) * 4;
+            u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+        };
+        assert_eq!(pixel(0, 0), 0xFFFF_FFFF);
+        assert_eq!(pixel(1, 1), 0xFF00_0000);
+        assert_eq!(pixel(2, 2), 0);
+        assert_eq!(pixel(3, 3), 0xFFFF_FFFF);
+    }
+
+    fn names(menu: &Menu) -> Vec<&str> {
+        menu.filtered
+            .iter()
+            .map(|(item, _)| item.display_name.as_str())
+            .collect()
+    }
+
+    fn type_text(menu: &mut Menu, text: &str, cfg: &Config) {
+        menu.key(Key::Text(text.to_string()), cfg);
+    }
+
+    #[test]
+    fn ctrl_tab_cycles_the_launcher_modes_and_comes_back() {
+        let cfg = Config::default();
+        let items = vec![
+            item("firefox", ItemType::Application),
+            item("ls", ItemType::Command),
+        ];
+        let mut menu = Menu::new(
+            items,
+            vec![LauncherMode::Drun, LauncherMode::Run],
+            Vec::new(),
+        );
+        menu.refilter(&cfg);
+        type_text(&mut menu, "fire", &cfg);
+        assert_eq!(names(&menu), ["firefox"]);
+
+        // A mode not visited yet starts with the query as typed
+        menu.key(Key::NextMode, &cfg);
+        assert_eq!(menu.launcher_mode(), Some(&LauncherMode::Run));
+        assert_eq!(menu.query, "fire");
+        for _ in 0..4 {
+            menu.key(Key::Backspace, &cfg);
+        }
+        assert_eq!(names(&menu), ["ls"]);
+
+        menu.key(Key::NextMode, &cfg);
+        assert_eq!(menu.launcher_mode(), Some(&LauncherMode::Drun));
+        assert_eq!(menu.query, "fire");
+        assert_eq!(names(&menu), ["firefox"]);
+    }
+
+    #[test]
+    fn ctrl_tab_does_nothing_outside_the_launcher() {
+        let cfg = Config::default();
+        let mut menu = menu(&["a", "b"]);
+        menu.key(Key::Down, &cfg);
+        assert_eq!(menu.key(Key::NextMode, &cfg), Outcome::Redraw);
+        assert_eq!(menu.launcher_mode(), None);
+        assert_eq!(menu.sel, 1);
+    }
+
+    #[test]
+    fn enter_on_a_directory_lists_it_instead_of_opening_it() {
+        let cfg = Config::default();
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "").unwrap();
+        let mut menu = Menu::new(Vec::new(), vec![LauncherMode::Files], Vec::new());
+        menu.refilter(&cfg);
+        let root = format!("{}/", dir.path().display());
+        type_text(&mut menu, &root, &cfg);
+        assert_eq!(names(&menu), ["notes.txt", "sub/"]);
+
+        menu.sel = 1;
+        assert_eq!(menu.key(Key::Enter, &cfg), Outcome::Redraw);
+        assert_eq!(menu.query, format!("{}sub/", root));
+
+        menu.key(Key::DeleteWord, &cfg);
+        type_text(&mut menu, &root, &cfg);
+        assert_eq!(menu.key(Key::Enter, &cfg), Outcome::Accept(0));
+    }
+
+    #[test]
+    fn unmatched_launcher_queries_offer_a_web_search() {
+        let cfg = Config::default();
+        let mut launcher = Menu::new(Vec::new(), vec![LauncherMode::Combi], Vec::new());
+        launcher.refilter(&cfg);
+        type_text(&mut launcher, "rust lifetimes", &cfg);
+        assert_eq!(launcher.filtered.len(), 1);
+        assert_eq!(launcher.filtered[0].0.item_type, ItemType::Web);
+
+        // dmenu and the other modes print or run only what they listed
+        let mut listed = menu(&["a"]);
+        type_text(&mut listed, "rust lifetimes", &cfg);
+        assert!(listed.filtered.is_empty());
+    }
+}
@@ -0,0 +1,108 @@
+//! The "Search the web" row for queries nothing matches, and `!engine`
+//! bangs that search a named engine right away.
+
+use crate::commands::{ItemType, LaunchItem, shell_quote};
+use crate::config::WebSearchConfig;
+
+/// The search a `!name terms` query asks for, when `name` is a configured
+/// engine.
+pub fn bang_item(web: &WebSearchConfig, query: &str) -> Option<LaunchItem> {
+    let (name, terms) = query.trim().strip_prefix('!')?.split_once(' ')?;
+    let url = web.engines.get(name)?;
+    let terms = terms.trim();
+    (!terms.is_empty()).then(|| search_item(name, url, terms))
+}
+
+/// The search on the default engine offered when nothing matched `query`.
+pub fn fallback_item(web: &WebSearchConfig, query: &str) -> Option<LaunchItem> {
+    let terms = query.trim();
+    let url = web.engines.get(&web.default)?;
+    (!terms.is_empty()).then(|| search_item(&web.default, url, terms))
+}
+
+fn search_item(engine: &str, url: &str, terms: &str) -> LaunchItem {
+    let url = url.replace("{query}", &percent_encode(terms));
+    LaunchItem {
+        name: format!("web:{}", engine),
+        display_name: format!("Search the web for '{}'", terms),
+        command: format!("xdg-open {}", shell_quote(&url)),
+        description: Some(format!("{} - {}", engine, url)),
+        generic_name: None,
+        keywords: Vec::new(),
+        icon: Some("web-browser".to_string()),
+        working_dir: None,
+        source: None,
+        wm_class: None,
+        needs_terminal: false,
+        item_type: ItemType::Web,
+    }
+}
+
+/// Escapes everything but the unreserved URL characters, encoding text as
+/// UTF-8 first, so spaces, `&` and non-ASCII survive the query string.
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_encode_escapes_reserved_characters_and_spaces() {
+        assert_eq!(percent_encode("a b"), "a%20b");
+        assert_eq!(percent_encode("&=?/+#"), "%26%3D%3F%2F%2B%23");
+        assert_eq!(percent_encode("it's"), "it%27s");
+        assert_eq!(percent_encode("rust-lang_1.0~x"), "rust-lang_1.0~x");
+    }
+
+    #[test]
+    fn percent_encode_encodes_multibyte_utf8_per_byte() {
+        assert_eq!(percent_encode("über"), "%C3%BCber");
+        assert_eq!(percent_encode("日本"), "%E6%97%A5%E6%9C%AC");
+    }
+
+    #[test]
+    fn bang_searches_the_named_engine() {
+        let item = bang_item(&WebSearchConfig::default(), "!gh  rust & cairo ").unwrap();
+        assert_eq!(item.name, "web:gh");
+        assert_eq!(item.display_name, "Search the web for 'rust & cairo'");
+        assert_eq!(
+            item.command,
+            "xdg-open 'https://github.com/search?q=rust%20%26%20cairo'"
+        );
+        assert_eq!(item.item_type, ItemType::Web);
+    }
+
+    #[test]
+    fn unknown_or_empty_bangs_are_not_searches() {
+        let web = WebSearchConfig::default();
+        assert!(bang_item(&web, "!nope rust").is_none());
+        assert!(bang_item(&web, "!gh").is_none());
+        assert!(bang_item(&web, "!gh   ").is_none());
+        assert!(bang_item(&web, "! rust").is_none());
+        assert!(bang_item(&web, "gh rust").is_none());
+    }
+
+    #[test]
+    fn fallback_uses_the_default_engine() {
+        let mut web = WebSearchConfig::default();
+        let item = fallback_item(&web, "café menu").unwrap();
+        assert_eq!(
+            item.command,
+            "xdg-open 'https://duckduckgo.com/?q=caf%C3%A9%20menu'"
+        );
+        assert!(fallback_item(&web, "  ").is_none());
+
+        web.default = String::new();
+        assert!(fallback_item(&web, "café").is_none());
+    }
+}
@@ -0,0 +1,274 @@
+//! Runs the rufi binary against a private Xvfb server and types into it
+//! with the XTEST extension.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _, MapState, Window};
+use x11rb::protocol::xtest::ConnectionExt as _;
+use x11rb::rust_connection::RustConnection;
+
+/// How long to wait for Xvfb, the rufi window and rufi exiting.
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Pause after the window shows up, so the items have loaded.
+const SETTLE: Duration = Duration::from_millis(500);
+
+const KEY_PRESS: u8 = 2;
+const KEY_RELEASE: u8 = 3;
+
+pub const XK_RETURN: u32 = 0xFF0D;
+pub const XK_ESCAPE: u32 = 0xFF1B;
+pub const XK_DOWN: u32 = 0xFF54;
+
+/// Held by the running test, so only one Xvfb picks a display number at
+/// a time.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// An Xvfb server on a free display number, stopped when dropped.
+pub struct Xvfb {
+    process: Child,
+    pub display: String,
+    pub conn: RustConnection,
+    _serial: MutexGuard<'static, ()>,
+}
+
+impl Xvfb {
+    pub fn start() -> Xvfb {
+        // A failed test poisons the lock, which says nothing about the next one
+        let serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        // Separate test binaries may run at the same time, so look for a
+        // number nobody has a lock file for
+        let number = (90..200)
+            .find(|n| !Path::new(&format!("/tmp/.X{}-lock", n)).exists())
+            .expect("no free X display number");
+        let display = format!(":{}", number);
+        let mut process = Command::new("Xvfb")
+            .arg(&display)
+            .args(["-screen", "0", "1280x800x24", "-nolisten", "tcp"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Xvfb must be installed to run the e2e tests");
+
+        let start = Instant::now();
+        let conn = loop {
+            match RustConnection::connect(Some(&display)) {
+                Ok((conn, _)) => break conn,
+                Err(_) if start.elapsed() > TIMEOUT => {
+                    let _ = process.kill();
+                    let _ = process.wait();
+                    panic!("Xvfb did not start on {}", display);
+                }
+                Err(_) => thread::sleep(Duration::from_millis(50)),
+            }
+        };
+        Xvfb {
+            process,
+            display,
+            conn,
+            _serial: serial,
+        }
+    }
+
+    fn root(&self) -> Window {
+        self.conn.setup().roots[0].root
+    }
+
+    /// Waits until a viewable window of class `rufi` exists.
+    pub fn wait_for_window(&self) {
+        let start = Instant::now();
+        while !self.rufi_window_shown() {
+            assert!(start.elapsed() < TIMEOUT, "the rufi window never showed");
+            thread::sleep(Duration::from_millis(50));
+        }
+        thread::sleep(SETTLE);
+    }
+
+    fn rufi_window_shown(&self) -> bool {
+        let Some(tree) = self
+            .conn
+            .query_tree(self.root())
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+        else {
+            return false;
+        };
+        tree.children.iter().any(|&win| {
+            let class = self
+                .conn
+                .get_property(false, win, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 64)
+                .ok()
+                .and_then(|cookie| cookie.reply().ok())
+                .map(|reply| reply.value)
+                .unwrap_or_default();
+            let viewable = self
+                .conn
+                .get_window_attributes(win)
+                .ok()
+                .and_then(|cookie| cookie.reply().ok())
+                .is_some_and(|attrs| attrs.map_state == MapState::VIEWABLE);
+            viewable && class.split(|&b| b == 0).any(|part| part == b"rufi")
+        })
+    }
+
+    /// The keycode and whether Shift is needed for `keysym`.
+    fn keycode(&self, keysym: u32) -> (u8, bool) {
+        let setup = self.conn.setup();
+        let count = setup.max_keycode - setup.min_keycode + 1;
+        let mapping = self
+            .conn
+            .get_keyboard_mapping(setup.min_keycode, count)
+            .expect("keyboard mapping request")
+            .reply()
+            .expect("keyboard mapping");
+        let per = mapping.keysyms_per_keycode as usize;
+        for (index, syms) in mapping.keysyms.chunks(per).enumerate() {
+            let code = setup.min_keycode + index as u8;
+            if syms.first() == Some(&keysym) {
+                return (code, false);
+            }
+            if syms.get(1) == Some(&keysym) {
+                return (code, true);
+            }
+        }
+        panic!("no keycode for keysym {:#x}", keysym);
+    }
+
+    fn fake(&self, kind: u8, code: u8) {
+        self.conn
+            .xtest_fake_input(kind, code, x11rb::CURRENT_TIME, self.root(), 0, 0, 0)
+            .expect("XTEST request");
+        self.conn.flush().expect("flush");
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    /// Presses and releases the key for `keysym`, holding Shift if needed.
+    pub fn key(&self, keysym: u32) {
+        const XK_SHIFT_L: u32 = 0xFFE1;
+        let (code, shifted) = self.keycode(keysym);
+        let shift = shifted.then(|| self.keycode(XK_SHIFT_L).0);
+        if let Some(shift) = shift {
+            self.fake(KEY_PRESS, shift);
+        }
+        self.fake(KEY_PRESS, code);
+        self.fake(KEY_RELEASE, code);
+        if let Some(shift) = shift {
+            self.fake(KEY_RELEASE, shift);
+        }
+    }
+
+    /// Types ASCII `text`, whose keysyms equal the characters.
+    pub fn type_text(&self, text: &str) {
+        for c in text.chars() {
+            assert!(c.is_ascii_graphic() || c == ' ', "can only type ASCII");
+            self.key(c as u32);
+        }
+    }
+}
+
+impl Drop for Xvfb {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// A home, application and PATH directory with a `firefox` desktop entry
+/// that touches a marker file instead of starting a browser.
+pub struct Fixture {
+    pub dir: TempDir,
+}
+
+impl Fixture {
+    pub fn new() -> Fixture {
+        let dir = tempfile::tempdir().expect("temp dir");
+        for sub in ["home", "apps", "bin", "runtime"] {
+            fs::create_dir(dir.path().join(sub)).expect("fixture dir");
+        }
+        let fixture = Fixture { dir };
+        fixture.add_app("firefox", "Firefox");
+        fixture.add_app("gimp", "GIMP");
+        fixture
+    }
+
+    fn path(&self, sub: &str) -> PathBuf {
+        self.dir.path().join(sub)
+    }
+
+    /// The file the app with the desktop-file ID `id` creates when launched.
+    pub fn marker(&self, id: &str) -> PathBuf {
+        self.path(&format!("launched-{}", id))
+    }
+
+    fn add_app(&self, id: &str, name: &str) {
+        let entry = format!(
+            "[Desktop Entry]\nType=Application\nName={}\nExec=touch {}\n",
+            name,
+            self.marker(id).display()
+        );
+        fs::write(self.path("apps").join(format!("{}.desktop", id)), entry)
+            .expect("fixture desktop file");
+    }
+
+    /// Starts rufi with `args` on `xvfb`, writing `stdin` to it when given.
+    pub fn spawn(&self, xvfb: &Xvfb, args: &[&str], stdin: Option<&str>) -> Child {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rufi"))
+            .arg("--no-config")
+            .args(args)
+            .env("DISPLAY", &xvfb.display)
+            .env_remove("WAYLAND_DISPLAY")
+            .env("HOME", self.path("home"))
+            .env("XDG_RUNTIME_DIR", self.path("runtime"))
+            .env("RUFI_TEST_APP_DIRS", self.path("apps"))
+            .env("RUFI_TEST_PATH", self.path("bin"))
+            .stdin(if stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("run rufi");
+        if let Some(input) = stdin {
+            let mut pipe = child.stdin.take().expect("stdin pipe");
+            pipe.write_all(input.as_bytes()).expect("write stdin");
+        }
+        child
+    }
+}
+
+/// Waits for `child` to exit, killing it after the timeout.
+pub fn finish(mut child: Child) -> (ExitStatus, String) {
+    let start = Instant::now();
+    while child.try_wait().expect("wait for rufi").is_none() {
+        if start.elapsed() > TIMEOUT {
+            let _ = child.kill();
+            panic!("rufi did not exit");
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    let output = child.wait_with_output().expect("rufi output");
+    (
+        output.status,
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+    )
+}
+
+/// Waits a little for `path` to appear, as launched apps run detached.
+pub fn appears(path: &Path) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(3) {
+        if path.exists() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
@@ -0,0 +1,150 @@
+//! End-to-end scenarios against a headless X server. They need `Xvfb` on
+//! the PATH and are skipped by default; run them with
+//! `cargo test --test e2e -- --ignored`.
+
+mod harness;
+
+use harness::{appears, finish, Fixture, Xvfb, XK_DOWN, XK_ESCAPE, XK_RETURN};
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn typing_a_name_and_enter_launches_the_app() {
+    let xvfb = Xvfb::start();
+    let fixture = Fixture::new();
+    let rufi = fixture.spawn(&xvfb, &[], None);
+    xvfb.wait_for_window();
+
+    xvfb.type_text("fir");
+    xvfb.key(XK_RETURN);
+
+    let (status, _) = finish(rufi);
+    assert!(status.success());
+    assert!(appears(&fixture.marker("firefox")));
+    assert!(!fixture.marker("gimp").exists());
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn escape_closes_without_launching() {
+    let xvfb = Xvfb::start();
+    let fixture = Fixture::new();
+    let rufi = fixture.spawn(&xvfb, &[], None);
+    xvfb.wait_for_window();
+
+    xvfb.type_text("fir");
+    xvfb.key(XK_ESCAPE);
+
+    let (status, _) = finish(rufi);
+    assert!(status.success());
+    assert!(!appears(&fixture.marker("firefox")));
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn dmenu_prints_the_picked_line() {
+    let xvfb = Xvfb::start();
+    let fixture = Fixture::new();
+    let rufi = fixture.spawn(&xvfb, &["--dmenu"], Some("alpha\nbeta\ngamma\n"));
+    xvfb.wait_for_window();
+
+    xvfb.type_text("bet");
+    xvfb.key(XK_RETURN);
+
+    let (status, stdout) = finish(rufi);
+    assert!(status.success());
+    assert_eq!(stdout, "beta\n");
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn dmenu_index_follows_the_selection() {
+    let xvfb = Xvfb::start();
+    let fixture = Fixture::new();
+    let rufi = fixture.spawn(&xvfb, &["--dmenu", "--index"], Some("alpha\nbeta\ngamma\n"));
+    xvfb.wait_for_window();
+
+    xvfb.key(XK_DOWN);
+    xvfb.key(XK_RETURN);
+
+    let (status, stdout) = finish(rufi);
+    assert!(status.success());
+    assert_eq!(stdout, "1\n");
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn enter_without_results_prints_the_typed_text() {
+    let xvfb = Xvfb::start();
+    let fixture = Fixture::new();
+    let rufi = fixture.spawn(&xvfb, &["--dmenu"], Some("alpha\nbeta\n"));
+    xvfb.wait_for_window();
+
+    xvfb.type_text("zzz");
+    xvfb.key(XK_RETURN);
+
+    let (status, stdout) = finish(rufi);
+    assert!(status.success());
+    assert_eq!(stdout, "zzz\n");
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn cancelled_dmenu_exits_with_failure() {
+    let xvfb = Xvfb::start();
+    let fixture = Fixture::new();
+    let rufi = fixture.spawn(&xvfb, &["--dmenu"], Some("alpha\nbeta\n"));
+    xvfb.wait_for_window();
+
+    xvfb.key(XK_ESCAPE);
+
+    let (status, stdout) = finish(rufi);
+    assert_eq!(status.code(), Some(1));
+    assert!(stdout.is_empty());
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn theme_flag_picks_a_built_in_theme() {
+    let xvfb = Xvfb::start();
+    let fixture = Fixture::new();
+    let rufi = fixture.spawn(&xvfb, &["--theme", "nord-dark", "--dmenu"], Some("alpha\n"));
+    xvfb.wait_for_window();
+
+    xvfb.key(XK_RETURN);
+
+    let (status, stdout) = finish(rufi);
+    assert!(status.success());
+    assert_eq!(stdout, "alpha\n");
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn unknown_theme_fails_before_opening() {
+    let xvfb = Xvfb::start();
+    let fixture = Fixture::new();
+    let rufi = fixture.spawn(&xvfb, &["--theme", "no-such-theme"], None);
+
+    let (status, stdout) = finish(rufi);
+    assert_eq!(status.code(), Some(1));
+    assert!(stdout.is_empty());
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn json_input_prints_the_pick_as_json() {
+    let xvfb = Xvfb::start();
+    let fixture = Fixture::new();
+    let items = r#"[{"name": "Alpha", "command": "a"}, {"name": "Beta", "command": "b"}]"#;
+    let rufi = fixture.spawn(&xvfb, &["--json-input", "--json"], Some(items));
+    xvfb.wait_for_window();
+
+    xvfb.type_text("bet");
+    xvfb.key(XK_RETURN);
+
+    let (status, stdout) = finish(rufi);
+    assert!(status.success());
+    assert_eq!(
+        stdout.trim(),
+        r#"{"name":"Beta","command":"b","type":"text"}"#
+    );
+}